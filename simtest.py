@@ -33,6 +33,11 @@ gis_color_attr = "color"
 # This causes us to not draw text on entity 8 and beyond
 max_entity_idx_to_name = 8
 
+# Keep every entity within a box of sorts
+[world]
+bounds = [100.0, 100.0, 1000.0, 800.0]
+boundary = "clamp"
+
 [data_constants]
 
 '''.strip()+'\n')
@@ -54,8 +59,8 @@ name = "compute_position"
 # [colmap] is a dictionary with keys containing
 # source arg names and values containing Data column names.
 # we get type data by querying parsed source directly and convert from the original to the processing target types.
-colmap.x0 = 'X0'
-colmap.y0 = 'Y0'
+colmap.X0 = 'X0'
+colmap.Y0 = 'Y0'
 
 # Constants is a list of keys -> value data.
 #   1st element is Name of the variable; this is only used for diagnostic & reporting reasons
@@ -99,16 +104,16 @@ kernel void compute_position (
         Y0[i] = Y0[i] - (blue_entity_speed_coef);
       }
 
-      if (X0[i] > 1240) {
+      if (X0[i] >= 1000) {
         entity_x_direction[i] = 1;
       }
-      if (X0[i] < 40) {
+      if (X0[i] <= 100) {
         entity_x_direction[i] = 0;
       }
-      if (Y0[i] > 940) {
+      if (Y0[i] >= 800) {
         entity_y_direction[i] = 1;
       }
-      if (Y0[i] < 40) {
+      if (Y0[i] <= 100) {
         entity_y_direction[i] = 0;
       }
 
@@ -137,20 +142,6 @@ kernel void compute_position (
       X0[i] = X0[i] + (red_entity_speed_coef * (0.9 / x_dist_to_i1) );
       Y0[i] = Y0[i] + (red_entity_speed_coef * (0.9 / y_dist_to_i1) );
 
-      // Finally clamp to a box of sorts
-      if (X0[i] < 100) {
-        X0[i] = 100;
-      }
-      if (X0[i] > 1000) {
-        X0[i] = 1000;
-      }
-      if (Y0[i] < 100) {
-        Y0[i] = 100;
-      }
-      if (Y0[i] > 800) {
-        Y0[i] = 800;
-      }
-
     }
 }
 """
@@ -0,0 +1,46 @@
+// apollon_events.h
+// Event handling & posting for kernels of an event-driven simulation (SimControl.mode = "event").
+//
+// Kernels use this with `#include "apollon_events.h"`; apollon substitutes this file before compiling.
+// Every event type listed in SimControl.event_types is available as APOLLON_EVENT_<NAME> (uppercased), ie
+// event_types = ["arrival", "departure"] defines APOLLON_EVENT_ARRIVAL 0 and APOLLON_EVENT_DEPARTURE 1.
+//
+// A kernel with `on_event = "arrival"` runs once per arrival event of the current batch; work item e handles
+// the event for entity event_entity[e]:
+//
+//   kernel void on_arrival(global float* queue_len, APOLLON_EVENT_PARAMS, APOLLON_POST_EVENT_PARAMS) {
+//     const uint i = event_entity[get_global_id(0)];
+//     queue_len[i] += 1.0f;
+//     APOLLON_POST_EVENT(5.0f, i, APOLLON_EVENT_DEPARTURE, event_payload[get_global_id(0)]);
+//   }
+//
+// Posted events occur `delay` time units after the current batch's sim_time; negative delays are treated as 0.
+// Several events of one batch may name the same entity, in which case their work items race like any other kernel.
+
+#ifndef APOLLON_EVENTS_H
+#define APOLLON_EVENTS_H
+
+#define APOLLON_EVENT_PARAMS \
+  global const uint* event_entity, \
+  global const float* event_payload
+
+#define APOLLON_POST_EVENT_PARAMS \
+  global uint* post_event_count, \
+  global float* post_event_delay, \
+  global uint* post_event_entity, \
+  global uint* post_event_type, \
+  global float* post_event_payload, \
+  const uint post_event_capacity
+
+// Events beyond post_event_capacity in one batch are dropped, and apollon warns about them
+#define APOLLON_POST_EVENT(delay, entity, type, payload) do { \
+    const uint apollon_post_slot = atomic_inc(post_event_count); \
+    if (apollon_post_slot < post_event_capacity) { \
+      post_event_delay[apollon_post_slot] = (delay); \
+      post_event_entity[apollon_post_slot] = (entity); \
+      post_event_type[apollon_post_slot] = (type); \
+      post_event_payload[apollon_post_slot] = (payload); \
+    } \
+  } while (0)
+
+#endif
@@ -0,0 +1,74 @@
+// apollon_field.h
+// Access to the cells of SimControl.fields, 2D rasters covering a rectangle of the world.
+//
+// Kernels use this with `#include "apollon_field.h"`; apollon substitutes this file before compiling.
+// A field `pheromone` is bound by declaring `global float* pheromone` (of the field's type), and the scalars
+// pheromone_width, pheromone_height, pheromone_origin_x, pheromone_origin_y, pheromone_cell_width & pheromone_cell_height
+// give its shape; APOLLON_FIELD_PARAMS declares all of them. Cells are stored row-major, cell (cx, cy) covering the world from
+// (origin_x + cx*cell_width, origin_y + cy*cell_height) to one cell further.
+//
+//   // Entities deposit pheromone in the cell beneath them
+//   kernel void deposit(global const float* X0, global const float* Y0, APOLLON_FIELD_PARAMS(float, pheromone)) {
+//     const uint i = get_global_id(0);
+//     pheromone[APOLLON_FIELD_INDEX(pheromone, X0[i], Y0[i])] += 1.0f;
+//   }
+//
+//   // A kernel with `field = "pheromone"` runs one work item per cell
+//   kernel void evaporate(APOLLON_FIELD_PARAMS(float, pheromone)) {
+//     const uint cx = get_global_id(0);
+//     const uint cy = get_global_id(1);
+//     pheromone[cy * pheromone_width + cx] *= 0.95f;
+//   }
+//
+// Positions outside the field are clamped to its edge cells. Work items of different kernels see each other's writes,
+// but work items of one kernel writing the same cell race; use atomics or a separate output field where that matters.
+
+#ifndef APOLLON_FIELD_H
+#define APOLLON_FIELD_H
+
+#define APOLLON_FIELD_PARAMS(type, name) \
+  global type* name, \
+  uint name##_width, \
+  uint name##_height, \
+  float name##_origin_x, \
+  float name##_origin_y, \
+  float name##_cell_width, \
+  float name##_cell_height
+
+// Index of the cell holding world position (x, y)
+#define APOLLON_FIELD_INDEX(name, x, y) \
+  apollon_field_index(name##_width, name##_height, name##_origin_x, name##_origin_y, name##_cell_width, name##_cell_height, (x), (y))
+
+// Index of the cell dx columns & dy rows from cell (cx, cy), ie a neighbor in a diffusion stencil
+#define APOLLON_FIELD_NEIGHBOR(name, cx, cy, dx, dy) \
+  apollon_field_neighbor(name##_width, name##_height, (cx), (cy), (dx), (dy))
+
+// Bilinear interpolation of a float field between cell centers at world position (x, y)
+#define APOLLON_FIELD_SAMPLE(name, x, y) \
+  apollon_field_sample(name, name##_width, name##_height, name##_origin_x, name##_origin_y, name##_cell_width, name##_cell_height, (x), (y))
+
+inline uint apollon_field_index(uint width, uint height, float origin_x, float origin_y, float cell_width, float cell_height, float x, float y) {
+  const int cx = clamp((int)floor((x - origin_x) / cell_width), 0, (int)width - 1);
+  const int cy = clamp((int)floor((y - origin_y) / cell_height), 0, (int)height - 1);
+  return (uint)cy * width + (uint)cx;
+}
+
+inline uint apollon_field_neighbor(uint width, uint height, uint cx, uint cy, int dx, int dy) {
+  const int nx = clamp((int)cx + dx, 0, (int)width - 1);
+  const int ny = clamp((int)cy + dy, 0, (int)height - 1);
+  return (uint)ny * width + (uint)nx;
+}
+
+inline float apollon_field_sample(const global float* field, uint width, uint height, float origin_x, float origin_y, float cell_width, float cell_height, float x, float y) {
+  const float px = clamp((x - origin_x) / cell_width - 0.5f, 0.0f, (float)(width - 1));
+  const float py = clamp((y - origin_y) / cell_height - 0.5f, 0.0f, (float)(height - 1));
+  const uint c0 = (uint)px;
+  const uint r0 = (uint)py;
+  const uint c1 = min(c0 + 1, width - 1);
+  const uint r1 = min(r0 + 1, height - 1);
+  const float top    = mix(field[r0 * width + c0], field[r0 * width + c1], px - (float)c0);
+  const float bottom = mix(field[r1 * width + c0], field[r1 * width + c1], px - (float)c0);
+  return mix(top, bottom, py - (float)r0);
+}
+
+#endif
@@ -0,0 +1,34 @@
+// apollon_graph.h
+// Traversal of the edges of SimControl.graph, which apollon stores in compressed sparse row (CSR) form.
+//
+// Kernels use this with `#include "apollon_graph.h"`; apollon substitutes this file before compiling.
+// The edges leaving entity i are row_offsets[i] .. row_offsets[i + 1] - 1, edge e leads to entity col_indices[e],
+// and every edge attribute column <attr> of the edge list is bound as `global const <type>* edge_<attr>` in the same edge order.
+// `uint edge_count` gives the number of edges, and edge_source[e] the entity edge e leaves.
+//
+//   kernel void spread(global const float* infected, global float* exposure, APOLLON_GRAPH_PARAMS, global const float* edge_weight) {
+//     const uint i = get_global_id(0);
+//     float sum = 0.0f;
+//     APOLLON_FOR_EACH_EDGE(i, e) {
+//       sum += infected[col_indices[e]] * edge_weight[e];
+//     }
+//     exposure[i] = sum;
+//   }
+//
+// Kernels may instead run one work item per edge with global_work_size = "edges", binding edge_source for each edge's source.
+// Edges connect entity indices, so the graph's table must have a fixed population (max_entities = 0).
+
+#ifndef APOLLON_GRAPH_H
+#define APOLLON_GRAPH_H
+
+#define APOLLON_GRAPH_PARAMS \
+  global const uint* row_offsets, \
+  global const uint* col_indices
+
+#define APOLLON_FOR_EACH_EDGE(i, e) for (uint e = row_offsets[(i)]; e < row_offsets[(i) + 1]; e++)
+
+inline uint apollon_out_degree(global const uint* row_offsets, uint i) {
+  return row_offsets[i + 1] - row_offsets[i];
+}
+
+#endif
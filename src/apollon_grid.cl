@@ -0,0 +1,43 @@
+// apollon_grid.h
+// Uniform grid spatial index for apollon kernels; see SimControl.spatial_index.
+//
+// Kernels use this with `#include "apollon_grid.h"`; apollon substitutes this file before compiling.
+// Before every step apollon sorts the indexed table's entities by grid cell. A kernel which declares
+//
+//   global const uint* cell_start, global const uint* cell_end, global const uint* sorted_index,
+//   uint grid_width, uint grid_height, float grid_cell_size, float grid_origin_x, float grid_origin_y
+//
+// visits every entity in the 3x3 cells around entity i (including i itself) with:
+//
+//   const int2 cell = apollon_grid_cell(X0[i], Y0[i], grid_origin_x, grid_origin_y, grid_cell_size, grid_width, grid_height);
+//   for (int cy = max(cell.y - 1, 0); cy <= min(cell.y + 1, (int)grid_height - 1); cy++) {
+//     for (int cx = max(cell.x - 1, 0); cx <= min(cell.x + 1, (int)grid_width - 1); cx++) {
+//       const uint c = apollon_grid_cell_index((int2)(cx, cy), grid_width);
+//       for (uint s = cell_start[c]; s < cell_end[c]; s++) {
+//         const uint j = sorted_index[s];
+//         ...
+//       }
+//     }
+//   }
+//
+// Entities within a cell are ordered by entity index, so the visiting order is the same on every device.
+// Positions outside the grid are clamped into its edge cells.
+
+#ifndef APOLLON_GRID_H
+#define APOLLON_GRID_H
+
+inline int2 apollon_grid_cell(float x, float y, float origin_x, float origin_y, float cell_size, uint grid_width, uint grid_height) {
+  const float cx = floor((x - origin_x) / cell_size);
+  const float cy = floor((y - origin_y) / cell_size);
+  // NaN positions land in cell (0, 0)
+  return (int2)(
+    isnan(cx) ? 0 : (int)clamp(cx, 0.0f, (float)(grid_width - 1)),
+    isnan(cy) ? 0 : (int)clamp(cy, 0.0f, (float)(grid_height - 1))
+  );
+}
+
+inline uint apollon_grid_cell_index(int2 cell, uint grid_width) {
+  return (uint)cell.y * grid_width + (uint)cell.x;
+}
+
+#endif
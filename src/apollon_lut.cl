@@ -0,0 +1,75 @@
+// apollon_lut.h
+// Interpolation helpers for array data constants (lookup tables).
+//
+// Kernels use this with `#include "apollon_lut.h"`; apollon substitutes this file before compiling.
+// An array data constant `drag` is bound by declaring `global const float* drag` (or `constant float* drag`), and the
+// scalars `uint drag_len` (rows) & `uint drag_cols` (elements per row) give its shape. 2D arrays are stored row-major.
+//
+//   // drag holds samples at altitudes 0, 500, 1000, ...
+//   const float d = apollon_interp1(drag, drag_len, 0.0f, 500.0f, altitude[i]);
+//
+//   // drag_curve = { file = "drag.csv", columns = ["altitude", "drag"] } holds [altitude, drag] rows sorted by altitude
+//   const float d = apollon_interp1_pairs(drag_curve, drag_curve_len, altitude[i]);
+//
+// Inputs outside the table are clamped to its first & last entries. These helpers take global pointers, so tables
+// passed to them must be declared `global const float*` rather than `constant float*`.
+
+#ifndef APOLLON_LUT_H
+#define APOLLON_LUT_H
+
+// Linear interpolation of len samples taken at x0, x0 + dx, x0 + 2*dx, ...
+inline float apollon_interp1(const global float* table, uint len, float x0, float dx, float x) {
+  if (len < 2) {
+    return len == 1 ? table[0] : 0.0f;
+  }
+  const float pos = clamp((x - x0) / dx, 0.0f, (float)(len - 1));
+  const uint lo = min((uint)pos, len - 2);
+  return mix(table[lo], table[lo + 1], pos - (float)lo);
+}
+
+// Linear interpolation of rows [x, y] pairs sorted by x
+inline float apollon_interp1_pairs(const global float* table, uint rows, float x) {
+  if (rows < 1) {
+    return 0.0f;
+  }
+  if (x <= table[0]) {
+    return table[1];
+  }
+  if (x >= table[2 * (rows - 1)]) {
+    return table[2 * (rows - 1) + 1];
+  }
+  // Find the last row whose x is <= x
+  uint lo = 0;
+  uint hi = rows - 1;
+  while (hi - lo > 1) {
+    const uint mid = (lo + hi) / 2;
+    if (table[2 * mid] <= x) {
+      lo = mid;
+    }
+    else {
+      hi = mid;
+    }
+  }
+  const float x_lo = table[2 * lo];
+  const float x_hi = table[2 * hi];
+  const float t = x_hi > x_lo ? (x - x_lo) / (x_hi - x_lo) : 0.0f;
+  return mix(table[2 * lo + 1], table[2 * hi + 1], t);
+}
+
+// Bilinear interpolation of a rows x cols grid whose row r & column c hold the sample at (x0 + c*dx, y0 + r*dy)
+inline float apollon_interp2(const global float* grid, uint rows, uint cols, float x0, float dx, float y0, float dy, float x, float y) {
+  if (rows < 1 || cols < 1) {
+    return 0.0f;
+  }
+  const float px = clamp((x - x0) / dx, 0.0f, (float)(cols - 1));
+  const float py = clamp((y - y0) / dy, 0.0f, (float)(rows - 1));
+  const uint c0 = (uint)px;
+  const uint r0 = (uint)py;
+  const uint c1 = min(c0 + 1, cols - 1);
+  const uint r1 = min(r0 + 1, rows - 1);
+  const float top    = mix(grid[r0 * cols + c0], grid[r0 * cols + c1], px - (float)c0);
+  const float bottom = mix(grid[r1 * cols + c0], grid[r1 * cols + c1], px - (float)c0);
+  return mix(top, bottom, py - (float)r0);
+}
+
+#endif
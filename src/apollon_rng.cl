@@ -0,0 +1,93 @@
+// apollon_rng.h
+// Counter-based Philox4x32-10 random numbers for apollon kernels, as described in
+// "Parallel Random Numbers: As Easy as 1, 2, 3" (Salmon et al, SC11).
+//
+// Kernels use this with `#include "apollon_rng.h"`; apollon substitutes this file before compiling.
+// Every (seed, step, entity, stream) tuple gives an independent stream which is identical on every device,
+// so a kernel declaring `ulong random_seed, ulong sim_step` can draw reproducible numbers with:
+//
+//   apollon_rng rng = apollon_rng_init(random_seed, sim_step, get_global_id(0), 0);
+//   float u = apollon_rng_float(&rng);   // uniform [0, 1)
+//   float z = apollon_rng_normal(&rng);  // standard normal
+//
+// Use a different stream number for each kernel which draws numbers for the same entity in the same step.
+
+#ifndef APOLLON_RNG_H
+#define APOLLON_RNG_H
+
+#define APOLLON_PHILOX_M0 0xD2511F53u
+#define APOLLON_PHILOX_M1 0xCD9E8D57u
+#define APOLLON_PHILOX_W0 0x9E3779B9u
+#define APOLLON_PHILOX_W1 0xBB67AE85u
+
+typedef struct {
+  uint4 ctr;   // (entity, stream, step lo, step hi); ctr.y's upper 16 bits count blocks drawn
+  uint2 key;   // seed
+  uint4 block; // last generated block
+  uint used;   // number of values of block already returned
+} apollon_rng;
+
+inline uint4 apollon_philox4x32_round(uint4 ctr, uint2 key) {
+  const uint lo0 = APOLLON_PHILOX_M0 * ctr.x;
+  const uint hi0 = mul_hi(APOLLON_PHILOX_M0, ctr.x);
+  const uint lo1 = APOLLON_PHILOX_M1 * ctr.z;
+  const uint hi1 = mul_hi(APOLLON_PHILOX_M1, ctr.z);
+  return (uint4)(hi1 ^ ctr.y ^ key.x, lo1, hi0 ^ ctr.w ^ key.y, lo0);
+}
+
+inline uint4 apollon_philox4x32_10(uint4 ctr, uint2 key) {
+  for (int r = 0; r < 9; r++) {
+    ctr = apollon_philox4x32_round(ctr, key);
+    key += (uint2)(APOLLON_PHILOX_W0, APOLLON_PHILOX_W1);
+  }
+  return apollon_philox4x32_round(ctr, key);
+}
+
+inline apollon_rng apollon_rng_init(ulong seed, ulong step, uint entity, uint stream) {
+  apollon_rng rng;
+  rng.ctr = (uint4)(entity, stream & 0xFFFFu, (uint)step, (uint)(step >> 32));
+  rng.key = (uint2)((uint)seed, (uint)(seed >> 32));
+  rng.block = (uint4)(0, 0, 0, 0);
+  rng.used = 4;
+  return rng;
+}
+
+inline uint apollon_rng_uint(apollon_rng* rng) {
+  if (rng->used >= 4) {
+    rng->block = apollon_philox4x32_10(rng->ctr, rng->key);
+    rng->ctr.y += 0x10000u;
+    rng->used = 0;
+  }
+  const uint4 b = rng->block;
+  const uint i = rng->used++;
+  return i == 0 ? b.x : (i == 1 ? b.y : (i == 2 ? b.z : b.w));
+}
+
+// Uniform in [0, 1) with 24 bits of precision
+inline float apollon_rng_float(apollon_rng* rng) {
+  return (float)(apollon_rng_uint(rng) >> 8) * (1.0f / 16777216.0f);
+}
+
+// Uniform in [lo, hi)
+inline float apollon_rng_range(apollon_rng* rng, float lo, float hi) {
+  return lo + (hi - lo) * apollon_rng_float(rng);
+}
+
+// Standard normal via the Box-Muller transform
+inline float apollon_rng_normal(apollon_rng* rng) {
+  const float u1 = 1.0f - apollon_rng_float(rng); // (0, 1] so log() is finite
+  const float u2 = apollon_rng_float(rng);
+  return sqrt(-2.0f * log(u1)) * cospi(2.0f * u2);
+}
+
+#ifdef cl_khr_fp64
+#pragma OPENCL EXTENSION cl_khr_fp64 : enable
+// Uniform in [0, 1) with 53 bits of precision
+inline double apollon_rng_double(apollon_rng* rng) {
+  const ulong hi = apollon_rng_uint(rng) >> 5;
+  const ulong lo = apollon_rng_uint(rng) >> 6;
+  return (double)((hi << 26) | lo) * (1.0 / 9007199254740992.0);
+}
+#endif
+
+#endif
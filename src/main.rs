@@ -45,10 +45,53 @@ fn main() -> Result<(), Box<dyn std::error::Error>>  {
 async fn main_async(args: &structs::Args) -> Result<(), Box<dyn std::error::Error>> {
   let total_start = std::time::Instant::now();
 
-  let mut simcontrol = utils::read_simcontrol_file(&args.simcontrol_file_path).await.map_err(structs::eloc!())?;
+  // A resumed simulation uses the fully resolved simcontrol stored in its checkpoint
+  let resume_checkpoint = match &args.resume {
+    Some(checkpoint_path) => Some(utils::read_checkpoint(checkpoint_path).map_err(structs::eloc!())?),
+    None => None,
+  };
+  let mut simcontrol = if let Some(ref checkpoint) = resume_checkpoint {
+    eprintln!("Resuming from {} at step {}", args.resume.as_ref().map(|p| p.display().to_string()).unwrap_or_default(), checkpoint.header.step);
+    checkpoint.header.simcontrol.clone()
+  }
+  else {
+    let simcontrol_file_path = args.simcontrol_file_path.as_ref().ok_or("A simcontrol file is required unless --resume is given")?;
+    utils::read_simcontrol_file(simcontrol_file_path).await.map_err(structs::eloc!())?
+  };
   // Overwrite any simcontrol args w/ cli-specified args
   utils::inplace_update_simcontrol_from_args(&mut simcontrol, args);
-  let simcontrol = simcontrol;
+  if resume_checkpoint.is_some() && simcontrol.ensemble_size > 1 {
+    return Err("--resume continues a single simulation and cannot be combined with an ensemble".into());
+  }
+  if let Some(ref checkpoint) = resume_checkpoint {
+    utils::check_resumable_outputs(&simcontrol, &checkpoint.header).map_err(structs::eloc!())?;
+  }
+  let checkpoint_file_path_s = simcontrol.checkpoint_file_path.to_string_lossy();
+  if simcontrol.ensemble_size > 1 && !(checkpoint_file_path_s == "/dev/null" || checkpoint_file_path_s == "NUL") {
+    eprintln!("[ Warning ] Ensemble replicas cannot be resumed, so checkpoint_file_path {} is not written", simcontrol.checkpoint_file_path.display());
+  }
+  if simcontrol.has_dynamic_population(&simcontrol.table_name) && simcontrol.ensemble_size > 1 {
+    return Err("Ensemble statistics are computed per entity index, which requires the primary table to have a fixed population (max_entities = 0)".into());
+  }
+  if simcontrol.mode == structs::SimulationMode::Event && simcontrol.table_names().iter().any(|table| simcontrol.has_dynamic_population(table)) {
+    return Err("Events name entities by index, which requires every table to have a fixed population (max_entities = 0) in an event-driven simulation".into());
+  }
+  // Event types become APOLLON_EVENT_<TYPE> macros of the kernels, so they must be distinct C identifiers once uppercased
+  for (event_type_i, event_type) in simcontrol.event_types.iter().enumerate() {
+    let is_identifier = event_type.chars().next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false)
+      && event_type.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !is_identifier {
+      return Err(format!("Event type \"{}\" must be a C identifier (letters, digits & '_', not starting with a digit) as it is defined as APOLLON_EVENT_{}", event_type, event_type.to_uppercase()).into());
+    }
+    if simcontrol.event_types[..event_type_i].iter().any(|t| t.to_uppercase() == event_type.to_uppercase()) {
+      return Err(format!("Event type \"{}\" is declared more than once in event_types (names are compared ignoring case)", event_type).into());
+    }
+  }
+  for event_def in simcontrol.initial_events.iter() {
+    if simcontrol.event_type_id(&event_def.event_type).is_none() {
+      return Err(format!("Initial event type \"{}\" is not one of event_types {:?}", &event_def.event_type, &simcontrol.event_types).into());
+    }
+  }
 
   if args.verbose >= 2 {
     println!("simcontrol = {:#?}", simcontrol);
@@ -63,12 +106,66 @@ async fn main_async(args: &structs::Args) -> Result<(), Box<dyn std::error::Erro
     }
   }
 
-  let t0_data = utils::read_ld_file(&simcontrol.input_data_file_path).await;
-  let mut cl_kernels = utils::read_cl_kernel_file(&simcontrol.cl_kernels_file_path).await.map_err(structs::eloc!())?.kernel;
+  let tables = utils::read_table_data(&simcontrol).await.map_err(structs::eloc!())?;
+  let graph = utils::read_graph(&simcontrol, &tables).await.map_err(structs::eloc!())?;
+  if let Some(ref graph) = graph {
+    if simcontrol.has_dynamic_population(&graph.table) {
+      return Err(format!("graph edges connect entities by index, which requires table '{}' to have a fixed population (max_entities = 0)", &graph.table).into());
+    }
+  }
+  let t0_data = &tables[&simcontrol.table_name];
+  let cl_kernels_file = utils::read_cl_kernel_file(&simcontrol.cl_kernels_file_path).await.map_err(structs::eloc!())?;
+  let mut cl_kernels = cl_kernels_file.kernel;
+  utils::load_array_constant_files(&mut simcontrol, &mut cl_kernels).await.map_err(structs::eloc!())?;
+  utils::load_field_inputs(&mut simcontrol).await.map_err(structs::eloc!())?;
+  let simcontrol = simcontrol;
+  let reductions = cl_kernels_file.reduction;
+  let global_buffers = cl_kernels_file.global_buffer;
+  for (buffer_i, global_buffer) in global_buffers.iter().enumerate() {
+    if global_buffer.len < 1 || global_buffer.name.contains('.') || global_buffers[..buffer_i].iter().any(|b| b.name == global_buffer.name) {
+      return Err(format!("global_buffer {} must have a unique name without '.' and len >= 1", &global_buffer.name).into());
+    }
+    if structs::SPATIAL_INDEX_BUFFERS.contains(&global_buffer.name.as_str()) || structs::EVENT_BUFFERS.iter().any(|(name, _type)| *name == global_buffer.name) || structs::GRAPH_BUFFERS.contains(&global_buffer.name.as_str()) {
+      return Err(format!("global_buffer {} has the name of a buffer managed by apollon", &global_buffer.name).into());
+    }
+    if simcontrol.fields.iter().any(|f| f.name == global_buffer.name) {
+      return Err(format!("global_buffer {} has the name of a field", &global_buffer.name).into());
+    }
+    if global_buffer.init.iter().any(|v| matches!(v, structs::Value::String(_))) {
+      return Err(format!("global_buffer {} has a string init value; only numbers are allowed", &global_buffer.name).into());
+    }
+  }
+
+  // Reserved arguments are supplied by apollon, so a data constant of the same name would be silently ignored
+  let mut data_constant_names: Vec<(String, &str)> = args.data_constant.iter().map(|dc| ("--data-constant".to_string(), dc.name.as_str())).collect();
+  data_constant_names.extend(simcontrol.data_constants.keys().map(|name| ("simcontrol data_constants".to_string(), name.as_str())));
+  for cl_kernel in cl_kernels.iter() {
+    data_constant_names.extend(cl_kernel.data_constants.iter().map(|dc| (format!("kernel {}", &cl_kernel.name), dc.name.as_str())));
+  }
+  for (source, name) in data_constant_names.iter() {
+    if simcontrol.is_reserved_arg_name(name) {
+      return Err(format!("Data constant '{}' of {} has the name of an argument reserved by apollon; rename it (and the kernel argument)", name, source).into());
+    }
+  }
+
+  for cl_kernel in cl_kernels.iter() {
+    if let Some(ref on_event) = cl_kernel.on_event {
+      if simcontrol.event_type_id(on_event).is_none() {
+        return Err(format!("Kernel {} handles event type \"{}\" which is not one of event_types {:?}", &cl_kernel.name, on_event, &simcontrol.event_types).into());
+      }
+    }
+    if let Some(ref field) = cl_kernel.field {
+      if !simcontrol.fields.iter().any(|f| &f.name == field) {
+        return Err(format!("Kernel {} runs over field \"{}\", which is not declared", &cl_kernel.name, field).into());
+      }
+    }
+  }
 
   if args.verbose >= 2 {
-    println!("t0_data = {:#?}", &t0_data);
+    println!("tables = {:#?}", &tables);
     println!("cl_kernels = {:#?}", &cl_kernels);
+    println!("reductions = {:#?}", &reductions);
+    println!("global_buffers = {:#?}", &global_buffers);
   }
 
   let context = opencl3::context::Context::from_device(&device).map_err(structs::eloc!())?;
@@ -79,22 +176,152 @@ async fn main_async(args: &structs::Args) -> Result<(), Box<dyn std::error::Erro
   // Compile cl_kernel source code to programs
   let kernel_compile_start = std::time::Instant::now();
   for i in 0..cl_kernels.len() {
-    cl_kernels[i].load_program(&context).map_err(structs::eloc!())?;
+    cl_kernels[i].load_program(&context, &simcontrol).map_err(structs::eloc!())?;
   }
   let kernel_compile_end = std::time::Instant::now();
   eprintln!("CL Kernel Compile Time: {}", utils::duration_to_display_str(&(kernel_compile_end - kernel_compile_start)));
 
   video_rs::init()?;
 
+  // Programs generated by apollon (ie conversion kernels) are compiled on first use & cached here for all runs.
+  let mut generated_programs = utils::CL_ProgramCache::new();
+
+  // Every combination of swept data_constants becomes one run; all runs share the device context & compiled kernels above.
+  let sweep_combinations = simcontrol.data_constant_sweep_combinations().map_err(structs::eloc!())?;
+  let inputs = structs::SimInputs {
+    device: &device,
+    context: &context,
+    cl_kernels: &cl_kernels,
+    reductions: &reductions,
+    global_buffers: &global_buffers,
+    graph: graph.as_ref(),
+    tables: &tables,
+  };
+  let num_runs = sweep_combinations.len();
+  let mut run_stop_steps: Vec<(String, Option<u64>)> = vec![];
+  for (run_i, combination) in sweep_combinations.iter().enumerate() {
+    let mut run_simcontrol = simcontrol.with_data_constants(combination);
+    run_simcontrol.output_data_file_path = utils::template_run_path(&simcontrol.output_data_file_path, num_runs, run_i, combination);
+    run_simcontrol.output_animation_file_path = utils::template_run_path(&simcontrol.output_animation_file_path, num_runs, run_i, combination);
+    run_simcontrol.trajectory_file_path = utils::template_run_path(&simcontrol.trajectory_file_path, num_runs, run_i, combination);
+    run_simcontrol.stats_file_path = utils::template_run_path(&simcontrol.stats_file_path, num_runs, run_i, combination);
+    run_simcontrol.global_buffers_file_path = utils::template_run_path(&simcontrol.global_buffers_file_path, num_runs, run_i, combination);
+    run_simcontrol.checkpoint_file_path = utils::template_run_path(&simcontrol.checkpoint_file_path, num_runs, run_i, combination);
+    for table in run_simcontrol.tables.iter_mut() {
+      table.output = utils::template_run_path(&table.output, num_runs, run_i, combination);
+    }
+
+    if num_runs > 1 {
+      let combination_s: Vec<String> = combination.iter().map(|(name, value)| format!("{}={}", name, value.to_string())).collect();
+      eprintln!("===== Run {} of {}: {} =====", run_i + 1, num_runs, combination_s.join(" "));
+    }
+
+    if run_simcontrol.ensemble_size > 1 {
+      // Replicas differ only in random_seed; their final states are reduced to statistics in run_simcontrol.output_data_file_path
+      let mut ensemble = utils::EnsembleAccumulator::new(&run_simcontrol.ensemble_quantiles, run_simcontrol.ensemble_trajectory_stats && run_simcontrol.trajectory_step_period > 0).map_err(structs::eloc!())?;
+      for replica_i in 0..run_simcontrol.ensemble_size {
+        let mut replica_simcontrol = run_simcontrol.clone();
+        replica_simcontrol.random_seed = run_simcontrol.random_seed.wrapping_add(replica_i);
+        replica_simcontrol.checkpoint_file_path = structs::serde_default_pathbuf_devnull();
+        // Ensemble statistics cover the primary table only
+        for table in replica_simcontrol.tables.iter_mut() {
+          table.output = structs::serde_default_pathbuf_devnull();
+        }
+        if replica_i > 0 {
+          replica_simcontrol.output_animation_file_path = structs::serde_default_pathbuf_devnull();
+        }
+        replica_simcontrol.trajectory_file_path = if run_simcontrol.ensemble_trajectory_stats {
+          structs::serde_default_pathbuf_devnull()
+        }
+        else {
+          utils::template_replica_path(&run_simcontrol.trajectory_file_path, replica_i)
+        };
+        replica_simcontrol.stats_file_path = utils::template_replica_path(&run_simcontrol.stats_file_path, replica_i);
+        replica_simcontrol.global_buffers_file_path = utils::template_replica_path(&run_simcontrol.global_buffers_file_path, replica_i);
+
+        eprintln!("----- Replica {} of {} (random_seed={}) -----", replica_i + 1, run_simcontrol.ensemble_size, replica_simcontrol.random_seed);
+        let stop_step_i = run_simulation(args, &replica_simcontrol, &inputs, &mut generated_programs, None, Some(&mut ensemble)).await.map_err(structs::eloc!())?;
+        if !simcontrol.stop_conditions.is_empty() {
+          let combination_s: Vec<String> = combination.iter().map(|(name, value)| format!("{}={}", name, value.to_string())).collect();
+          run_stop_steps.push((format!("{} replica={}", combination_s.join(" "), replica_i), stop_step_i));
+        }
+      }
+      ensemble.write_final(args, t0_data, &run_simcontrol.output_data_file_path).await.map_err(structs::eloc!())?;
+      if ensemble.trajectory_stats {
+        ensemble.write_trajectory(&run_simcontrol.trajectory_file_path).map_err(structs::eloc!())?;
+      }
+      continue;
+    }
+
+    let stop_step_i = run_simulation(args, &run_simcontrol, &inputs, &mut generated_programs, resume_checkpoint.as_ref(), None).await.map_err(structs::eloc!())?;
+    if !simcontrol.stop_conditions.is_empty() {
+      let combination_s: Vec<String> = combination.iter().map(|(name, value)| format!("{}={}", name, value.to_string())).collect();
+      run_stop_steps.push((combination_s.join(" "), stop_step_i));
+    }
+  }
+
+  // With a parameter sweep or ensemble, summarize when each run stopped
+  if run_stop_steps.len() > 1 {
+    eprintln!("===== Stop steps =====");
+    for (combination_s, stop_step_i) in run_stop_steps.iter() {
+      match stop_step_i {
+        Some(stop_step_i) => eprintln!("{}: stopped at step {}", combination_s, stop_step_i),
+        None => eprintln!("{}: no stop condition fired within {} steps", combination_s, simcontrol.num_steps),
+      }
+    }
+  }
+
+  let total_end = std::time::Instant::now();
+  eprintln!("Total Time: {}", utils::duration_to_display_str(&(total_end - total_start)));
+
+  if let Some(cmd_txt) = &args.post_sim_cmd {
+    tokio::process::Command::new("sh")
+      .arg("-c")
+      .arg(cmd_txt)
+      .spawn()?
+      .wait().await?;
+  }
+
+  Ok(())
+}
+
+
+/// Runs a single simulation from the T=0 data of every entity table for simcontrol.num_steps steps, writing the animation and final output data of every table.
+/// If resume is given, device buffers & renderer state are restored from it and stepping continues from its step;
+/// the trajectory and stats files are cut back to their checkpointed lengths & appended to (see utils::check_resumable_outputs)
+/// and the animation continues in a segment file (see utils::template_resume_segment_path).
+/// Returns the step at which a stop condition ended the simulation, if one did.
+/// If ensemble is given the final state (and trajectory, if it collects trajectory statistics) is added to it
+/// instead of being written to simcontrol.output_data_file_path; the other tables are then not written.
+async fn run_simulation(
+    args: &structs::Args,
+    simcontrol: &structs::SimControl,
+    inputs: &structs::SimInputs<'_>,
+    generated_programs: &mut utils::CL_ProgramCache,
+    resume: Option<&structs::Checkpoint>,
+    mut ensemble: Option<&mut utils::EnsembleAccumulator>,
+  ) -> Result<Option<u64>, Box<dyn std::error::Error>>
+{
+  let structs::SimInputs { device, context, cl_kernels, reductions, global_buffers, graph, tables } = *inputs;
   let encoder_width_usize = simcontrol.output_animation_width as usize;
   let encoder_height_usize = simcontrol.output_animation_height as usize;
   let settings = video_rs::encode::Settings::preset_h264_yuv420p(encoder_width_usize, encoder_height_usize, false);
   let mut encoder: Option<video_rs::encode::Encoder> = None;
   if !(simcontrol.output_animation_file_path.to_string_lossy() == "/dev/null" || simcontrol.output_animation_file_path.to_string_lossy() == "NUL") {
-    encoder = Some(video_rs::encode::Encoder::new(simcontrol.output_animation_file_path.clone(), settings).map_err(structs::eloc!())?);
+    // A resumed run continues the animation in a new segment, whose first frame follows the last checkpointed one
+    let animation_path = match resume {
+      Some(checkpoint) => {
+        let segment_path = utils::template_resume_segment_path(&simcontrol.output_animation_file_path, checkpoint.header.step);
+        eprintln!("Continuing the animation {} after its first {} frames in {}", simcontrol.output_animation_file_path.display(), checkpoint.header.anim_frames_encoded, segment_path.display());
+        segment_path
+      }
+      None => simcontrol.output_animation_file_path.clone(),
+    };
+    encoder = Some(video_rs::encode::Encoder::new(animation_path, settings).map_err(structs::eloc!())?);
   }
   let anim_frame_duration = video_rs::time::Time::from_secs_f64(simcontrol.output_animation_frame_delay as f64 / 1000.0f64);
   let mut anim_t_position = video_rs::time::Time::zero();
+  let mut anim_frames_encoded: u64 = 0;
 
   let mut plotter_dt = raqote::DrawTarget::new(simcontrol.output_animation_width as i32, simcontrol.output_animation_height as i32);
   let plotter_dt_f32width = simcontrol.output_animation_width as f32;
@@ -113,53 +340,109 @@ async fn main_async(args: &structs::Args) -> Result<(), Box<dyn std::error::Erro
 
   let simulation_start = std::time::Instant::now();
 
-  // Each step we go in between ListedData (sim_data) and a utils::ld_data_to_kernel_data vector; eventually
-  // the best approach is to keep everything in a utils::ld_data_to_kernel_data format & map indexes between kernels so they read/write the same data.
-  let mut sim_data = t0_data.clone();
-
-  // anim_point_history is used as a circular buffer
-  let mut anim_point_history: Vec<(f32, f32)> = vec![(0.0, 0.0); sim_data.len() * simcontrol.max_historic_entity_locations];
-  let mut anim_point_history_i = 0;
-
-  // For performance reasons we pre-allocate all entity colors here and re-use
-  // when plotting data. This means there will be NO capability to change an entity color in the middle of
-  // a sim; and if there were I'd want to provide the API as an "index into known colors" anyhow.
-  let mut sim_data_colors: Vec<raqote::Source> = vec![];
-  for row in sim_data.iter() {
-    if let Some(str_val) = row.get(&simcontrol.gis_color_attr) {
-      match csscolorparser::parse(str_val.to_string().as_str()) {
-        Ok(css_color_obj) => {
-          let components = css_color_obj.to_rgba8();
-          //sim_data_colors.push( plotters::style::RGBColor(components[0], components[1], components[2]) );
-          sim_data_colors.push( raqote::Source::Solid(raqote::SolidSource::from_unpremultiplied_argb(0xff, components[0], components[1], components[2])) );
-        }
-        Err(e) => {
-          if args.verbose > 0 {
-            eprintln!("{:?}", e);
+  // Simulation state lives in the device buffers of all_kernel_args for the entire run; tables is only used to upload
+  // initial values & for host-only attributes such as names and colors. Columns are read back into host_columns
+  // only when the renderer needs them, and every written column is read back once at the end for the output files.
+  let t0_data = &tables[&simcontrol.table_name];
+  let num_entities = t0_data.len();
+  let mut table_num_entities: std::collections::HashMap<String, usize> = tables.iter().map(|(name, ld)| (name.clone(), ld.len())).collect();
+  let table_capacities: std::collections::HashMap<String, usize> = tables.iter().map(|(name, ld)| (name.clone(), simcontrol.table_capacity(name, ld.len()))).collect();
+  // Tables whose entities may be spawned & removed during the run; live entities occupy [0, table_num_entities[name]) of their buffers
+  let mut populations: std::collections::HashMap<String, structs::TablePopulation> = tables.iter()
+    .filter(|(name, _ld)| simcontrol.has_dynamic_population(name))
+    .map(|(name, ld)| (name.clone(), structs::TablePopulation::new(ld.len(), table_capacities[name])))
+    .collect();
+
+  // Every table is drawn with its own coordinate, name & color attributes
+  let mut render_tables: Vec<RenderTable> = vec![];
+  for table_name in simcontrol.table_names() {
+    let entity_table = simcontrol.tables.iter().find(|t| t.name == table_name);
+    if entity_table.map(|t| !t.render).unwrap_or(false) {
+      continue;
+    }
+    let gis_x_attr_name = entity_table.and_then(|t| t.gis_x_attr_name.clone()).unwrap_or_else(|| simcontrol.gis_x_attr_name.clone());
+    let gis_y_attr_name = entity_table.and_then(|t| t.gis_y_attr_name.clone()).unwrap_or_else(|| simcontrol.gis_y_attr_name.clone());
+    let gis_name_attr = entity_table.and_then(|t| t.gis_name_attr.clone()).unwrap_or_else(|| simcontrol.gis_name_attr.clone());
+    let gis_color_attr = entity_table.and_then(|t| t.gis_color_attr.clone()).unwrap_or_else(|| simcontrol.gis_color_attr.clone());
+    let ld_data = &tables[table_name];
+
+    // For performance reasons we pre-allocate all entity colors here and re-use
+    // when plotting data. This means there will be NO capability to change an entity color in the middle of
+    // a sim; and if there were I'd want to provide the API as an "index into known colors" anyhow.
+    let mut sim_data_colors: Vec<raqote::Source<'static>> = vec![];
+    for row in ld_data.iter() {
+      if let Some(str_val) = row.get(&gis_color_attr) {
+        match csscolorparser::parse(str_val.to_string().as_str()) {
+          Ok(css_color_obj) => {
+            let components = css_color_obj.to_rgba8();
+            //sim_data_colors.push( plotters::style::RGBColor(components[0], components[1], components[2]) );
+            sim_data_colors.push( raqote::Source::Solid(raqote::SolidSource::from_unpremultiplied_argb(0xff, components[0], components[1], components[2])) );
+          }
+          Err(e) => {
+            if args.verbose > 0 {
+              eprintln!("{:?}", e);
+            }
+           sim_data_colors.push(plotter_dt_solid_black.clone());
           }
-         sim_data_colors.push(plotter_dt_solid_black.clone());
         }
       }
+      else {
+         sim_data_colors.push(plotter_dt_solid_black.clone());
+      }
     }
-    else {
-       sim_data_colors.push(plotter_dt_solid_black.clone());
+
+    render_tables.push(RenderTable {
+      table_name: table_name.to_string(),
+      ld_data,
+      x_column: simcontrol.qualified_column_name(table_name, &gis_x_attr_name),
+      y_column: simcontrol.qualified_column_name(table_name, &gis_y_attr_name),
+      gis_x_attr_name,
+      gis_y_attr_name,
+      gis_name_attr,
+      colors: sim_data_colors,
+    });
+  }
+
+  // Graph edges are drawn as lines beneath the entities of their table
+  let graph_edge_color: Option<raqote::Source<'static>> = match (&simcontrol.graph, graph) {
+    (Some(graph_def), Some(_)) if graph_def.render => {
+      let components = csscolorparser::parse(&graph_def.edge_color).map_err(structs::eloc!(format!("graph edge_color {}", &graph_def.edge_color)))?.to_rgba8();
+      Some(raqote::Source::Solid(raqote::SolidSource::from_unpremultiplied_argb(components[3], components[0], components[1], components[2])))
+    }
+    _ => None,
+  };
+  let graph_edge_sources: Vec<u32> = graph.map(|g| g.edge_sources()).unwrap_or_default();
+
+  let mut host_columns: std::collections::HashMap<String, structs::HostColumn> = std::collections::HashMap::new();
+  for render_table in render_tables.iter() {
+    for (gis_attr, gis_column) in [(&render_table.gis_x_attr_name, &render_table.x_column), (&render_table.gis_y_attr_name, &render_table.y_column)] {
+      if let Some(host_column) = structs::HostColumn::from_ld_column(render_table.ld_data, gis_attr) {
+        host_columns.insert(gis_column.to_string(), host_column);
+      }
     }
   }
 
+  // anim_point_history is used as a circular buffer
+  let num_rendered_entities: usize = render_tables.iter().map(|rt| table_capacities[&rt.table_name]).sum();
+  let mut anim_point_history: Vec<(f32, f32)> = vec![(0.0, 0.0); num_rendered_entities * simcontrol.max_historic_entity_locations];
+  let mut anim_point_history_i = 0;
+
   //let mut sim_data_rasterized_argb: Vec<> = vec![];
 
 
   // We also read an arbitrary background image, or use white as a background for the renderer.
   let sim_bg_argb_frame: Vec<u32> = if simcontrol.background_img.len() > 0 {
-    let img = image::ImageReader::open(simcontrol.background_img)?.decode()?;
-
-
-    std::unimplemented!()
+    let img = image::ImageReader::open(&simcontrol.background_img)?.decode().map_err(structs::eloc!(format!("background_img {}", &simcontrol.background_img)))?;
+    let img = img.resize_exact(simcontrol.output_animation_width, simcontrol.output_animation_height, image::imageops::FilterType::Triangle).to_rgb8();
+    img.pixels().map(|px| 0xff000000u32 | ((px.0[0] as u32) << 16) | ((px.0[1] as u32) << 8) | (px.0[2] as u32)).collect()
   }
   else {
-    vec![0xffffffffu32, ((simcontrol.output_animation_width * simcontrol.output_animation_height) as usize).try_into().unwrap()]
+    vec![0xffffffffu32; (simcontrol.output_animation_width * simcontrol.output_animation_height) as usize]
   };
 
+  // Fields with render = true are drawn over the background, beneath everything else
+  let render_fields: Vec<&structs::FieldDef> = simcontrol.fields.iter().filter(|f| f.render).collect();
+
 
   let mut total_kernel_execs_duration = std::time::Duration::from_millis(0);
   let mut total_convert_overhead_duration = std::time::Duration::from_millis(0);
@@ -178,17 +461,26 @@ async fn main_async(args: &structs::Args) -> Result<(), Box<dyn std::error::Erro
   // allowing re-use of the same buffers across the entire simulation.
   let mut all_kernel_args: Vec<structs::CL_NamedTaggedArgument> = vec![];
   let mut all_kernel_arg_indicies: Vec<Vec<usize>> = vec![];
+  // Parallel to all_kernel_arg_indicies; true where the kernel declared a non-const buffer it may write to.
+  let mut all_kernel_arg_writes: Vec<Vec<bool>> = vec![];
+  // (all_kernel_args index, schedule) of every scalar rewritten before every step: scheduled data constants, then reserved arguments without a schedule.
+  let mut dynamic_args: Vec<(usize, Option<structs::DataConstantSchedule>)> = vec![];
   for i in 0..cl_kernels.len() {
     if let Some(k) = &cl_kernels[i].cl_device_kernel {
 
       let ld_to_kernel_start = std::time::Instant::now();
-      let kernel_args = utils::ld_data_to_kernel_data_named(&args, &simcontrol, &sim_data, &context, &cl_kernels[i], &k, &queue, &sim_events_cl).map_err(structs::eloc!())?;
+      let kernel_args = utils::ld_data_to_kernel_data_named(args, simcontrol, inputs, &cl_kernels[i], k, &queue, &sim_events_cl).map_err(structs::eloc!())?;
       let ld_to_kernel_end = std::time::Instant::now();
       total_convert_overhead_duration += ld_to_kernel_end - ld_to_kernel_start;
 
       let mut this_kernel_ak_indicies: Vec<usize> = vec![];
+      let mut this_kernel_ak_writes: Vec<bool> = vec![];
 
       for kai in 0..kernel_args.len() {
+        // Arguments declared const never write their buffer, even where it is writable because it is shared (ie a packed vector buffer)
+        let is_declared_const = k.get_arg_type_qualifier(kai as u32).map(|q| q == 1).unwrap_or(false);
+        this_kernel_ak_writes.push(kernel_args[kai].tagged_argument.is_writable_buffer() && !is_declared_const);
+
         let mut all_kernel_args_existing_idx: Option<usize> = None;
         for akai in 0..all_kernel_args.len() {
           if kernel_args[kai].name == all_kernel_args[akai].name && std::mem::discriminant::<structs::CL_TaggedArgument>(kernel_args[kai].tagged_argument.borrow()) == std::mem::discriminant::<structs::CL_TaggedArgument>(all_kernel_args[akai].tagged_argument.borrow()) {
@@ -206,6 +498,11 @@ async fn main_async(args: &structs::Args) -> Result<(), Box<dyn std::error::Erro
             // New name,type must be added to all_kernel_args.
             // Calling .clone() will make the interior .tagged_argument read-only until kernel_args is dropped at the end of this cl_kernels[i] loop iteration.
             this_kernel_ak_indicies.push(all_kernel_args.len());
+            if !kernel_args[kai].tagged_argument.is_buffer() && reserved_arg_value(simcontrol, &table_num_entities, &table_capacities, &kernel_args[kai].name, 0, 0.0).is_none() {
+              if let Some(structs::DataConstantDef::Schedule(schedule)) = utils::resolve_data_constant(args, simcontrol, &cl_kernels[i], &kernel_args[kai].name) {
+                dynamic_args.push((all_kernel_args.len(), Some(schedule)));
+              }
+            }
             all_kernel_args.push(
               kernel_args[kai].clone()
             );
@@ -215,6 +512,16 @@ async fn main_async(args: &structs::Args) -> Result<(), Box<dyn std::error::Erro
       }
 
       all_kernel_arg_indicies.push(this_kernel_ak_indicies);
+      all_kernel_arg_writes.push(this_kernel_ak_writes);
+
+      // Packed vector buffers are unpacked into scalar buffers of their columns, which must exist even if no kernel binds them
+      for kernel_arg in kernel_args.iter().filter(|kernel_arg| !kernel_arg.components.is_empty()) {
+        for component_arg in utils::packed_component_buffers(args, simcontrol, tables, context, &queue, &cl_kernels[i], kernel_arg).map_err(structs::eloc!())? {
+          if !all_kernel_args.iter().any(|a| a.name == component_arg.name && a.tagged_argument.is_buffer()) {
+            all_kernel_args.push(component_arg);
+          }
+        }
+      }
 
     }
   }
@@ -230,59 +537,278 @@ async fn main_async(args: &structs::Args) -> Result<(), Box<dyn std::error::Erro
     eprintln!("all_kernel_arg_indicies = {:?}", all_kernel_arg_indicies);
   }
 
+  // The spatial index may add its buffers to all_kernel_args, so it is built before the conversion kernels
+  let spatial_index = match &simcontrol.spatial_index {
+    Some(def) => {
+      let index_table = def.table.as_deref().unwrap_or(simcontrol.table_name.as_str());
+      let index_table_capacity = *table_capacities.get(index_table).ok_or_else(|| format!("spatial_index indexes table '{}', which is not declared", index_table))?;
+      Some(structs::CL_SpatialIndex::new(simcontrol, def, &mut all_kernel_args, index_table_capacity, context, generated_programs).map_err(structs::eloc!())?)
+    }
+    None => None,
+  };
+
+  // The world boundary may add the alive column to all_kernel_args, so it is built before the conversion kernels as well
+  let world_boundary = match &simcontrol.world {
+    Some(def) => {
+      Some(structs::CL_WorldBoundary::new(simcontrol, def, &mut all_kernel_args, tables, context, &queue, generated_programs).map_err(structs::eloc!())?)
+    }
+    None => None,
+  };
+
   // Finally, we must create & inject "Conversion Kernels" into the stream where we have
   // Variable A of type A followed by Variable A of type B in all_kernel_args.
-  // ^^ TODO
+  let column_variants = structs::CL_ColumnVariants::new(&all_kernel_args, context, generated_programs).map_err(structs::eloc!())?;
+  let mut dev = structs::CL_DeviceState {
+    all_kernel_args,
+    kernel_arg_indicies: all_kernel_arg_indicies,
+    kernel_arg_writes: all_kernel_arg_writes,
+    column_variants,
+    queue,
+    sim_events,
+    sim_events_cl,
+  };
+  if args.verbose > 0 {
+    for (name, variants) in dev.column_variants.variants.iter() {
+      let variant_types: Vec<&str> = variants.iter().map(|akai| dev.all_kernel_args[*akai].tagged_argument.cl_type_name()).collect();
+      eprintln!("Column {} is used as {:?}; conversion kernels will keep these buffers in sync", name, variant_types);
+    }
+  }
+
+  // Optionally record per-step states of the primary table's entities; columns no kernel binds are recorded from their t0 values
+  let mut trajectory_columns: Vec<String> = if !simcontrol.trajectory_columns.is_empty() {
+    simcontrol.trajectory_columns.clone()
+  }
+  else {
+    let mut all_columns: Vec<String> = dev.all_kernel_args.iter()
+      .filter(|a| a.tagged_argument.is_buffer() && !structs::is_apollon_buffer_name(&a.name) && simcontrol.split_qualified_column_name(&a.name).0 == simcontrol.table_name)
+      .map(|a| a.name.clone()).collect();
+    all_columns.sort();
+    all_columns.dedup();
+    all_columns
+  };
+  // Entity indexes change as entities are removed & spawned, so rows of a dynamic population are identified by entity_id
+  let primary_population_is_dynamic = populations.contains_key(&simcontrol.table_name);
+  if primary_population_is_dynamic && !trajectory_columns.iter().any(|c| c == "entity_id") {
+    trajectory_columns.insert(0, "entity_id".to_string());
+  }
+  // A trajectory_step_period of 0 disables trajectory recording
+  let mut trajectory_writer = if simcontrol.trajectory_step_period > 0 {
+    utils::TrajectoryWriter::new(&simcontrol.trajectory_file_path, trajectory_columns.clone(), resume.and_then(|c| c.header.trajectory_file_offset)).map_err(structs::eloc!())?
+  }
+  else {
+    None
+  };
+  let record_trajectory = simcontrol.trajectory_step_period > 0 && (trajectory_writer.is_some() || ensemble.as_ref().map(|e| e.trajectory_stats).unwrap_or(false));
+  let mut trajectory_static_columns: std::collections::HashMap<String, structs::HostColumn> = std::collections::HashMap::new();
+  if record_trajectory {
+    for column in trajectory_columns.iter() {
+      if dev.canonical_idx(column).is_none() && !(primary_population_is_dynamic && column == "entity_id") {
+        let host_column = structs::HostColumn::from_ld_column(t0_data, column).ok_or_else(|| format!("trajectory_columns contains '{}', which is neither bound by a kernel nor present in the input data", column))?;
+        trajectory_static_columns.insert(column.to_string(), host_column);
+      }
+    }
+  }
+
+  let mut stop_conditions: Vec<structs::CL_StopCondition> = vec![];
+  for (condition_i, condition) in simcontrol.stop_conditions.iter().enumerate() {
+    if let Some(ref table) = condition.table {
+      if !table_num_entities.contains_key(table) {
+        return Err(format!("stop_conditions[{}] is evaluated over table '{}', which is not declared; tables are {:?}", condition_i, table, simcontrol.table_names()).into());
+      }
+    }
+    stop_conditions.push(structs::CL_StopCondition::new(simcontrol, condition_i, condition, &dev, context, generated_programs).map_err(structs::eloc!())?);
+  }
+  // (step, expression) of the stop condition which ended the simulation
+  let mut stopped_by: Option<(u64, String)> = None;
+
+  // Column statistics are computed on-device after every step's kernels & written to stats_file_path
+  let mut cl_reductions: Vec<structs::CL_Reduction> = vec![];
+  let max_work_group_size = device.max_work_group_size().map_err(structs::eloc!())?;
+  for (reduction_i, reduction) in reductions.iter().enumerate() {
+    if let Some(ref table) = reduction.table {
+      if !table_num_entities.contains_key(table) {
+        return Err(format!("reduction[{}] is evaluated over table '{}', which is not declared; tables are {:?}", reduction_i, table, simcontrol.table_names()).into());
+      }
+    }
+    cl_reductions.push(structs::CL_Reduction::new(simcontrol, reduction_i, reduction, &dev, max_work_group_size, context, generated_programs).map_err(structs::eloc!())?);
+  }
+  let stat_names: Vec<String> = reductions.iter().flat_map(|r| r.ops.iter().map(|op| r.stat_name(*op)).collect::<Vec<String>>()).collect();
+  for (stat_i, stat_name) in stat_names.iter().enumerate() {
+    if stat_names[..stat_i].contains(stat_name) {
+      return Err(format!("More than one [[reduction]] computes {}; give them distinct names", stat_name).into());
+    }
+  }
+  let mut stats_writer = utils::StatsWriter::new(&simcontrol.stats_file_path, stat_names.clone(), resume.and_then(|c| c.header.stats_file_offset)).map_err(structs::eloc!())?;
+  // Scalar kernel arguments named after a statistic receive its latest value
+  let reduction_arg_indicies: Vec<usize> = (0..dev.all_kernel_args.len())
+    .filter(|akai| !dev.all_kernel_args[*akai].tagged_argument.is_buffer() && stat_names.contains(&dev.all_kernel_args[*akai].name))
+    .collect();
+  let mut reduction_values: std::collections::HashMap<String, f64> = std::collections::HashMap::new();
+
+  // Global buffers reset every step are rewritten with their initial contents, captured before any kernel runs
+  let mut global_buffer_resets: Vec<(usize, structs::HostColumn)> = vec![];
+  for global_buffer in global_buffers.iter() {
+    let buffer_name = structs::apollon_buffer_name(&global_buffer.name);
+    match (0..dev.all_kernel_args.len()).find(|akai| dev.all_kernel_args[*akai].name == buffer_name) {
+      Some(akai) if global_buffer.reset == structs::GlobalBufferReset::EveryStep => {
+        let host_column = dev.all_kernel_args[akai].tagged_argument.read_to_host(&dev.queue, &dev.sim_events_cl).map_err(structs::eloc!())?.ok_or("Logic error! Global buffer is not a buffer")?;
+        global_buffer_resets.push((akai, host_column));
+      }
+      Some(_akai) => { }
+      None => {
+        if args.verbose > 0 {
+          eprintln!("[ Warning ] No kernel binds global_buffer {}", &global_buffer.name);
+        }
+      }
+    }
+  }
+
+  let checkpoint_enabled = !(simcontrol.checkpoint_file_path.to_string_lossy() == "/dev/null" || simcontrol.checkpoint_file_path.to_string_lossy() == "NUL") && simcontrol.checkpoint_step_period > 0;
+
+  // Overwrite the t0 buffers & renderer state with the checkpointed ones
+  let mut start_step_i: u64 = 0;
+  if let Some(checkpoint) = resume {
+    if checkpoint.header.num_entities != num_entities {
+      return Err(format!("Checkpoint holds {} entities but {} has {}", checkpoint.header.num_entities, simcontrol.input_data_file_path.display(), num_entities).into());
+    }
+    for akai in 0..dev.all_kernel_args.len() {
+      if !dev.all_kernel_args[akai].tagged_argument.is_buffer() {
+        continue;
+      }
+      let cl_type = dev.all_kernel_args[akai].tagged_argument.cl_type_name();
+      let checkpoint_col_i = checkpoint.header.columns.iter().position(|c| c.name == dev.all_kernel_args[akai].name && c.cl_type == cl_type)
+        .ok_or_else(|| format!("Checkpoint does not hold column {} as {}; have the kernels changed since it was written?", dev.all_kernel_args[akai].name, cl_type))?;
+      if Some(checkpoint.columns[checkpoint_col_i].len()) != dev.all_kernel_args[akai].tagged_argument.buffer_len() {
+        return Err(format!("Checkpoint holds {} values of column {} but its table has {}", checkpoint.columns[checkpoint_col_i].len(), dev.all_kernel_args[akai].name, dev.all_kernel_args[akai].tagged_argument.buffer_len().unwrap_or(0)).into());
+      }
+      dev.write_from_host(akai, &checkpoint.columns[checkpoint_col_i]).map_err(structs::eloc!(format!("column {}", dev.all_kernel_args[akai].name)))?;
+    }
+
+    if checkpoint.anim_point_history.len() == anim_point_history.len() {
+      anim_point_history.copy_from_slice(&checkpoint.anim_point_history);
+      anim_point_history_i = checkpoint.header.anim_point_history_i;
+    }
+    else if args.verbose > 0 {
+      eprintln!("[ Warning ] Checkpoint location history has {} points but {} are expected; starting with an empty history", checkpoint.anim_point_history.len(), anim_point_history.len());
+    }
+
+    for (table_name, population) in checkpoint.header.populations.iter() {
+      if !populations.contains_key(table_name) {
+        return Err(format!("Checkpoint holds a population for table '{}', which does not have max_entities set", table_name).into());
+      }
+      table_num_entities.insert(table_name.clone(), population.num_entities);
+      populations.insert(table_name.clone(), population.clone());
+    }
+
+    // Replay frame advances rather than storing a Time so the next frame lands on exactly the same timestamp
+    anim_frames_encoded = checkpoint.header.anim_frames_encoded;
+    for _ in 0..anim_frames_encoded {
+      anim_t_position = anim_t_position.aligned_with(anim_frame_duration).add();
+    }
+
+    start_step_i = checkpoint.header.step;
+    reduction_values = checkpoint.header.reduction_values.clone();
+    update_reduction_args(&reduction_arg_indicies, &reduction_values, &mut dev.all_kernel_args).map_err(structs::eloc!())?;
+  }
+
+  // An event-driven simulation advances sim_time to each batch of events instead of by dt; see src/apollon_events.cl
+  let mut sim_time: f64 = resume.map(|checkpoint| checkpoint.header.sim_time).unwrap_or(0.0);
+  let mut event_queue = match resume {
+    Some(checkpoint) => structs::EventQueue::from_events(&checkpoint.header.events, checkpoint.header.next_event_seq),
+    None => structs::EventQueue::default(),
+  };
+  if resume.is_none() {
+    for event_def in simcontrol.initial_events.iter() {
+      let event_type_id = simcontrol.event_type_id(&event_def.event_type).ok_or_else(|| format!("Unknown event type \"{}\"", &event_def.event_type))?;
+      event_queue.push(event_def.time, event_def.entity, event_type_id, event_def.payload);
+    }
+  }
 
 
-  for sim_step_i in 0..simcontrol.num_steps {
-    // For each kernel, read in sim_data, process that data, then transform back mutating sim_data itself.
+  // Evaluate each kernel's NDRange; re-evaluated whenever a compaction changes the number of live entities
+  let mut kernel_ndranges = evaluate_kernel_ndranges(simcontrol, cl_kernels, &table_num_entities, graph.map(|g| g.num_edges()), device).map_err(structs::eloc!())?;
+  if args.verbose > 0 {
     for i in 0..cl_kernels.len() {
-      if let Some(k) = &cl_kernels[i].cl_device_kernel {
+      eprintln!("Kernel {} NDRange = {:?}", cl_kernels[i].name, kernel_ndranges[i]);
+    }
+  }
 
+  // Reserved arguments (sim_step, sim_time, random_seed, ...) and scheduled constants are rewritten in place before every step
+  dynamic_args.extend((0..dev.all_kernel_args.len())
+    .filter(|akai| !dev.all_kernel_args[*akai].tagged_argument.is_buffer() && reserved_arg_value(simcontrol, &table_num_entities, &table_capacities, &dev.all_kernel_args[*akai].name, 0, 0.0).is_some())
+    .map(|akai| (akai, None)));
+  if resume.is_some() {
+    update_dynamic_args(simcontrol, &dynamic_args, &mut dev.all_kernel_args, start_step_i, sim_time, &table_num_entities, &table_capacities).map_err(structs::eloc!())?;
+  }
+
+  // Initialization kernels run once before the first step; a resumed simulation already holds their results.
+  if resume.is_none() {
+    if let Some(ref spatial_index) = spatial_index {
+      if cl_kernels.iter().any(|k| k.run_once_at_start) {
+        spatial_index.rebuild(&mut dev, table_num_entities[&spatial_index.table]).map_err(structs::eloc!())?;
+      }
+    }
+    for i in 0..cl_kernels.len() {
+      if cl_kernels[i].run_once_at_start {
         let kernel_exec_start = std::time::Instant::now();
+        let k = cl_kernels[i].cl_device_kernel.as_ref().ok_or_else(|| format!("Kernel {} does not have a cl_device_kernel!", cl_kernels[i].name))?;
+        enqueue_sim_kernel(k, i, &kernel_ndranges[i], &mut dev).map_err(structs::eloc!(cl_kernels[i].name.clone()))?;
+        let kernel_exec_end = std::time::Instant::now();
+        total_kernel_execs_duration += kernel_exec_end - kernel_exec_start;
+      }
+    }
+  }
 
-        // Allocate a runtime kernel & feed it inputs; we use RefCell here b/c otherwise inner-loop lifetimes would kill us
-        let mut exec_kernel = opencl3::kernel::ExecuteKernel::new(&k);
-
-        for aka_idx in all_kernel_arg_indicies[i].iter() {
-          let arg = &all_kernel_args[*aka_idx].clone();
-          unsafe {
-            match arg.tagged_argument.borrow() {
-              structs::CL_TaggedArgument::Uint8Buffer(a)  => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Uint16Buffer(a) => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Uint32Buffer(a) => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Uint64Buffer(a) => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Int8Buffer(a)   => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Int16Buffer(a)  => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Int32Buffer(a)  => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Int64Buffer(a)  => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::FloatBuffer(a)  => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::DoubleBuffer(a) => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Uint8(a)        => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Uint16(a)       => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Uint32(a)       => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Uint64(a)       => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Int8(a)         => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Int16(a)        => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Int32(a)        => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Int64(a)        => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Float(a)        => {let exec_kernel = exec_kernel.set_arg(a);},
-              structs::CL_TaggedArgument::Double(a)       => {let exec_kernel = exec_kernel.set_arg(a);},
-            }
-          }
+  for sim_step_i in start_step_i..simcontrol.num_steps {
+    // Each step of an event-driven simulation handles every event of the earliest remaining time
+    let event_batch = if simcontrol.mode == structs::SimulationMode::Event {
+      match event_queue.pop_batch(simcontrol.end_time) {
+        Some((batch_time, event_batch)) => {
+          sim_time = batch_time;
+          event_batch
         }
-
-        { // Set the global work size is the number of entitied being simulated
-          let exec_kernel = exec_kernel.set_global_work_size( sim_data.len() );
+        None => {
+          eprintln!("No events remain{} after step {} (t={}{})", if simcontrol.end_time.is_some() { " before end_time" } else { "" }, sim_step_i, sim_time, simcontrol.time_unit);
+          break;
         }
+      }
+    }
+    else {
+      sim_time = sim_step_i as f64 * simcontrol.dt;
+      vec![]
+    };
+
+    update_dynamic_args(simcontrol, &dynamic_args, &mut dev.all_kernel_args, sim_step_i, sim_time, &table_num_entities, &table_capacities).map_err(structs::eloc!())?;
+    for (akai, host_column) in global_buffer_resets.iter() {
+      dev.write_from_host(*akai, host_column).map_err(structs::eloc!())?;
+    }
+
+    // Sort the indexed table into grid cells using the positions left by the previous step
+    if let Some(ref spatial_index) = spatial_index {
+      let spatial_index_start = std::time::Instant::now();
+      spatial_index.rebuild(&mut dev, table_num_entities[&spatial_index.table]).map_err(structs::eloc!())?;
+      total_kernel_execs_duration += std::time::Instant::now() - spatial_index_start;
+    }
+
+    if simcontrol.mode == structs::SimulationMode::Event {
+      let kernel_exec_start = std::time::Instant::now();
+      let posted_events = dispatch_event_batch(simcontrol, cl_kernels, sim_step_i, sim_time, &event_batch, &table_num_entities, &mut dev).map_err(structs::eloc!())?;
+      for event in posted_events {
+        event_queue.push(event.time, event.entity, event.event_type, event.payload);
+      }
+      total_kernel_execs_duration += std::time::Instant::now() - kernel_exec_start;
+    }
+
+    // For each kernel scheduled at this step, run it over the device-resident column buffers; nothing is read back to the host here.
+    for i in 0..cl_kernels.len() {
+      if simcontrol.mode == structs::SimulationMode::Event || cl_kernels[i].on_event.is_some() || !cl_kernels[i].runs_at_step(sim_step_i) {
+        continue;
+      }
+      if let Some(k) = &cl_kernels[i].cl_device_kernel {
 
-        // Setup command queue
-        let mut kernel_event = unsafe { exec_kernel.enqueue_nd_range(&queue).map_err(structs::eloc!())? };
+        let kernel_exec_start = std::time::Instant::now();
 
-        // Safety: both vectors increase at same time
-        sim_events_cl.push(kernel_event.get());
-        sim_events.push(kernel_event);
+        enqueue_sim_kernel(k, i, &kernel_ndranges[i], &mut dev).map_err(structs::eloc!(cl_kernels[i].name.clone()))?;
 
         let kernel_exec_end = std::time::Instant::now();
         total_kernel_execs_duration += kernel_exec_end - kernel_exec_start;
@@ -290,13 +816,88 @@ async fn main_async(args: &structs::Args) -> Result<(), Box<dyn std::error::Erro
       }
       else {
         eprintln!("[ Fatal Error ] Kernel {} does not have a cl_device_kernel! Inspect hardware & s/w to ensure kernels compile when loaded.", cl_kernels[i].name);
-        return Ok(());
+        return Ok(None);
+      }
+    }
+
+    // Move the entities which left the world back into it, or mark them removed
+    if let Some(ref world_boundary) = world_boundary {
+      let world_boundary_start = std::time::Instant::now();
+      world_boundary.apply(&mut dev, table_num_entities[&world_boundary.table]).map_err(structs::eloc!())?;
+      total_kernel_execs_duration += std::time::Instant::now() - world_boundary_start;
+    }
+
+    // Apply entity removals & spawns; the reserved entity_count arguments follow at the next step
+    if !populations.is_empty() && simcontrol.compaction_step_period > 0 && sim_step_i % simcontrol.compaction_step_period == 0 {
+      let compaction_start = std::time::Instant::now();
+      let mut population_changed = false;
+      for (table_name, population) in populations.iter_mut() {
+        if compact_population(simcontrol, table_name, population, &mut dev).map_err(structs::eloc!(table_name.clone()))? {
+          table_num_entities.insert(table_name.clone(), population.num_entities);
+          population_changed = true;
+        }
+      }
+      if population_changed {
+        kernel_ndranges = evaluate_kernel_ndranges(simcontrol, cl_kernels, &table_num_entities, graph.map(|g| g.num_edges()), device).map_err(structs::eloc!())?;
+      }
+      let compaction_end = std::time::Instant::now();
+      total_convert_overhead_duration += compaction_end - compaction_start;
+    }
+
+    // Reduce columns to statistics; kernels binding them see the new values from the next step
+    let step_stats_start = std::time::Instant::now();
+    let mut step_stats: Vec<(String, f64)> = vec![];
+    for cl_reduction in cl_reductions.iter() {
+      if cl_reduction.def.every > 0 && sim_step_i % cl_reduction.def.every == 0 {
+        step_stats.extend(cl_reduction.evaluate(&mut dev, table_num_entities[&cl_reduction.table]).map_err(structs::eloc!())?);
+      }
+    }
+    if !step_stats.is_empty() {
+      if let Some(ref mut stats_writer) = stats_writer {
+        stats_writer.write_step(sim_step_i, sim_time, &step_stats).map_err(structs::eloc!())?;
       }
+      reduction_values.extend(step_stats);
+      update_reduction_args(&reduction_arg_indicies, &reduction_values, &mut dev.all_kernel_args).map_err(structs::eloc!())?;
     }
+    total_kernel_execs_duration += std::time::Instant::now() - step_stats_start;
 
     // Every N or so steps trim the events vector on the assumption some have completed
     if sim_step_i % 20 == 0 {
-      utils::trim_completed_events(&args, &mut sim_events, &mut sim_events_cl).map_err(structs::eloc!())?;
+      utils::trim_completed_events(args, &mut dev.sim_events, &mut dev.sim_events_cl).map_err(structs::eloc!())?;
+    }
+
+    // Append entity states to the trajectory file and/or the ensemble's trajectory statistics
+    if record_trajectory && sim_step_i % simcontrol.trajectory_step_period == 0 {
+      let kernel_to_ld_start = std::time::Instant::now();
+      let mut trajectory_host_columns: Vec<structs::HostColumn> = vec![];
+      for column in trajectory_columns.iter() {
+        if let Some(akai) = dev.canonical_idx(column) {
+          let host_column = dev.read_to_host(akai).map_err(structs::eloc!())?.ok_or("Logic error! Trajectory column is not a buffer")?;
+          trajectory_host_columns.push(host_column);
+        }
+        else if let Some(population) = populations.get(&simcontrol.table_name) {
+          if column == "entity_id" {
+            trajectory_host_columns.push(structs::HostColumn::from_integers("ulong", &population.entity_ids, population.num_entities).map_err(structs::eloc!())?);
+            continue;
+          }
+          // Static columns follow the live entities back to their T=0 records
+          trajectory_host_columns.push(trajectory_static_columns[column].gather(&population.rows, population.num_entities));
+        }
+        else {
+          trajectory_host_columns.push(trajectory_static_columns[column].clone());
+        }
+      }
+      let trajectory_host_column_refs: Vec<&structs::HostColumn> = trajectory_host_columns.iter().collect();
+      if let Some(ref mut trajectory_writer) = trajectory_writer {
+        trajectory_writer.write_step(sim_step_i, table_num_entities[&simcontrol.table_name], &trajectory_host_column_refs).map_err(structs::eloc!())?;
+      }
+      if let Some(ref mut ensemble) = ensemble {
+        if ensemble.trajectory_stats {
+          ensemble.add_trajectory_step(sim_step_i, num_entities, &trajectory_columns, &trajectory_host_column_refs);
+        }
+      }
+      let kernel_to_ld_end = std::time::Instant::now();
+      total_convert_overhead_duration += kernel_to_ld_end - kernel_to_ld_start;
     }
 
     // Finally possibly render a frame of data to gif_plot
@@ -304,7 +905,16 @@ async fn main_async(args: &structs::Args) -> Result<(), Box<dyn std::error::Erro
       if let Some(ref mut encoder) = encoder {
 
         let kernel_to_ld_start = std::time::Instant::now();
-        utils::kernel_data_update_ld_data_named(&args, &context, &queue, &sim_events_cl, &all_kernel_args, &mut sim_data).map_err(structs::eloc!())?;
+        // Only the coordinate columns are needed to render; columns no kernel binds keep their t0 values
+        for render_table in render_tables.iter() {
+          for gis_column in [&render_table.x_column, &render_table.y_column] {
+            if let Some(akai) = dev.canonical_idx(gis_column) {
+              if let Some(host_column) = dev.read_to_host(akai).map_err(structs::eloc!())? {
+                host_columns.insert(gis_column.to_string(), host_column);
+              }
+            }
+          }
+        }
         let kernel_to_ld_end = std::time::Instant::now();
         total_convert_overhead_duration += kernel_to_ld_end - kernel_to_ld_start;
 
@@ -317,6 +927,15 @@ async fn main_async(args: &structs::Args) -> Result<(), Box<dyn std::error::Erro
 
         write_frame_to_dt(&sim_bg_argb_frame, udt_width, udt_height, &udt);
 
+        for field in render_fields.iter() {
+          let buffer_name = structs::apollon_buffer_name(&field.name);
+          if let Some(akai) = (0..dev.all_kernel_args.len()).find(|akai| dev.all_kernel_args[*akai].name == buffer_name) {
+            if let Some(cells) = dev.all_kernel_args[akai].tagged_argument.read_to_host(&dev.queue, &dev.sim_events_cl).map_err(structs::eloc!())? {
+              write_field_to_dt(field, &field.world_bounds(simcontrol), &cells, udt_width, udt_height, &udt);
+            }
+          }
+        }
+
         // Render entity histories as small dots in parallel
         //let mut join_set = tokio::task::JoinSet::new();
         tokio_scoped::scope(|scope| {
@@ -325,39 +944,66 @@ async fn main_async(args: &structs::Args) -> Result<(), Box<dyn std::error::Erro
           }
         });
 
-        // For each entity, if an gis_x_attr_name and gis_y_attr_name coordinate are known and are numeric,
+        if let (Some(graph), Some(ref edge_color)) = (graph, &graph_edge_color) {
+          let graph_render_table = render_tables.iter().find(|rt| rt.table_name == graph.table);
+          if let Some((Some(x_column), Some(y_column))) = graph_render_table.map(|rt| (host_columns.get(&rt.x_column), host_columns.get(&rt.y_column))) {
+            let mut edge_path = raqote::PathBuilder::new();
+            for (e, source) in graph_edge_sources.iter().enumerate() {
+              let (source, target) = (*source as usize, graph.col_indices[e] as usize);
+              let (x0, y0, x1, y1) = (x_column.get_f32(source), y_column.get_f32(source), x_column.get_f32(target), y_column.get_f32(target));
+              if x0.is_finite() && y0.is_finite() && x1.is_finite() && y1.is_finite() {
+                edge_path.move_to(x0, y0);
+                edge_path.line_to(x1, y1);
+              }
+            }
+            plotter_dt.stroke(&edge_path.finish(), edge_color, &raqote::StrokeStyle { width: 1.0, ..Default::default() }, &plotter_dt_default_drawops);
+          }
+        }
+
+        // For each entity of each table, if an gis_x_attr_name and gis_y_attr_name coordinate are known and are numeric,
         // render a dot with a label from gis_name_attr
-        for row_i in 0..sim_data.len() {
-          if let (Some(x_val), Some(y_val)) = (sim_data[row_i].get(&simcontrol.gis_x_attr_name), sim_data[row_i].get(&simcontrol.gis_y_attr_name)) {
-            if let (Ok(x_f32), Ok(y_f32)) = (x_val.to_f32(), y_val.to_f32()) {
-              // Render!
-              plotter_dt.fill_rect(
-                x_f32-1.0f32, y_f32-1.0f32,
-                3.0f32, 3.0f32,
-                &sim_data_colors[row_i],
-                &plotter_dt_default_drawops
-              );
-
-              // Write text at same y but x+8px to right
-              if row_i < simcontrol.max_entity_idx_to_name {
-                let mut label_s = sim_data[row_i].get(&simcontrol.gis_name_attr).map(|v| v.to_string()).unwrap_or_else(|| format!("{}", row_i));
-                plotter_dt.draw_text(
-                  &plotter_dt_font,
-                  15.0,
-                  &label_s,
-                  raqote::Point::new(x_f32 + 8.0f32, y_f32),
-                  &plotter_dt_solid_black,
+        for render_table in render_tables.iter() {
+          if let (Some(x_column), Some(y_column)) = (host_columns.get(&render_table.x_column), host_columns.get(&render_table.y_column)) {
+            let population = populations.get(&render_table.table_name);
+            for row_i in 0..std::cmp::min(table_num_entities[&render_table.table_name], std::cmp::min(x_column.len(), y_column.len())) {
+              let (x_f32, y_f32) = (x_column.get_f32(row_i), y_column.get_f32(row_i));
+              if x_f32.is_finite() && y_f32.is_finite() {
+                // Host-only attributes come from the entity's T=0 record; spawned entities are black & labeled by entity_id
+                let t0_row_i = match population {
+                  Some(population) => population.rows[row_i],
+                  None => Some(row_i),
+                };
+
+                // Render!
+                plotter_dt.fill_rect(
+                  x_f32-1.0f32, y_f32-1.0f32,
+                  3.0f32, 3.0f32,
+                  t0_row_i.map(|r| &render_table.colors[r]).unwrap_or(&plotter_dt_solid_black),
                   &plotter_dt_default_drawops
                 );
-              }
 
-              // Safety; anim_point_history_i begins at 0 and we never allow it to be >= .len() below
-              unsafe { *(anim_point_history.get_unchecked_mut(anim_point_history_i)) = (x_f32, y_f32); }
-              anim_point_history_i += 1;
-              if anim_point_history_i >= anim_point_history.len() {
-                anim_point_history_i = 0;
-              }
+                // Write text at same y but x+8px to right
+                if row_i < simcontrol.max_entity_idx_to_name {
+                  let entity_label = population.map(|p| p.entity_ids[row_i] as usize).unwrap_or(row_i);
+                  let mut label_s = t0_row_i.and_then(|r| render_table.ld_data[r].get(&render_table.gis_name_attr)).map(|v| v.to_string()).unwrap_or_else(|| format!("{}", entity_label));
+                  plotter_dt.draw_text(
+                    &plotter_dt_font,
+                    15.0,
+                    &label_s,
+                    raqote::Point::new(x_f32 + 8.0f32, y_f32),
+                    &plotter_dt_solid_black,
+                    &plotter_dt_default_drawops
+                  );
+                }
+
+                // Safety; anim_point_history_i begins at 0 and we never allow it to be >= .len() below
+                unsafe { *(anim_point_history.get_unchecked_mut(anim_point_history_i)) = (x_f32, y_f32); }
+                anim_point_history_i += 1;
+                if anim_point_history_i >= anim_point_history.len() {
+                  anim_point_history_i = 0;
+                }
 
+              }
             }
           }
         }
@@ -390,31 +1036,91 @@ async fn main_async(args: &structs::Args) -> Result<(), Box<dyn std::error::Erro
         encoder.encode(&ndarr_data, anim_t_position).map_err(structs::eloc!())?;
 
         anim_t_position = anim_t_position.aligned_with(anim_frame_duration).add();
+        anim_frames_encoded += 1;
 
         let render_end = std::time::Instant::now();
         total_gis_paint_duration += render_end- render_start;
       }
     }
 
+    // End the simulation after this step if any stop condition holds
+    if !stop_conditions.is_empty() && simcontrol.stop_condition_step_period > 0 && sim_step_i % simcontrol.stop_condition_step_period == 0 {
+      let stop_check_start = std::time::Instant::now();
+      for stop_condition in stop_conditions.iter_mut() {
+        let condition_num_entities = table_num_entities[stop_condition.condition.table.as_deref().unwrap_or(simcontrol.table_name.as_str())];
+        if stop_condition.evaluate(&mut dev, condition_num_entities).map_err(structs::eloc!())? {
+          stopped_by = Some((sim_step_i, stop_condition.condition.expression.clone()));
+          break;
+        }
+      }
+      let stop_check_end = std::time::Instant::now();
+      total_kernel_execs_duration += stop_check_end - stop_check_start;
+      if stopped_by.is_some() {
+        break;
+      }
+    }
+
+    // Persist everything needed to continue from the next step; every type of every column is synced first so a resumed run needs no conversions.
+    let next_step_i = sim_step_i + 1;
+    if checkpoint_enabled && next_step_i % simcontrol.checkpoint_step_period == 0 && next_step_i < simcontrol.num_steps {
+      let checkpoint_start = std::time::Instant::now();
+      let mut checkpoint_columns: Vec<structs::CheckpointColumn> = vec![];
+      let mut checkpoint_host_columns: Vec<structs::HostColumn> = vec![];
+      for akai in 0..dev.all_kernel_args.len() {
+        if dev.all_kernel_args[akai].tagged_argument.is_buffer() {
+          let host_column = dev.read_to_host(akai).map_err(structs::eloc!())?.ok_or("Logic error! Checkpoint column is not a buffer")?;
+          checkpoint_columns.push(structs::CheckpointColumn {
+            name: dev.all_kernel_args[akai].name.clone(),
+            cl_type: host_column.cl_type_name().to_string(),
+            len: host_column.len(),
+          });
+          checkpoint_host_columns.push(host_column);
+        }
+      }
+      let checkpoint_header = structs::CheckpointHeader {
+        format: "apollon-checkpoint-1".to_string(),
+        step: next_step_i,
+        num_entities,
+        simcontrol: simcontrol.clone(),
+        columns: checkpoint_columns,
+        anim_frames_encoded,
+        anim_point_history_i,
+        anim_point_history_len: anim_point_history.len(),
+        populations: populations.clone(),
+        events: event_queue.events(),
+        next_event_seq: event_queue.next_seq,
+        sim_time,
+        reduction_values: reduction_values.clone(),
+        trajectory_file_offset: match trajectory_writer { Some(ref mut w) => Some(w.flushed_len().map_err(structs::eloc!())?), None => None },
+        stats_file_offset: match stats_writer { Some(ref mut w) => Some(w.flushed_len().map_err(structs::eloc!())?), None => None },
+      };
+      utils::write_checkpoint(&simcontrol.checkpoint_file_path, &checkpoint_header, &checkpoint_host_columns, &anim_point_history).map_err(structs::eloc!())?;
+      let checkpoint_end = std::time::Instant::now();
+      total_convert_overhead_duration += checkpoint_end - checkpoint_start;
+      if args.verbose > 0 {
+        eprintln!("Wrote checkpoint for step {} to {} in {}", next_step_i, simcontrol.checkpoint_file_path.display(), utils::duration_to_display_str(&(checkpoint_end - checkpoint_start)));
+      }
+    }
+
   }
 
 
-  if sim_events.len() > 0 {
+  if !dev.sim_events.is_empty() {
     loop {
       if args.verbose > 0 {
-        eprintln!("Waiting for {} events to complete...", sim_events.len());
+        eprintln!("Waiting for {} events to complete...", dev.sim_events.len());
       }
 
       for wait_i in 0..40 {
         tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
 
-        utils::trim_completed_events(&args, &mut sim_events, &mut sim_events_cl).map_err(structs::eloc!())?;
+        utils::trim_completed_events(args, &mut dev.sim_events, &mut dev.sim_events_cl).map_err(structs::eloc!())?;
 
-        if sim_events.len() < 1 {
+        if dev.sim_events.is_empty() {
           break;
         }
       }
-      if sim_events.len() < 1 {
+      if dev.sim_events.is_empty() {
         eprintln!("All sim events complete!");
         break;
       }
@@ -425,37 +1131,474 @@ async fn main_async(args: &structs::Args) -> Result<(), Box<dyn std::error::Erro
   if let Some(ref mut encoder) = encoder {
     encoder.finish().map_err(structs::eloc!())?;
   }
+  if let Some(ref mut trajectory_writer) = trajectory_writer {
+    trajectory_writer.finish().map_err(structs::eloc!())?;
+  }
+  if let Some(ref mut stats_writer) = stats_writer {
+    stats_writer.finish().map_err(structs::eloc!())?;
+  }
 
 
   let simulation_end = std::time::Instant::now();
+  if let Some((stop_step_i, ref expression)) = stopped_by {
+    let stop_time = if simcontrol.mode == structs::SimulationMode::Event { sim_time } else { (stop_step_i + 1) as f64 * simcontrol.dt };
+    eprintln!("Stop condition \"{}\" fired at step {} (t={}{} after the step)", expression, stop_step_i, stop_time, simcontrol.time_unit);
+  }
   eprintln!("Simulation Time: {}", utils::duration_to_display_str(&(simulation_end - simulation_start)));
 
   eprintln!("Simulation Time Kernel Exec: {}", utils::duration_to_display_str(&total_kernel_execs_duration));
   eprintln!("Simulation Time Convert Overhead: {}", utils::duration_to_display_str(&total_convert_overhead_duration));
   eprintln!("Simulation Time Paint: {}", utils::duration_to_display_str(&total_gis_paint_duration));
 
-  // Write to simcontrol.output_data_file_path
-  utils::write_ld_file(args, &sim_data, &simcontrol.output_data_file_path).await.map_err(structs::eloc!())?;
+  // Read back every column a kernel may have written & write each table to its output file
+  let kernel_to_ld_start = std::time::Instant::now();
+  let mut written_columns: Vec<String> = dev.all_kernel_args.iter().filter(|a| a.tagged_argument.is_writable_buffer() && !structs::is_apollon_buffer_name(&a.name)).map(|a| a.name.clone()).collect();
+  written_columns.sort();
+  written_columns.dedup();
+  // (table name, final data, columns kernels wrote, schema to write it with) of each table to output
+  let mut tables_sim_data = vec![];
+  for table_name in simcontrol.table_names() {
+    // An ensemble only reduces the primary table
+    if ensemble.is_some() && table_name != simcontrol.table_name {
+      continue;
+    }
+    // Tables with a dynamic population hold their live entities, which keep the attributes of their T=0 records
+    let mut sim_data = match populations.get(table_name) {
+      Some(population) => population.rows.iter().map(|r| r.map(|r| tables[table_name][r].clone()).unwrap_or_default()).collect(),
+      None => tables[table_name].clone(),
+    };
+    let mut written_column_names: Vec<String> = vec![];
+    for column in written_columns.iter() {
+      let (column_table, column_name) = simcontrol.split_qualified_column_name(column);
+      if column_table != table_name {
+        continue;
+      }
+      if let Some(akai) = dev.canonical_idx(column) {
+        if let Some(host_column) = dev.read_to_host(akai).map_err(structs::eloc!())? {
+          host_column.update_ld_column(&mut sim_data, column_name);
+        }
+      }
+      written_column_names.push(column_name.to_string());
+    }
+    let mut table_schema = simcontrol.table_schema(table_name).clone();
+    if let Some(population) = populations.get(table_name) {
+      for (record, entity_id) in sim_data.iter_mut().zip(population.entity_ids.iter()) {
+        record.insert("entity_id".to_string(), structs::Value::Integer(*entity_id as i64));
+      }
+      // Spawned entities have no T=0 record, so columns no kernel writes are left to their schema default or empty
+      for (column, column_schema) in table_schema.iter_mut() {
+        if !written_column_names.contains(column) {
+          column_schema.nullable = true;
+        }
+      }
+    }
+    tables_sim_data.push((table_name, sim_data, written_column_names, table_schema));
+  }
+  let kernel_to_ld_end = std::time::Instant::now();
+  if args.verbose > 0 {
+    eprintln!("Output Readback Time: {}", utils::duration_to_display_str(&(kernel_to_ld_end - kernel_to_ld_start)));
+  }
+
+  for (table_name, sim_data, written_column_names, table_schema) in tables_sim_data.iter() {
+    if *table_name == simcontrol.table_name {
+      match ensemble {
+        Some(ref mut ensemble) => {
+          ensemble.add_final(sim_data, written_column_names);
+        }
+        None => {
+          utils::write_ld_file(args, sim_data, table_schema, &simcontrol.output_data_file_path).await.map_err(structs::eloc!())?;
+        }
+      }
+    }
+    else if let Some(entity_table) = simcontrol.tables.iter().find(|t| t.name == *table_name) {
+      utils::write_ld_file(args, sim_data, table_schema, &entity_table.output).await.map_err(structs::eloc!(format!("table {}", table_name)))?;
+    }
+  }
+
+  // Global buffers are written as one (name, index, value) record per element
+  let mut global_buffers_data: utils::ListedData = vec![];
+  for global_buffer in global_buffers.iter() {
+    let buffer_name = structs::apollon_buffer_name(&global_buffer.name);
+    if let Some(akai) = (0..dev.all_kernel_args.len()).find(|akai| dev.all_kernel_args[*akai].name == buffer_name) {
+      let host_column = dev.all_kernel_args[akai].tagged_argument.read_to_host(&dev.queue, &dev.sim_events_cl).map_err(structs::eloc!())?.ok_or("Logic error! Global buffer is not a buffer")?;
+      let values: Vec<structs::Value> = (0..host_column.len()).map(|i| host_column.get_value(i)).collect();
+      if args.verbose > 0 || host_column.len() <= 8 {
+        eprintln!("Global buffer {} = [{}]", &global_buffer.name, values.iter().take(8).map(|v| v.to_string()).collect::<Vec<String>>().join(", "));
+      }
+      for (i, value) in values.into_iter().enumerate() {
+        let mut record: std::collections::HashMap<String, structs::Value> = std::collections::HashMap::new();
+        record.insert("name".to_string(), structs::Value::String(global_buffer.name.clone()));
+        record.insert("index".to_string(), structs::Value::Integer(i as i64));
+        record.insert("value".to_string(), value);
+        global_buffers_data.push(record);
+      }
+    }
+  }
+  let global_buffers_file_path_s = simcontrol.global_buffers_file_path.to_string_lossy();
+  if !global_buffers_data.is_empty() && global_buffers_file_path_s != "/dev/null" && global_buffers_file_path_s != "NUL" {
+    utils::write_ld_file(args, &global_buffers_data, &std::collections::HashMap::new(), &simcontrol.global_buffers_file_path).await.map_err(structs::eloc!())?;
+  }
 
   // Write to simcontrol.output_animation_file_path
 
 
-  let total_end = std::time::Instant::now();
-  eprintln!("Total Time: {}", utils::duration_to_display_str(&(total_end - total_start)));
+  Ok(stopped_by.map(|(stop_step_i, _expression)| stop_step_i))
+}
 
-  if let Some(cmd_txt) = &args.post_sim_cmd {
-    tokio::process::Command::new("sh")
-      .arg("-c")
-      .arg(&cmd_txt)
-      .spawn()?
-      .wait().await?;
+/// SimControl::reserved_arg_value for an dev.all_kernel_args name, which for entity_count & entity_capacity is qualified by the table it counts.
+fn reserved_arg_value(
+    simcontrol: &structs::SimControl,
+    table_num_entities: &std::collections::HashMap<String, usize>,
+    table_capacities: &std::collections::HashMap<String, usize>,
+    name: &str,
+    sim_step_i: u64,
+    sim_time: f64,
+  ) -> Option<structs::Value>
+{
+  let (table, arg_name) = simcontrol.split_qualified_column_name(name);
+  simcontrol.reserved_arg_value(arg_name, sim_step_i, sim_time, table_num_entities.get(table).copied().unwrap_or(0), table_capacities.get(table).copied().unwrap_or(0))
+}
+
+/// Sets every argument in dynamic_args to its value at the start of sim_step_i (which begins at sim_time), keeping the CL type the kernel declared;
+/// scheduled constants take the value of their schedule and the other arguments are reserved.
+fn update_dynamic_args(
+    simcontrol: &structs::SimControl,
+    dynamic_args: &[(usize, Option<structs::DataConstantSchedule>)],
+    all_kernel_args: &mut [structs::CL_NamedTaggedArgument],
+    sim_step_i: u64,
+    sim_time: f64,
+    table_num_entities: &std::collections::HashMap<String, usize>,
+    table_capacities: &std::collections::HashMap<String, usize>,
+  ) -> Result<(), Box<dyn std::error::Error>>
+{
+  for (akai, schedule) in dynamic_args.iter() {
+    let val = match schedule {
+      Some(schedule) => schedule.value_at_step(sim_step_i),
+      None => reserved_arg_value(simcontrol, table_num_entities, table_capacities, &all_kernel_args[*akai].name, sim_step_i, sim_time).ok_or("Logic error! Argument is not reserved")?,
+    };
+    let tagged_argument = std::sync::Arc::<structs::CL_TaggedArgument>::get_mut(&mut all_kernel_args[*akai].tagged_argument).ok_or("Logic error! all_kernel_args tagged_argument is not mutable")?;
+    *tagged_argument = structs::CL_TaggedArgument::from_value(&val, tagged_argument.cl_type_name());
+  }
+  Ok(())
+}
+
+/// Sets every scalar argument in reduction_arg_indicies to the latest value of the statistic it is named after, keeping its CL type.
+fn update_reduction_args(
+    reduction_arg_indicies: &[usize],
+    reduction_values: &std::collections::HashMap<String, f64>,
+    all_kernel_args: &mut [structs::CL_NamedTaggedArgument],
+  ) -> Result<(), Box<dyn std::error::Error>>
+{
+  for akai in reduction_arg_indicies.iter() {
+    if let Some(value) = reduction_values.get(&all_kernel_args[*akai].name) {
+      let tagged_argument = std::sync::Arc::<structs::CL_TaggedArgument>::get_mut(&mut all_kernel_args[*akai].tagged_argument).ok_or("Logic error! all_kernel_args tagged_argument is not mutable")?;
+      *tagged_argument = structs::CL_TaggedArgument::from_value(&structs::Value::Double(*value), tagged_argument.cl_type_name());
+    }
+  }
+  Ok(())
+}
+
+/// Evaluates the NDRange of every kernel; expressions may use the live entity count of the kernel's table ("entities"),
+/// of any table ("<table>_entities"), the number of graph edges ("edges") & integer data constants.
+fn evaluate_kernel_ndranges(
+    simcontrol: &structs::SimControl,
+    cl_kernels: &[structs::CL_Kernel],
+    table_num_entities: &std::collections::HashMap<String, usize>,
+    num_edges: Option<usize>,
+    device: &opencl3::device::Device,
+  ) -> Result<Vec<structs::CL_NDRange>, Box<dyn std::error::Error>>
+{
+  let mut kernel_ndranges: Vec<structs::CL_NDRange> = vec![];
+  for cl_kernel in cl_kernels.iter() {
+    let kernel_num_entities = *table_num_entities.get(cl_kernel.table_name(simcontrol)).ok_or_else(|| format!("Kernel {} runs over table '{}', which is not declared", cl_kernel.name, cl_kernel.table_name(simcontrol)))?;
+    let work_size_lookup = |name: &str| -> Option<u64> {
+      match name {
+        "entities" | "entity_count" => Some(kernel_num_entities as u64),
+        "edges" | "edge_count" if num_edges.is_some() => num_edges.map(|n| n as u64),
+        _ => match name.strip_suffix("_entities").and_then(|table| table_num_entities.get(table)) {
+          Some(table_len) => Some(*table_len as u64),
+          None => match simcontrol.fields.iter().find_map(|field| field.scalar_value(simcontrol, name)) {
+            Some(structs::Value::Integer(cells)) => Some(cells as u64),
+            _ => simcontrol.data_constants.get(name).and_then(|def| def.value_at_step(0)).and_then(|v| v.to_f64().ok()).map(|f| f as u64),
+          }
+        }
+      }
+    };
+    kernel_ndranges.push(cl_kernel.ndrange(&work_size_lookup, kernel_num_entities, device)?);
+  }
+  Ok(kernel_ndranges)
+}
+
+/// Applies the removals & spawns kernels made since the last compaction to table_name's population: surviving entities move
+/// to the front of every column buffer of the table in their existing order, followed by the spawned ones, which receive new entity_ids.
+/// Returns true if the number of live entities changed.
+fn compact_population(
+    simcontrol: &structs::SimControl,
+    table_name: &str,
+    population: &mut structs::TablePopulation,
+    dev: &mut structs::CL_DeviceState,
+  ) -> Result<bool, Box<dyn std::error::Error>>
+{
+  let structs::CL_DeviceState { all_kernel_args, column_variants, queue, sim_events, sim_events_cl, .. } = dev;
+  let num_entities_before = population.num_entities;
+
+  // Collect & reset the spawn requests
+  let spawn_count_name = simcontrol.qualified_column_name(table_name, "spawn_count");
+  let mut num_spawned: usize = 0;
+  for argument in all_kernel_args.iter_mut() {
+    if argument.name == spawn_count_name && argument.tagged_argument.is_buffer() {
+      let host_column = argument.tagged_argument.read_to_host(queue, sim_events_cl)?.ok_or("Logic error! spawn_count is not a buffer")?;
+      if let structs::Value::Integer(n) = host_column.get_value(0) {
+        num_spawned += n as usize;
+      }
+      let tagged_argument = std::sync::Arc::<structs::CL_TaggedArgument>::get_mut(&mut argument.tagged_argument).ok_or("Logic error! all_kernel_args tagged_argument is not mutable")?;
+      tagged_argument.write_from_host(queue, &structs::HostColumn::Uint32(vec![0]))?;
+    }
+  }
+  if population.num_entities + num_spawned > population.capacity {
+    eprintln!("[ Warning ] Table {} is full; {} of {} spawned entities were dropped (max_entities = {})",
+      table_name, population.num_entities + num_spawned - population.capacity, num_spawned, population.capacity);
+  }
+
+  // Every buffer of the table is synced before any is reordered, so no conversion reads an already-reordered buffer
+  let table_akais: Vec<usize> = (0..all_kernel_args.len())
+    .filter(|akai| all_kernel_args[*akai].tagged_argument.is_buffer() && all_kernel_args[*akai].name != spawn_count_name && !structs::is_apollon_buffer_name(&all_kernel_args[*akai].name))
+    .filter(|akai| simcontrol.split_qualified_column_name(&all_kernel_args[*akai].name).0 == table_name)
+    .collect();
+  for akai in table_akais.iter() {
+    column_variants.sync(*akai, all_kernel_args, queue, sim_events, sim_events_cl)?;
+  }
+
+  let alive_column = match column_variants.canonical_idx(all_kernel_args, &simcontrol.qualified_column_name(table_name, "alive")) {
+    Some(akai) => all_kernel_args[akai].tagged_argument.read_to_host(queue, sim_events_cl)?,
+    None => None,
+  };
+  let total_before = std::cmp::min(population.num_entities + num_spawned, population.capacity);
+  // Spawned entities are alive until a kernel removes them after this compaction
+  let keep = population.compact(num_spawned, |i| i >= num_entities_before || alive_column.as_ref().map(|c| c.get_f32(i) != 0.0).unwrap_or(true));
+  let reordered = keep.len() != total_before;
+  let keep: Vec<Option<usize>> = keep.into_iter().map(Some).collect();
+
+  for akai in table_akais.iter() {
+    let column_name = simcontrol.split_qualified_column_name(&all_kernel_args[*akai].name).1;
+    let is_managed = column_name == "entity_id" || column_name == "alive";
+    if !reordered && !is_managed {
+      continue;
+    }
+    let host_column = all_kernel_args[*akai].tagged_argument.read_to_host(queue, sim_events_cl)?.ok_or("Logic error! Population column is not a buffer")?;
+    let compacted = match column_name {
+      "entity_id" => structs::HostColumn::from_integers(host_column.cl_type_name(), &population.entity_ids, host_column.len())?,
+      "alive"     => structs::HostColumn::from_integers(host_column.cl_type_name(), &vec![1; population.num_entities], host_column.len())?,
+      _           => host_column.gather(&keep, host_column.len()),
+    };
+    let tagged_argument = std::sync::Arc::<structs::CL_TaggedArgument>::get_mut(&mut all_kernel_args[*akai].tagged_argument).ok_or("Logic error! all_kernel_args tagged_argument is not mutable")?;
+    tagged_argument.write_from_host(queue, &compacted)?;
+    // Every type of the column is compacted alike, but vector buffers packing it must be re-packed
+    column_variants.mark_packed_stale(&all_kernel_args[*akai].name, None, all_kernel_args);
+  }
+
+  Ok(population.num_entities != num_entities_before)
+}
+
+/// Runs the on_event kernels of one batch of events at sim_time in cl-kernels file order; each runs over the batch's events of
+/// its event type, event_buffer_capacity at a time. Returns the events the kernels posted, whose seq is assigned once they are pushed to the event queue.
+fn dispatch_event_batch(
+    simcontrol: &structs::SimControl,
+    cl_kernels: &[structs::CL_Kernel],
+    sim_step_i: u64,
+    sim_time: f64,
+    event_batch: &[structs::ScheduledEvent],
+    table_num_entities: &std::collections::HashMap<String, usize>,
+    dev: &mut structs::CL_DeviceState,
+  ) -> Result<Vec<structs::ScheduledEvent>, Box<dyn std::error::Error>>
+{
+  let event_akai = |all_kernel_args: &[structs::CL_NamedTaggedArgument], name: &str| -> Option<usize> {
+    let buffer_name = structs::apollon_buffer_name(name);
+    all_kernel_args.iter().position(|a| a.name == buffer_name)
+  };
+
+  for (i, cl_kernel) in cl_kernels.iter().enumerate() {
+    let event_type_id = match cl_kernel.on_event.as_ref().and_then(|on_event| simcontrol.event_type_id(on_event)) {
+      Some(event_type_id) => event_type_id,
+      None => continue,
+    };
+    if !cl_kernel.runs_at_step(sim_step_i) {
+      continue;
+    }
+    let k = cl_kernel.cl_device_kernel.as_ref().ok_or_else(|| format!("Kernel {} does not have a cl_device_kernel!", cl_kernel.name))?;
+
+    // Events naming entities outside the kernel's table would index past the end of its columns
+    let num_entities = table_num_entities.get(cl_kernel.table_name(simcontrol)).copied().unwrap_or(0);
+    let kernel_events: Vec<&structs::ScheduledEvent> = event_batch.iter().filter(|e| e.event_type == event_type_id).collect();
+    let num_dropped = kernel_events.iter().filter(|e| e.entity as usize >= num_entities).count();
+    if num_dropped > 0 {
+      eprintln!("[ Warning ] {} {} events at t={} name entities beyond the {} entities of table {} and were dropped",
+        num_dropped, &simcontrol.event_types[event_type_id as usize], sim_time, num_entities, cl_kernel.table_name(simcontrol));
+    }
+    let kernel_events: Vec<&structs::ScheduledEvent> = kernel_events.into_iter().filter(|e| (e.entity as usize) < num_entities).collect();
+
+    for chunk in kernel_events.chunks(std::cmp::max(1, simcontrol.event_buffer_capacity)) {
+      let chunk_columns = [
+        ("event_entity",  structs::HostColumn::Uint32(chunk.iter().map(|e| e.entity).collect())),
+        ("event_payload", structs::HostColumn::Float(chunk.iter().map(|e| e.payload).collect())),
+      ];
+      for (name, host_column) in chunk_columns.iter() {
+        if let Some(akai) = event_akai(&dev.all_kernel_args, name) {
+          dev.write_from_host(akai, host_column)?;
+        }
+      }
+      let ndrange = structs::CL_NDRange { global: vec![chunk.len()], local: None, offset: None };
+      enqueue_sim_kernel(k, i, &ndrange, dev).map_err(structs::eloc!(cl_kernel.name.clone()))?;
+    }
+  }
+
+  // Collect the posted events & reset the count for the next batch
+  let count_akai = match event_akai(&dev.all_kernel_args, "post_event_count") {
+    Some(akai) => akai,
+    None => return Ok(vec![]),
+  };
+  let num_posted = match dev.all_kernel_args[count_akai].tagged_argument.read_to_host(&dev.queue, &dev.sim_events_cl)? {
+    Some(host_column) if !host_column.is_empty() => host_column.get_value(0).to_f64()? as usize,
+    _ => 0,
+  };
+  if num_posted < 1 {
+    return Ok(vec![]);
+  }
+  if num_posted > simcontrol.event_buffer_capacity {
+    eprintln!("[ Warning ] Kernels posted {} events at t={} but event_buffer_capacity is {}; {} were dropped",
+      num_posted, sim_time, simcontrol.event_buffer_capacity, num_posted - simcontrol.event_buffer_capacity);
+  }
+  let num_posted = std::cmp::min(num_posted, simcontrol.event_buffer_capacity);
+  let mut posted_columns: Vec<Option<structs::HostColumn>> = vec![];
+  for name in ["post_event_delay", "post_event_entity", "post_event_type", "post_event_payload"] {
+    posted_columns.push(match event_akai(&dev.all_kernel_args, name) {
+      Some(akai) => dev.all_kernel_args[akai].tagged_argument.read_to_host(&dev.queue, &dev.sim_events_cl)?,
+      None => None,
+    });
+  }
+  let posted_value = |column_i: usize, event_i: usize| -> f64 {
+    posted_columns[column_i].as_ref().map(|c| c.get_f32(event_i) as f64).unwrap_or(0.0)
+  };
+  let mut posted_events: Vec<structs::ScheduledEvent> = vec![];
+  for event_i in 0..num_posted {
+    let delay = posted_value(0, event_i).max(0.0);
+    let event_type = posted_columns[2].as_ref().map(|c| c.get_value(event_i)).and_then(|v| v.to_f64().ok()).unwrap_or(0.0) as u32;
+    let entity = posted_columns[1].as_ref().map(|c| c.get_value(event_i)).and_then(|v| v.to_f64().ok()).unwrap_or(0.0) as u32;
+    if event_type as usize >= simcontrol.event_types.len() {
+      eprintln!("[ Warning ] A kernel posted unknown event type {} at t={}; it was dropped", event_type, sim_time);
+      continue;
+    }
+    posted_events.push(structs::ScheduledEvent {
+      time: sim_time + delay,
+      seq: 0,
+      entity,
+      event_type,
+      payload: posted_value(3, event_i) as f32,
+    });
+  }
+  dev.write_from_host(count_akai, &structs::HostColumn::Uint32(vec![0]))?;
+
+  Ok(posted_events)
+}
+
+/// Enqueues kernel kernel_i of the cl-kernels file over ndrange, first bringing any stale column buffers it uses up to date.
+fn enqueue_sim_kernel(
+    k: &opencl3::kernel::Kernel,
+    kernel_i: usize,
+    ndrange: &structs::CL_NDRange,
+    dev: &mut structs::CL_DeviceState,
+  ) -> Result<(), Box<dyn std::error::Error>>
+{
+  // OpenCL refuses empty NDRanges, and there is nothing to do anyway
+  if ndrange.num_work_items() < 1 {
+    return Ok(());
+  }
+
+  // Allocate a runtime kernel & feed it inputs
+  let mut exec_kernel = opencl3::kernel::ExecuteKernel::new(k);
+
+  // Bring any stale column buffers this kernel uses up to date from whichever type of that column was written last
+  let arg_indicies = dev.kernel_arg_indicies[kernel_i].clone();
+  for aka_idx in arg_indicies.iter() {
+    dev.sync(*aka_idx).map_err(structs::eloc!())?;
+  }
+
+  for aka_idx in arg_indicies.iter() {
+    unsafe {
+      dev.all_kernel_args[*aka_idx].tagged_argument.set_as_arg(&mut exec_kernel);
+    }
+  }
+
+  exec_kernel.set_global_work_sizes(&ndrange.global);
+  if let Some(ref local) = ndrange.local {
+    exec_kernel.set_local_work_sizes(local);
+  }
+  if let Some(ref offset) = ndrange.offset {
+    exec_kernel.set_global_work_offsets(offset);
+  }
+
+  // Setup command queue
+  let mut kernel_event = unsafe { exec_kernel.enqueue_nd_range(&dev.queue).map_err(structs::eloc!())? };
+
+  // Safety: both vectors increase at same time
+  dev.sim_events_cl.push(kernel_event.get());
+  dev.sim_events.push(kernel_event);
+
+  // Anything this kernel may have written makes the other types of that column stale
+  for (aka_i, aka_idx) in arg_indicies.iter().enumerate() {
+    if dev.kernel_arg_writes[kernel_i][aka_i] {
+      dev.mark_written(*aka_idx);
+    }
   }
 
   Ok(())
 }
 
 fn write_frame_to_dt(argb_frame: &[u32], draw_buffer_width: i32, draw_buffer_height: i32, draw_buffer: &UnsafeDrawTarget<'_>) {
+  let draw_buffer: &mut [u32] = unsafe { &mut *draw_buffer.0.get() };
+  let num_px = std::cmp::min((draw_buffer_width * draw_buffer_height) as usize, std::cmp::min(argb_frame.len(), draw_buffer.len()));
+  draw_buffer[..num_px].copy_from_slice(&argb_frame[..num_px]);
+}
 
+/// Blends the colormapped cells of field over the draw buffer; world coordinates are pixel coordinates, so the pixel at (x, y)
+/// shows the cell holding its center.
+fn write_field_to_dt(field: &structs::FieldDef, bounds: &[f64; 4], cells: &structs::HostColumn, draw_buffer_width: i32, draw_buffer_height: i32, draw_buffer: &UnsafeDrawTarget<'_>) {
+  let draw_buffer: &mut [u32] = unsafe { &mut *draw_buffer.0.get() };
+  let [x_min, y_min, x_max, y_max] = *bounds;
+  let (lo, hi) = match field.render_range {
+    Some([lo, hi]) => (lo, hi),
+    None => (0..cells.len()).map(|i| cells.get_f32(i) as f64).filter(|v| v.is_finite())
+      .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v))),
+  };
+  let span = if hi > lo { hi - lo } else { 1.0 };
+  let opacity = field.opacity.clamp(0.0, 1.0);
+  // draw_buffer has (A << 24) | (R << 16) | (G << 8) | B representation
+  let blend = |dst: u32, shift: u32, src: u8| -> u32 {
+    let dst = ((dst >> shift) & 0xff) as f64;
+    ((dst + (src as f64 - dst) * opacity).round() as u32) << shift
+  };
+  for db_y in 0..draw_buffer_height {
+    let world_y = db_y as f64 + 0.5;
+    if world_y < y_min || world_y >= y_max {
+      continue;
+    }
+    let cy = std::cmp::min(((world_y - y_min) / (y_max - y_min) * field.height as f64) as usize, field.height - 1);
+    for db_x in 0..draw_buffer_width {
+      let world_x = db_x as f64 + 0.5;
+      if world_x < x_min || world_x >= x_max {
+        continue;
+      }
+      let cx = std::cmp::min(((world_x - x_min) / (x_max - x_min) * field.width as f64) as usize, field.width - 1);
+      let cell_i = cy * field.width + cx;
+      if cell_i >= cells.len() {
+        continue;
+      }
+      let (r, g, b) = field.colormap.color((cells.get_f32(cell_i) as f64 - lo) / span);
+      let db_offset = (db_y * draw_buffer_width + db_x) as usize;
+      let dst = draw_buffer[db_offset];
+      draw_buffer[db_offset] = 0xff000000 | blend(dst, 16, r) | blend(dst, 8, g) | blend(dst, 0, b);
+    }
+  }
 }
 
 async fn write_historic_xy_points_to_dt(historic_xy_slice: &[(f32, f32)], draw_buffer_width: i32, draw_buffer_height: i32, draw_buffer: &UnsafeDrawTarget<'_>) {
@@ -470,6 +1613,18 @@ async fn write_historic_xy_points_to_dt(historic_xy_slice: &[(f32, f32)], draw_b
   }
 }
 
+/// Host-side rendering state of one entity table; x_column & y_column are the (qualified) names of its coordinate buffers
+struct RenderTable<'a> {
+  table_name: String,
+  ld_data: &'a utils::ListedData,
+  gis_x_attr_name: String,
+  gis_y_attr_name: String,
+  x_column: String,
+  y_column: String,
+  gis_name_attr: String,
+  colors: Vec<raqote::Source<'static>>,
+}
+
 // Type safety goes out the window when I need threads throwing pixels into a buffer
 struct UnsafeDrawTarget<'a>(std::cell::UnsafeCell<&'a mut [u32]>);
 unsafe impl Send for UnsafeDrawTarget<'_> {}
@@ -6,8 +6,9 @@ use std::collections::HashMap;
 
 #[derive(Debug, clap::Parser)]
 pub struct Args {
-    /// A data file (.toml, .json, etc.) containing simulation configuration data.
-    pub simcontrol_file_path: std::path::PathBuf,
+    /// A data file (.toml, .json, etc.) containing simulation configuration data. Not needed with --resume, which restores the checkpointed configuration.
+    #[arg(required_unless_present = "resume")]
+    pub simcontrol_file_path: Option<std::path::PathBuf>,
 
     /// A data file (.csv, .json, etc.) containing T=0 data for the simulation.
     #[arg(short, long)]
@@ -85,6 +86,45 @@ pub struct Args {
     #[arg(long)]
     pub background_img: Option<String>,
 
+    /// A .csv or .bin file path which will have (step, entity, columns...) records appended to it every trajectory-step-period steps
+    #[arg(long)]
+    pub trajectory_file_path: Option<std::path::PathBuf>,
+
+    /// Every N steps the trajectory columns of all entities are appended to trajectory-file-path; 0 disables recording
+    #[arg(long)]
+    pub trajectory_step_period: Option<u64>,
+
+    /// A .csv file path which receives one row of [[reduction]] results per step on which any reduction runs
+    #[arg(long)]
+    pub stats_file_path: Option<std::path::PathBuf>,
+
+    /// A data file path which receives the final values of every [[global_buffer]]
+    #[arg(long)]
+    pub global_buffers_file_path: Option<std::path::PathBuf>,
+
+    /// Path to a checkpoint file which is overwritten every checkpoint-step-period steps
+    #[arg(long)]
+    pub checkpoint_file_path: Option<std::path::PathBuf>,
+
+    /// Every N steps all simulation state is written to checkpoint-file-path
+    #[arg(long)]
+    pub checkpoint_step_period: Option<u64>,
+
+    /// Seed for kernel random number streams, overriding simcontrol random_seed
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Run N replicas of each simulation with random_seed, random_seed+1, ... and write per-entity statistics of the final state instead of each replica's output
+    #[arg(long)]
+    pub ensemble: Option<u64>,
+
+    /// Continue a simulation from a checkpoint file written by an earlier run, up to num_steps.
+    /// Other CLI args (ie --num-steps) still override the checkpointed simcontrol values. The trajectory & stats files are
+    /// continued from the checkpoint; the animation continues in <animation stem>-from-step<N>.<ext>, which follows the frames
+    /// of the checkpointed run.
+    #[arg(long)]
+    pub resume: Option<std::path::PathBuf>,
+
 
 }
 
@@ -117,10 +157,659 @@ impl NamedDataConstant {
 #[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
 pub struct SimControl_file { // utility to allow us to specify name of value
   pub simulation: SimControl,
-  pub data_constants: HashMap<String, Value>,
+  pub data_constants: HashMap<String, DataConstantDef>,
+  #[serde(default = "serde_default_schema")]
+  pub schema: HashMap<String, ColumnSchema>,
+  #[serde(default = "serde_default_entity_tables")]
+  pub table: Vec<EntityTable>,
+  #[serde(default = "serde_default_fields")]
+  pub field: Vec<FieldDef>,
+  #[serde(default)]
+  pub world: Option<WorldDef>,
 }
 
-#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SimulationMode {
+  #[default]
+  Step,
+  Event,
+}
+
+/// An event given in the simcontrol file
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct EventDef {
+  pub time: f64,
+  /// Index of the entity within the table of the kernels handling this event type
+  pub entity: u32,
+  /// One of SimControl.event_types
+  #[serde(rename = "type")]
+  pub event_type: String,
+  #[serde(default)]
+  pub payload: f32,
+}
+
+/// An event waiting in the EventQueue; event_type is an index into SimControl.event_types
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ScheduledEvent {
+  pub time: f64,
+  /// Order in which events were posted, which orders events of the same time
+  pub seq: u64,
+  pub entity: u32,
+  pub event_type: u32,
+  pub payload: f32,
+}
+
+impl PartialEq for ScheduledEvent {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == std::cmp::Ordering::Equal
+  }
+}
+impl Eq for ScheduledEvent {}
+impl PartialOrd for ScheduledEvent {
+  fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+impl Ord for ScheduledEvent {
+  fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    self.time.total_cmp(&other.time).then(self.seq.cmp(&other.seq))
+  }
+}
+
+/// Host-side priority queue of an event-driven simulation, ordered by time & then by posting order
+#[derive(Debug, Default)]
+pub struct EventQueue {
+  pub heap: std::collections::BinaryHeap<std::cmp::Reverse<ScheduledEvent>>,
+  pub next_seq: u64,
+}
+
+impl EventQueue {
+  /// Restores a queue from events & next_seq as stored by a checkpoint
+  pub fn from_events(events: &[ScheduledEvent], next_seq: u64) -> EventQueue {
+    EventQueue {
+      heap: events.iter().cloned().map(std::cmp::Reverse).collect(),
+      next_seq,
+    }
+  }
+
+  pub fn push(&mut self, time: f64, entity: u32, event_type: u32, payload: f32) {
+    self.heap.push(std::cmp::Reverse(ScheduledEvent {
+      time,
+      seq: self.next_seq,
+      entity,
+      event_type,
+      payload,
+    }));
+    self.next_seq += 1;
+  }
+
+  /// Removes & returns every event sharing the earliest time, in posting order; None if the queue is empty
+  /// or the earliest time is after end_time.
+  pub fn pop_batch(&mut self, end_time: Option<f64>) -> Option<(f64, Vec<ScheduledEvent>)> {
+    let time = self.heap.peek()?.0.time;
+    if end_time.map(|end_time| time > end_time).unwrap_or(false) {
+      return None;
+    }
+    let mut batch: Vec<ScheduledEvent> = vec![];
+    while self.heap.peek().map(|e| e.0.time == time).unwrap_or(false) {
+      if let Some(std::cmp::Reverse(event)) = self.heap.pop() {
+        batch.push(event);
+      }
+    }
+    Some((time, batch))
+  }
+
+  /// Every queued event in queue order
+  pub fn events(&self) -> Vec<ScheduledEvent> {
+    let mut events: Vec<ScheduledEvent> = self.heap.iter().map(|e| e.0.clone()).collect();
+    events.sort();
+    events
+  }
+}
+
+/// Kernel arguments with these names are bound to the event buffers of an event-driven simulation, which have the given element types
+pub const EVENT_BUFFERS: [(&str, &str); 7] = [
+  ("event_entity", "uint"), ("event_payload", "float"),
+  ("post_event_count", "uint"), ("post_event_delay", "float"), ("post_event_entity", "uint"), ("post_event_type", "uint"), ("post_event_payload", "float"),
+];
+
+/// Configures the uniform grid built by CL_SpatialIndex, eg `[simulation.spatial_index] cell_size = 25.0`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SpatialIndexDef {
+  /// Width & height of a cell, in the units of the coordinate columns; usually the largest interaction radius
+  pub cell_size: f64,
+
+  /// Entity table which is indexed; if unspecified the primary table (SimControl.table_name) is used.
+  #[serde(default)]
+  pub table: Option<String>,
+
+  /// Coordinate columns; if unspecified SimControl.gis_x_attr_name & gis_y_attr_name are used.
+  #[serde(default)]
+  pub x_column: Option<String>,
+  #[serde(default)]
+  pub y_column: Option<String>,
+
+  /// Coordinates of the corner of cell (0, 0)
+  #[serde(default)]
+  pub origin: [f64; 2],
+
+  /// Number of cells along x & y; if unspecified the grid covers the animation frame.
+  #[serde(default)]
+  pub grid_size: Option<[u32; 2]>,
+}
+
+impl SpatialIndexDef {
+  pub fn grid_dims(&self, simcontrol: &SimControl) -> (u32, u32) {
+    match self.grid_size {
+      Some([w, h]) => (std::cmp::max(w, 1), std::cmp::max(h, 1)),
+      None => (
+        std::cmp::max((simcontrol.output_animation_width as f64 / self.cell_size).ceil() as u32, 1),
+        std::cmp::max((simcontrol.output_animation_height as f64 / self.cell_size).ceil() as u32, 1),
+      ),
+    }
+  }
+
+  pub fn num_cells(&self, simcontrol: &SimControl) -> usize {
+    let (w, h) = self.grid_dims(simcontrol);
+    w as usize * h as usize
+  }
+}
+
+/// Configures the edge list read into a GraphCSR, eg `graph = { input = "edges.csv", id_column = "Name", render = true }`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct GraphDef {
+  /// Data file (.csv, .json, .toml) holding one record per edge; columns other than source_column & target_column
+  /// are edge attributes, which kernels bind as `edge_<column>`.
+  pub input: std::path::PathBuf,
+
+  /// Entity table whose entities the edges connect; if unspecified the primary table (SimControl.table_name) is used.
+  #[serde(default)]
+  pub table: Option<String>,
+
+  /// Column of table holding the IDs edges refer to entities by; if unspecified edges refer to entities by index.
+  #[serde(default)]
+  pub id_column: Option<String>,
+
+  #[serde(default = "serde_default_graph_source_column")]
+  pub source_column: String,
+
+  #[serde(default = "serde_default_graph_target_column")]
+  pub target_column: String,
+
+  /// If false every edge is also added from target to source, with the same attributes
+  #[serde(default = "serde_default_true")]
+  pub directed: bool,
+
+  /// Draw every edge as a line between its entities in the animation
+  #[serde(default)]
+  pub render: bool,
+
+  /// CSS color of rendered edges
+  #[serde(default = "serde_default_graph_edge_color")]
+  pub edge_color: String,
+}
+
+fn serde_default_graph_source_column() -> String { "source".to_string() }
+fn serde_default_graph_target_column() -> String { "target".to_string() }
+fn serde_default_graph_edge_color() -> String { "#c0c0c0".to_string() }
+
+/// Configures the bounds enforced by CL_WorldBoundary, eg `[world] bounds = [0.0, 0.0, 800.0, 600.0] boundary = "wrap"`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct WorldDef {
+  /// [x_min, y_min, x_max, y_max]; if unspecified the world is the animation frame.
+  #[serde(default)]
+  pub bounds: Option<[f64; 4]>,
+
+  #[serde(default)]
+  pub boundary: WorldBoundary,
+
+  /// Entity table which is kept within bounds; if unspecified the primary table (SimControl.table_name) is used.
+  #[serde(default)]
+  pub table: Option<String>,
+
+  /// Coordinate columns; if unspecified SimControl.gis_x_attr_name & gis_y_attr_name are used.
+  #[serde(default)]
+  pub x_column: Option<String>,
+  #[serde(default)]
+  pub y_column: Option<String>,
+
+  /// x & y velocity columns, ie ["vx", "vy"]; a "reflect" boundary inverts the velocity along the axis an entity bounced off.
+  #[serde(default)]
+  pub velocity_columns: Option<[String; 2]>,
+}
+
+impl WorldDef {
+  /// [x_min, y_min, x_max, y_max] of the world
+  pub fn world_bounds(&self, simcontrol: &SimControl) -> [f64; 4] {
+    self.bounds.unwrap_or([0.0, 0.0, simcontrol.output_animation_width as f64, simcontrol.output_animation_height as f64])
+  }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WorldBoundary {
+  /// Entities stop at the edge they crossed
+  #[default]
+  Clamp,
+  /// Entities leaving across one edge enter across the opposite one (periodic boundaries)
+  Wrap,
+  /// Entities bounce back into the world by as far as they overshot the edge
+  Reflect,
+  /// Entities leaving the world are removed at the next compaction, which requires the table to have max_entities
+  Remove,
+}
+
+impl WorldBoundary {
+  pub fn name(&self) -> &'static str {
+    match self {
+      WorldBoundary::Clamp   => "clamp",
+      WorldBoundary::Wrap    => "wrap",
+      WorldBoundary::Reflect => "reflect",
+      WorldBoundary::Remove  => "remove",
+    }
+  }
+}
+
+/// Kernel arguments with these names are bound to the CSR buffers of the graph rather than to columns;
+/// edge attributes are bound as "edge_<attribute>" too.
+pub const GRAPH_BUFFERS: [&str; 3] = ["row_offsets", "col_indices", "edge_source"];
+
+/// The edges of a GraphDef in compressed sparse row form: edges leaving entity i are row_offsets[i]..row_offsets[i+1],
+/// leading to entities col_indices[e]. Every edge attribute is stored in the same edge order.
+#[derive(Debug, Clone, Default)]
+pub struct GraphCSR {
+  pub table: String,
+  pub row_offsets: Vec<u32>,
+  pub col_indices: Vec<u32>,
+  /// Attribute name (without the edge_ prefix) -> one value per edge
+  pub edge_attributes: HashMap<String, Vec<Value>>,
+}
+
+impl GraphCSR {
+  /// Builds the CSR form of edges, given as (source index, target index, attribute values) between num_entities entities;
+  /// edges keep their input order among those leaving the same entity.
+  pub fn from_edges(table: &str, num_entities: usize, attribute_names: &[String], edges: &[(u32, u32, Vec<Value>)]) -> GraphCSR {
+    let mut row_offsets: Vec<u32> = vec![0; num_entities + 1];
+    for (source, _target, _attributes) in edges.iter() {
+      row_offsets[*source as usize + 1] += 1;
+    }
+    for i in 0..num_entities {
+      row_offsets[i + 1] += row_offsets[i];
+    }
+
+    let mut next_slot: Vec<u32> = row_offsets[..num_entities].to_vec();
+    let mut col_indices: Vec<u32> = vec![0; edges.len()];
+    let mut edge_attributes: HashMap<String, Vec<Value>> = attribute_names.iter().map(|name| (name.clone(), vec![Value::Integer(0); edges.len()])).collect();
+    for (source, target, attributes) in edges.iter() {
+      let e = next_slot[*source as usize] as usize;
+      next_slot[*source as usize] += 1;
+      col_indices[e] = *target;
+      for (attribute_i, name) in attribute_names.iter().enumerate() {
+        edge_attributes.get_mut(name).expect("attribute_names are keys")[e] = attributes[attribute_i].clone();
+      }
+    }
+
+    GraphCSR {
+      table: table.to_string(),
+      row_offsets,
+      col_indices,
+      edge_attributes,
+    }
+  }
+
+  pub fn num_edges(&self) -> usize {
+    self.col_indices.len()
+  }
+
+  /// Source entity of every edge, in edge order
+  pub fn edge_sources(&self) -> Vec<u32> {
+    let mut sources: Vec<u32> = Vec::with_capacity(self.num_edges());
+    for i in 0..self.row_offsets.len().saturating_sub(1) {
+      for _e in self.row_offsets[i]..self.row_offsets[i + 1] {
+        sources.push(i as u32);
+      }
+    }
+    sources
+  }
+
+  /// Values of the buffer a kernel argument named name binds to, or None if name is not a graph buffer
+  pub fn buffer_values(&self, name: &str) -> Option<Vec<Value>> {
+    let integers = |v: &[u32]| -> Vec<Value> { v.iter().map(|x| Value::Integer(*x as i64)).collect() };
+    match name {
+      "row_offsets" => Some(integers(&self.row_offsets)),
+      "col_indices" => Some(integers(&self.col_indices)),
+      "edge_source" => Some(integers(&self.edge_sources())),
+      _ => name.strip_prefix("edge_").and_then(|attribute| self.edge_attributes.get(attribute)).cloned(),
+    }
+  }
+}
+
+/// Kernel arguments with these names are bound to the buffers of the spatial index rather than to columns
+pub const SPATIAL_INDEX_BUFFERS: [&str; 3] = ["cell_start", "cell_end", "sorted_index"];
+
+/// Builds CL_VECTOR_TYPES from the names of their element types
+macro_rules! cl_vector_types {
+  ($($element_type:literal),*) => {
+    &[$(
+      (concat!($element_type, "2"),  $element_type, 2),
+      (concat!($element_type, "3"),  $element_type, 3),
+      (concat!($element_type, "4"),  $element_type, 4),
+      (concat!($element_type, "8"),  $element_type, 8),
+      (concat!($element_type, "16"), $element_type, 16),
+    )*]
+  };
+}
+
+/// OpenCL C vector types kernel arguments may be declared as, as (vector type, element type, width)
+pub const CL_VECTOR_TYPES: &[(&str, &str, usize)] = cl_vector_types!("uchar", "ushort", "uint", "ulong", "char", "short", "int", "long", "float", "double");
+
+/// Returns (vector type, element type, width) if cl_type_name names a vector type like "float4"
+pub fn cl_vector_type(cl_type_name: &str) -> Option<(&'static str, &'static str, usize)> {
+  CL_VECTOR_TYPES.iter().find(|(vector_type, _element_type, _width)| *vector_type == cl_type_name).copied()
+}
+
+/// Number of elements a vector of width occupies in memory; 3-element vectors are sized & aligned like 4-element ones
+pub fn cl_vector_stride(width: usize) -> usize {
+  if width == 3 { 4 } else { width }
+}
+
+/// all_kernel_args name of a buffer managed by apollon (ie SPATIAL_INDEX_BUFFERS, EVENT_BUFFERS & global buffers); these are never treated as columns.
+pub fn apollon_buffer_name(name: &str) -> String {
+  format!("apollon:{}", name)
+}
+
+pub fn is_apollon_buffer_name(name: &str) -> bool {
+  name.starts_with("apollon:")
+}
+
+/// An additional population of entities with its own columns & count, eg
+/// `[[table]] name = "sensors" input = "sensors.csv" output = "/tmp/sensors-out.csv"`.
+/// Kernels select the table driving their work size with `table = "sensors"`, and any kernel may bind
+/// another table's column read-only by qualifying it in colmap, ie `sensor_x = "sensors.X0"`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct EntityTable {
+  /// Must be unique, differ from SimControl.table_name & may not contain '.'
+  pub name: String,
+
+  /// A data file (.csv, .json, etc.) containing T=0 data for this table; validated against this table's schema.
+  pub input: std::path::PathBuf,
+
+  /// Column name -> type declarations of this table, like [schema] of the primary table, which does not apply here.
+  #[serde(default = "serde_default_schema")]
+  pub schema: HashMap<String, ColumnSchema>,
+
+  #[serde(default = "serde_default_pathbuf_devnull")]
+  pub output: std::path::PathBuf,
+
+  /// Overrides of the SimControl gis_* attributes for this table; if unspecified the SimControl ones are used.
+  #[serde(default)]
+  pub gis_x_attr_name: Option<String>,
+  #[serde(default)]
+  pub gis_y_attr_name: Option<String>,
+  #[serde(default)]
+  pub gis_name_attr: Option<String>,
+  #[serde(default)]
+  pub gis_color_attr: Option<String>,
+
+  /// If false the table's entities are not drawn in the animation
+  #[serde(default = "serde_default_true")]
+  pub render: bool,
+
+  /// Capacity of a table whose population changes during the run; see SimControl.max_entities
+  #[serde(default)]
+  pub max_entities: usize,
+}
+
+/// A 2D raster of width x height cells covering a rectangle of the world, eg
+/// `[[field]] name = "pheromone" width = 160 height = 120 bounds = [0.0, 0.0, 800.0, 600.0] render = true`.
+/// Kernels bind its cells by declaring an argument of its name (or mapping one to it with colmap) of its type,
+/// along with the scalars <name>_width, <name>_height, <name>_origin_x, <name>_origin_y, <name>_cell_width & <name>_cell_height.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FieldDef {
+  /// Must be unique & may not contain '.'
+  pub name: String,
+  pub width: usize,
+  pub height: usize,
+
+  /// OpenCL C type of each cell
+  #[serde(rename = "type", default = "serde_default_field_type")]
+  pub cl_type: String,
+
+  /// [x_min, y_min, x_max, y_max] of the world covered; cell (0, 0) starts at (x_min, y_min).
+  /// If unspecified the field covers the animation frame.
+  #[serde(default)]
+  pub bounds: Option<[f64; 4]>,
+
+  /// Value of every cell at T=0 unless input is given
+  #[serde(default)]
+  pub init: f64,
+
+  /// Initial cell values from an image, whose pixels are resized to width x height & read as brightness from 0.0 to 1.0,
+  /// or from a .csv file of height rows of width values without a header. Row 0 holds the cells at y_min.
+  #[serde(default)]
+  pub input: Option<std::path::PathBuf>,
+
+  /// Draw the field under the entities of the animation
+  #[serde(default)]
+  pub render: bool,
+
+  #[serde(default)]
+  pub colormap: FieldColormap,
+
+  /// Values drawn at the low & high ends of the colormap; if unspecified every frame uses the field's current min & max
+  #[serde(default)]
+  pub render_range: Option<[f64; 2]>,
+
+  /// From 0.0 (invisible) to 1.0 (hides the background)
+  #[serde(default = "serde_default_field_opacity")]
+  pub opacity: f64,
+
+  /// Cell values read from input by utils::load_field_inputs, in row-major order
+  #[serde(skip)]
+  pub input_values: Vec<f64>,
+}
+
+/// Cell types a field may hold
+pub const FIELD_TYPES: [&str; 10] = ["char", "uchar", "short", "ushort", "int", "uint", "long", "ulong", "float", "double"];
+
+fn serde_default_field_type() -> String { "float".to_string() }
+fn serde_default_field_opacity() -> f64 { 0.6 }
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldColormap {
+  #[default]
+  Viridis,
+  Heat,
+  Grayscale,
+}
+
+impl FieldColormap {
+  /// Color stops from the low to the high end of the colormap, as (r, g, b)
+  fn stops(&self) -> &'static [(f64, f64, f64)] {
+    match self {
+      FieldColormap::Viridis   => &[(68.0, 1.0, 84.0), (59.0, 82.0, 139.0), (33.0, 145.0, 140.0), (94.0, 201.0, 98.0), (253.0, 231.0, 37.0)],
+      FieldColormap::Heat      => &[(0.0, 0.0, 0.0), (180.0, 0.0, 0.0), (255.0, 140.0, 0.0), (255.0, 255.0, 90.0), (255.0, 255.0, 255.0)],
+      FieldColormap::Grayscale => &[(0.0, 0.0, 0.0), (255.0, 255.0, 255.0)],
+    }
+  }
+
+  /// Color of t, which is clamped to 0.0 ..= 1.0, as (r, g, b)
+  pub fn color(&self, t: f64) -> (u8, u8, u8) {
+    let stops = self.stops();
+    let pos = if t.is_finite() { t.clamp(0.0, 1.0) } else { 0.0 } * (stops.len() - 1) as f64;
+    let lo = std::cmp::min(pos as usize, stops.len() - 2);
+    let frac = pos - lo as f64;
+    let mix = |a: f64, b: f64| (a + (b - a) * frac).round() as u8;
+    (mix(stops[lo].0, stops[lo + 1].0), mix(stops[lo].1, stops[lo + 1].1), mix(stops[lo].2, stops[lo + 1].2))
+  }
+}
+
+impl FieldDef {
+  /// [x_min, y_min, x_max, y_max] of the world covered by the field
+  pub fn world_bounds(&self, simcontrol: &SimControl) -> [f64; 4] {
+    self.bounds.unwrap_or([0.0, 0.0, simcontrol.output_animation_width as f64, simcontrol.output_animation_height as f64])
+  }
+
+  /// Width & height of one cell in world units
+  pub fn cell_size(&self, simcontrol: &SimControl) -> (f64, f64) {
+    let [x_min, y_min, x_max, y_max] = self.world_bounds(simcontrol);
+    ((x_max - x_min) / std::cmp::max(self.width, 1) as f64, (y_max - y_min) / std::cmp::max(self.height, 1) as f64)
+  }
+
+  pub fn num_cells(&self) -> usize {
+    self.width * self.height
+  }
+
+  /// Cell values at T=0
+  pub fn initial_values(&self) -> Vec<Value> {
+    if self.input_values.len() == self.num_cells() {
+      return self.input_values.iter().map(|v| Value::Double(*v)).collect();
+    }
+    vec![Value::Double(self.init); self.num_cells()]
+  }
+
+  /// Value of the scalar kernel argument <name>_<suffix> describing this field's shape, if variable_name is one
+  pub fn scalar_value(&self, simcontrol: &SimControl, variable_name: &str) -> Option<Value> {
+    let suffix = variable_name.strip_prefix(self.name.as_str())?.strip_prefix('_')?;
+    let [x_min, y_min, _x_max, _y_max] = self.world_bounds(simcontrol);
+    let (cell_width, cell_height) = self.cell_size(simcontrol);
+    match suffix {
+      "width"       => Some(Value::Integer(self.width as i64)),
+      "height"      => Some(Value::Integer(self.height as i64)),
+      "origin_x"    => Some(Value::Double(x_min)),
+      "origin_y"    => Some(Value::Double(y_min)),
+      "cell_width"  => Some(Value::Double(cell_width)),
+      "cell_height" => Some(Value::Double(cell_height)),
+      _ => None,
+    }
+  }
+}
+
+/// Declares the type of one input data column, eg `[schema] X0 = { type = "f32", default = 0.0 }`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ColumnSchema {
+  #[serde(rename = "type")]
+  pub v_type: ValueType,
+
+  /// Used for records which do not have a value for this column
+  #[serde(default)]
+  pub default: Option<Value>,
+
+  /// If true records may omit this column even when no default is given; if false such records are an error.
+  #[serde(default)]
+  pub nullable: bool,
+}
+
+/// A condition evaluated on-device for every entity which ends the simulation early, eg
+/// `[[simulation.stop_conditions]] expression = "hypot(X0[i] - X0[0], Y0[i] - Y0[0]) < 25.0", quantifier = "all"`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct StopCondition {
+  /// An OpenCL C boolean expression; `i` is the entity index, `n` the entity count, and any column, global buffer or scalar
+  /// data constant bound by a kernel may be referenced by name (columns are arrays, ie `X0[i]`).
+  pub expression: String,
+
+  /// Entity table the condition is evaluated over; column names in expression refer to this table's columns.
+  /// If unspecified the primary table (SimControl.table_name) is used.
+  #[serde(default)]
+  pub table: Option<String>,
+
+  /// How many entities must satisfy expression for the simulation to stop
+  #[serde(default)]
+  pub quantifier: StopQuantifier,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StopQuantifier {
+  All,
+  #[default]
+  Any,
+  None,
+}
+
+impl StopQuantifier {
+  pub fn is_satisfied(&self, num_matched: usize, num_entities: usize) -> bool {
+    match self {
+      StopQuantifier::All  => num_matched >= num_entities,
+      StopQuantifier::Any  => num_matched > 0,
+      StopQuantifier::None => num_matched == 0,
+    }
+  }
+}
+
+/// Statistics of one column computed on-device by CL_Reduction, eg
+/// `[[reduction]] column = "X0", ops = ["sum", "min", "max", "mean"], every = 10`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ReductionDef {
+  /// A column bound by a kernel; integer columns are reduced as float
+  pub column: String,
+
+  /// Entity table holding column; if unspecified the primary table (SimControl.table_name) is used.
+  #[serde(default)]
+  pub table: Option<String>,
+
+  pub ops: Vec<ReductionOp>,
+
+  /// An OpenCL C boolean expression selecting the entities reduced, in the form of StopCondition.expression
+  #[serde(default)]
+  pub filter: Option<String>,
+
+  /// The reduction runs after steps 0, every, 2*every, ...; 0 disables it.
+  #[serde(default = "serde_default_reduction_every")]
+  pub every: u64,
+
+  /// Statistics are named "<name>_<op>" in the stats file & as kernel arguments; name defaults to column.
+  #[serde(default)]
+  pub name: Option<String>,
+}
+
+fn serde_default_reduction_every() -> u64 { 1 }
+fn serde_default_reductions() -> Vec<ReductionDef> { vec![] }
+
+impl ReductionDef {
+  /// Name of the statistic op computes, ie "X0_mean". A scalar kernel argument of this name receives its latest value.
+  pub fn stat_name(&self, op: ReductionOp) -> String {
+    format!("{}_{}", self.name.as_deref().unwrap_or(self.column.as_str()), op.name())
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReductionOp {
+  Sum,
+  Min,
+  Max,
+  Mean,
+  /// Number of entities selected by filter (or every live entity)
+  Count,
+}
+
+impl ReductionOp {
+  pub fn name(&self) -> &'static str {
+    match self {
+      ReductionOp::Sum   => "sum",
+      ReductionOp::Min   => "min",
+      ReductionOp::Max   => "max",
+      ReductionOp::Mean  => "mean",
+      ReductionOp::Count => "count",
+    }
+  }
+
+  /// The value of this op given the sum, min, max & count of the reduced values; NaN for min, max & mean of no values.
+  pub fn apply(&self, sum: f64, min: f64, max: f64, count: u64) -> f64 {
+    match self {
+      ReductionOp::Sum   => sum,
+      ReductionOp::Min   => if count > 0 { min } else { f64::NAN },
+      ReductionOp::Max   => if count > 0 { max } else { f64::NAN },
+      ReductionOp::Mean  => if count > 0 { sum / count as f64 } else { f64::NAN },
+      ReductionOp::Count => count as f64,
+    }
+  }
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
 pub struct SimControl {
     /// A data file (.csv, .json, etc.) containing T=0 data for the simulation.
     pub input_data_file_path: std::path::PathBuf,
@@ -173,9 +862,15 @@ pub struct SimControl {
     #[serde(default = "serde_default_gis_color_attr")]
     pub gis_color_attr: String,
 
-    // If not specified under [simulation], these are copied in from SimControl_file
-    #[serde(default = "serde_default_value_map")]
-    pub data_constants: HashMap<String, Value>,
+    // If not specified under [simulation], these are copied in from SimControl_file.
+    // Ranges and lists are expanded by main_async into one simulation run per combination.
+    #[serde(default = "serde_default_data_constant_def_map")]
+    pub data_constants: HashMap<String, DataConstantDef>,
+
+    /// Column name -> type declarations validated when input data is loaded & used when writing output data.
+    /// If not specified under [simulation], these are copied in from SimControl_file.
+    #[serde(default = "serde_default_schema")]
+    pub schema: HashMap<String, ColumnSchema>,
 
     #[serde(default = "serde_default_max_entity_idx_to_name")]
     pub max_entity_idx_to_name: usize,
@@ -187,6 +882,137 @@ pub struct SimControl {
     #[serde(default = "serde_default_background_img")]
     pub background_img: String,
 
+    /// A data file (.csv, .bin) which every trajectory_step_period steps has the trajectory_columns of every entity appended to it.
+    /// .csv files hold one "step,entity,<columns...>" row per entity; anything else is written in the binary columnar format described by utils::TrajectoryWriter.
+    #[serde(default = "serde_default_pathbuf_devnull")]
+    pub trajectory_file_path: std::path::PathBuf,
+
+    /// Steps between trajectory records; 0 disables trajectory recording, including ensemble_trajectory_stats.
+    #[serde(default = "serde_default_trajectory_step_period")]
+    pub trajectory_step_period: u64,
+
+    /// Columns recorded in trajectory_file_path; if empty every column bound by a kernel is recorded.
+    /// With max_entities set, entity_id is always recorded since entity indexes change as entities are removed & spawned.
+    #[serde(default = "serde_default_string_vec")]
+    pub trajectory_columns: Vec<String>,
+
+    /// A data file (.csv, .json, .toml) which receives the final values of every [[global_buffer]] of the kernel file as (name, index, value) records.
+    #[serde(default = "serde_default_pathbuf_devnull")]
+    pub global_buffers_file_path: std::path::PathBuf,
+
+    /// A .csv file which receives a "step,sim_time,<statistics...>" row for every step on which any [[reduction]] of the kernel file runs;
+    /// statistics not computed at that step are left empty.
+    #[serde(default = "serde_default_pathbuf_devnull")]
+    pub stats_file_path: std::path::PathBuf,
+
+    /// A file which is overwritten with every column buffer, the step index & renderer state every checkpoint_step_period steps.
+    /// Pass it to --resume to continue an interrupted simulation; see utils::write_checkpoint for the format.
+    #[serde(default = "serde_default_pathbuf_devnull")]
+    pub checkpoint_file_path: std::path::PathBuf,
+
+    #[serde(default = "serde_default_checkpoint_step_period")]
+    pub checkpoint_step_period: u64,
+
+    /// Simulated time advanced by each step, in time_unit; kernels receive it as the reserved `dt` argument.
+    #[serde(default = "serde_default_dt")]
+    pub dt: f64,
+
+    /// Unit of dt & sim_time, used when reporting times (ie "s", "ms", "days")
+    #[serde(default = "serde_default_time_unit")]
+    pub time_unit: String,
+
+    /// Seed of the counter-based random number streams kernels create with apollon_rng.h; the same seed reproduces a run exactly.
+    #[serde(default)]
+    pub random_seed: u64,
+
+    /// If > 1 every run is repeated with random_seed, random_seed + 1, ... and output_data_file_path receives the
+    /// mean, stddev, min, max & ensemble_quantiles of every written column for every entity across the replicas.
+    /// Only the first replica writes an animation, and replicas do not write checkpoints.
+    #[serde(default = "serde_default_ensemble_size")]
+    pub ensemble_size: u64,
+
+    /// Quantiles in [0, 1] reported for every column of an ensemble, ie 0.5 is written as <column>_q50
+    #[serde(default = "serde_default_ensemble_quantiles")]
+    pub ensemble_quantiles: Vec<f64>,
+
+    /// If true trajectory_file_path receives the same statistics for every recorded step (as CSV) rather than one trajectory file per replica.
+    /// All replicas' trajectories are held in memory until the ensemble completes.
+    #[serde(default)]
+    pub ensemble_trajectory_stats: bool,
+
+    /// The simulation stops after the first step at which any of these holds; num_steps remains the upper bound.
+    #[serde(default = "serde_default_stop_conditions")]
+    pub stop_conditions: Vec<StopCondition>,
+
+    /// stop_conditions are evaluated every N steps, as each check waits for all queued kernels to complete.
+    #[serde(default = "serde_default_stop_condition_step_period")]
+    pub stop_condition_step_period: u64,
+
+    /// Name of the table read from input_data_file_path, which kernels, trajectories, checkpoints & ensembles use unless told otherwise.
+    /// Its columns keep their plain names; columns of the other tables are named "<table>.<column>".
+    #[serde(default = "serde_default_table_name")]
+    pub table_name: String,
+
+    /// Additional entity tables; if not specified under [simulation], these are copied in from SimControl_file's [[table]] entries.
+    #[serde(default = "serde_default_entity_tables")]
+    pub tables: Vec<EntityTable>,
+
+    /// 2D rasters of cells covering the world, such as terrain or pheromone; if not specified under [simulation],
+    /// these are copied in from SimControl_file's [[field]] entries. See src/apollon_field.cl.
+    #[serde(default = "serde_default_fields")]
+    pub fields: Vec<FieldDef>,
+
+    /// If non-zero the primary table's population may change during the run, up to this many entities.
+    /// Column buffers are allocated with this capacity, and kernels may bind the apollon-managed columns
+    /// `alive` (set to 0 to remove an entity), `entity_id` (stable across compactions, read-only to kernels)
+    /// and `spawn_count` (a single uint to atomic_inc, spawning into index entity_count + the returned value if below entity_capacity).
+    /// A spawning kernel must write every column of the new entity; spawned entities are alive.
+    #[serde(default)]
+    pub max_entities: usize,
+
+    /// If given, entities of one table are sorted into a uniform grid on-device before every step, so kernels can visit
+    /// their neighbors through the cell_start, cell_end & sorted_index buffers; see src/apollon_grid.cl.
+    #[serde(default)]
+    pub spatial_index: Option<SpatialIndexDef>,
+
+    /// If given, an edge list connecting the entities of one table, which kernels bind in compressed sparse row form
+    /// through the row_offsets, col_indices & edge_<attribute> buffers; see src/apollon_graph.cl.
+    #[serde(default)]
+    pub graph: Option<GraphDef>,
+
+    /// If given, the rectangle entities live in; after every step's kernels CL_WorldBoundary moves (or removes) the entities
+    /// which left it. If not specified under [simulation], this is copied in from SimControl_file's [world] section.
+    #[serde(default)]
+    pub world: Option<WorldDef>,
+
+    /// "step" advances simulated time by dt every step. "event" instead jumps straight to the time of the next batch of
+    /// simultaneous events & runs only the on_event kernels of the batch's event types, over only the events' entities;
+    /// each batch then counts as one step for num_steps & the step periods below.
+    #[serde(default)]
+    pub mode: SimulationMode,
+
+    /// Event types of an event-driven simulation; kernels refer to them as APOLLON_EVENT_<NAME> (uppercased),
+    /// whose value is the index in this list. See src/apollon_events.cl.
+    #[serde(default = "serde_default_string_vec")]
+    pub event_types: Vec<String>,
+
+    /// Events queued before the first batch, eg `{ time = 0.0, entity = 3, type = "arrival", payload = 1.0 }`
+    #[serde(default = "serde_default_initial_events")]
+    pub initial_events: Vec<EventDef>,
+
+    /// An event-driven simulation ends once the event queue is empty, after num_steps batches, or before the first batch after end_time.
+    #[serde(default)]
+    pub end_time: Option<f64>,
+
+    /// Most events one kernel dispatch handles & most events kernels may post in one batch
+    #[serde(default = "serde_default_event_buffer_capacity")]
+    pub event_buffer_capacity: usize,
+
+    /// Removed & spawned entities take effect every N steps, when live entities are moved to the front of every column buffer.
+    /// Each compaction waits for all queued kernels to complete.
+    #[serde(default = "serde_default_compaction_step_period")]
+    pub compaction_step_period: u64,
+
 
 }
 
@@ -198,11 +1024,13 @@ fn serde_default_gis_name_attr()     -> String { "".to_string() }
 fn serde_default_gis_color_attr()    -> String { "".to_string() }
 fn serde_default_column_types()      -> HashMap<String, ValueType> { HashMap::<String, ValueType>::new() }
 fn serde_default_value_map()         -> HashMap<String, Value> { HashMap::<String, Value>::new() }
+fn serde_default_data_constant_def_map() -> HashMap<String, DataConstantDef> { HashMap::<String, DataConstantDef>::new() }
+fn serde_default_schema()            -> HashMap<String, ColumnSchema> { HashMap::<String, ColumnSchema>::new() }
 
 #[cfg(target_os = "windows")]
-fn serde_default_pathbuf_devnull()   -> std::path::PathBuf { "NUL".into() }
+pub fn serde_default_pathbuf_devnull()   -> std::path::PathBuf { "NUL".into() }
 #[cfg(not(target_os = "windows"))]
-fn serde_default_pathbuf_devnull()   -> std::path::PathBuf { "/dev/null".into() }
+pub fn serde_default_pathbuf_devnull()   -> std::path::PathBuf { "/dev/null".into() }
 
 fn serde_default_capture_step_period() -> u64 { 10 }
 
@@ -214,10 +1042,162 @@ fn serde_default_max_entity_idx_to_name() -> usize { std::usize::MAX /* name eve
 fn serde_default_max_historic_entity_locations() -> usize { 8 }
 
 fn serde_default_background_img() -> String { "".to_string() }
+fn serde_default_fields() -> Vec<FieldDef> { vec![] }
+
+fn serde_default_trajectory_step_period() -> u64 { 10 }
+fn serde_default_string_vec() -> Vec<String> { vec![] }
+fn serde_default_checkpoint_step_period() -> u64 { 1000 }
+fn serde_default_dt() -> f64 { 1.0 }
+fn serde_default_ensemble_size() -> u64 { 1 }
+fn serde_default_ensemble_quantiles() -> Vec<f64> { vec![0.05, 0.25, 0.5, 0.75, 0.95] }
+fn serde_default_time_unit() -> String { "s".to_string() }
+fn serde_default_stop_conditions() -> Vec<StopCondition> { vec![] }
+fn serde_default_stop_condition_step_period() -> u64 { 1 }
+fn serde_default_table_name() -> String { "entities".to_string() }
+fn serde_default_entity_tables() -> Vec<EntityTable> { vec![] }
+fn serde_default_true() -> bool { true }
+fn serde_default_compaction_step_period() -> u64 { 1 }
+fn serde_default_initial_events() -> Vec<EventDef> { vec![] }
+fn serde_default_event_buffer_capacity() -> usize { 4096 }
+
+
+/// One run of a parameter sweep: the (name, value) assignment of each swept data constant
+pub type DataConstantCombination = Vec<(String, Value)>;
+
+impl SimControl {
+  /// Expands every range or list in data_constants into the cartesian product of their values.
+  /// Each returned Vec holds one (name, value) assignment per swept constant, ordered by name so run numbering is stable.
+  /// A simcontrol without any swept constants yields exactly one empty combination.
+  pub fn data_constant_sweep_combinations(&self) -> Result<Vec<DataConstantCombination>, Box<dyn std::error::Error>> {
+    let mut swept_names: Vec<&String> = self.data_constants.iter().filter(|(_name, def)| def.is_swept()).map(|(name, _def)| name).collect();
+    swept_names.sort();
+
+    let mut combinations: Vec<DataConstantCombination> = vec![vec![]];
+    for name in swept_names {
+      let values = self.data_constants[name].expand_values().map_err(|e| format!("data_constants.{}: {}", name, e))?;
+      let mut next_combinations: Vec<DataConstantCombination> = Vec::with_capacity(combinations.len() * values.len());
+      for combination in combinations.iter() {
+        for value in values.iter() {
+          let mut c = combination.clone();
+          c.push((name.to_string(), value.clone()));
+          next_combinations.push(c);
+        }
+      }
+      combinations = next_combinations;
+    }
 
+    Ok(combinations)
+  }
 
-#[derive(Default, Debug, Clone, serde::Serialize)]
-#[serde(untagged)]
+  /// Index of event_type in event_types
+  pub fn event_type_id(&self, event_type: &str) -> Option<u32> {
+    self.event_types.iter().position(|t| t == event_type).map(|i| i as u32)
+  }
+
+  /// Value of a reserved kernel argument at the start of sim_step_i, or None if name is not reserved.
+  /// Kernel scalar arguments with these names are supplied by apollon and updated before every step:
+  ///   sim_step: index of the current step, sim_time: sim_step * dt (or the time of the current event batch), dt: SimControl.dt, entity_count: number of live entities,
+  ///   entity_capacity: number of entities the column buffers hold, random_seed: SimControl.random_seed, for use with apollon_rng.h,
+  ///   grid_width, grid_height, grid_cell_size, grid_origin_x & grid_origin_y: the shape of spatial_index, for use with apollon_grid.h,
+  ///   post_event_capacity: SimControl.event_buffer_capacity in an event-driven simulation, for use with apollon_events.h
+  pub fn reserved_arg_value(&self, name: &str, sim_step_i: u64, sim_time: f64, num_entities: usize, entity_capacity: usize) -> Option<Value> {
+    match name {
+      "sim_step"     => Some(Value::Integer(sim_step_i as i64)),
+      "sim_time"     => Some(Value::Double(sim_time)),
+      "post_event_capacity" if self.mode == SimulationMode::Event => Some(Value::Integer(self.event_buffer_capacity as i64)),
+      "dt"           => Some(Value::Double(self.dt)),
+      "entity_count" => Some(Value::Integer(num_entities as i64)),
+      "entity_capacity" => Some(Value::Integer(entity_capacity as i64)),
+      "grid_width" | "grid_height" | "grid_cell_size" | "grid_origin_x" | "grid_origin_y" => {
+        let spatial_index = self.spatial_index.as_ref()?;
+        let (grid_width, grid_height) = spatial_index.grid_dims(self);
+        match name {
+          "grid_width"     => Some(Value::Integer(grid_width as i64)),
+          "grid_height"    => Some(Value::Integer(grid_height as i64)),
+          "grid_cell_size" => Some(Value::Double(spatial_index.cell_size)),
+          "grid_origin_x"  => Some(Value::Double(spatial_index.origin[0])),
+          _                => Some(Value::Double(spatial_index.origin[1])),
+        }
+      }
+      "random_seed"  => Some(Value::Integer(self.random_seed as i64)),
+      _ => None,
+    }
+  }
+
+  /// Column declarations of table; [schema] for the primary table, the table's own schema for a [[table]]
+  pub fn table_schema(&self, table: &str) -> &HashMap<String, ColumnSchema> {
+    match self.tables.iter().find(|t| t.name == table && table != self.table_name) {
+      Some(entity_table) => &entity_table.schema,
+      None => &self.schema,
+    }
+  }
+
+  /// True if kernel arguments named name are supplied by apollon (see reserved_arg_value), so data constants may not use it
+  pub fn is_reserved_arg_name(&self, name: &str) -> bool {
+    self.reserved_arg_value(name, 0, 0.0, 0, 0).is_some()
+  }
+
+  /// Names of every entity table, the primary table first
+  pub fn table_names(&self) -> Vec<&str> {
+    let mut names: Vec<&str> = vec![self.table_name.as_str()];
+    names.extend(self.tables.iter().map(|t| t.name.as_str()));
+    names
+  }
+
+  /// True if entities of table may be spawned & removed during the run
+  pub fn has_dynamic_population(&self, table: &str) -> bool {
+    if table == self.table_name {
+      self.max_entities > 0
+    }
+    else {
+      self.tables.iter().any(|t| t.name == table && t.max_entities > 0)
+    }
+  }
+
+  /// Number of entities the column buffers of table hold, given its number of T=0 entities
+  pub fn table_capacity(&self, table: &str, num_t0_entities: usize) -> usize {
+    let max_entities = if table == self.table_name {
+      self.max_entities
+    }
+    else {
+      self.tables.iter().find(|t| t.name == table).map(|t| t.max_entities).unwrap_or(0)
+    };
+    std::cmp::max(max_entities, num_t0_entities)
+  }
+
+  /// Name of the device buffer holding column of table; columns of the primary table keep their plain names.
+  pub fn qualified_column_name(&self, table: &str, column: &str) -> String {
+    if table == self.table_name {
+      column.to_string()
+    }
+    else {
+      format!("{}.{}", table, column)
+    }
+  }
+
+  /// Splits "<table>.<column>" into its table & column if <table> names an entity table;
+  /// anything else is a column of the primary table.
+  pub fn split_qualified_column_name<'a>(&'a self, name: &'a str) -> (&'a str, &'a str) {
+    if let Some((table, column)) = name.split_once('.') {
+      if self.table_names().contains(&table) {
+        return (table, column);
+      }
+    }
+    (self.table_name.as_str(), name)
+  }
+
+  /// Returns a copy of self with every swept data constant pinned to the single value in combination.
+  pub fn with_data_constants(&self, combination: &[(String, Value)]) -> SimControl {
+    let mut sc = self.clone();
+    for (name, value) in combination.iter() {
+      sc.data_constants.insert(name.to_string(), DataConstantDef::Single(value.clone()));
+    }
+    sc
+  }
+}
+
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
 pub enum ValueType {
   Uint8,
   Uint16,
@@ -232,6 +1212,9 @@ pub enum ValueType {
   Float32,
   #[default]
   Float64,
+
+  /// Only meaningful for host-side data such as names & colors; String columns cannot be bound to kernel arguments.
+  String,
 }
 
 impl ValueType {
@@ -248,6 +1231,11 @@ impl ValueType {
       "u32" => Some(ValueType::Uint32),
       "u64" => Some(ValueType::Uint64),
 
+      "uchar"  => Some(ValueType::Uint8),
+      "ushort" => Some(ValueType::Uint16),
+      "uint"   => Some(ValueType::Uint32),
+      "ulong"  => Some(ValueType::Uint64),
+
       "int8"  => Some(ValueType::Int8),
       "int16" => Some(ValueType::Int16),
       "int32" => Some(ValueType::Int32),
@@ -258,18 +1246,104 @@ impl ValueType {
       "i32" => Some(ValueType::Int32),
       "i64" => Some(ValueType::Int64),
 
+      "char"  => Some(ValueType::Int8),
+      "short" => Some(ValueType::Int16),
+      "int"   => Some(ValueType::Int32),
+      "long"  => Some(ValueType::Int64),
+
       "float"   => Some(ValueType::Float32),
       "float32" => Some(ValueType::Float32),
       "f32"     => Some(ValueType::Float32),
 
-      "double"   => Some(ValueType::Float32),
-      "f64"      => Some(ValueType::Float32),
+      "double"   => Some(ValueType::Float64),
+      "float64"  => Some(ValueType::Float64),
+      "f64"      => Some(ValueType::Float64),
+
+      "string" => Some(ValueType::String),
+      "str"    => Some(ValueType::String),
 
       unk_val => {
         None
       },
     }
   }
+
+  /// The canonical spelling of this type, as accepted by maybe_from_str.
+  pub fn name(&self) -> &'static str {
+    match self {
+      ValueType::Uint8   => "u8",
+      ValueType::Uint16  => "u16",
+      ValueType::Uint32  => "u32",
+      ValueType::Uint64  => "u64",
+      ValueType::Int8    => "i8",
+      ValueType::Int16   => "i16",
+      ValueType::Int32   => "i32",
+      ValueType::Int64   => "i64",
+      ValueType::Float32 => "f32",
+      ValueType::Float64 => "f64",
+      ValueType::String  => "string",
+    }
+  }
+
+  /// The OpenCL C scalar type holding this type, or None for String.
+  pub fn cl_type_name(&self) -> Option<&'static str> {
+    match self {
+      ValueType::Uint8   => Some("uchar"),
+      ValueType::Uint16  => Some("ushort"),
+      ValueType::Uint32  => Some("uint"),
+      ValueType::Uint64  => Some("ulong"),
+      ValueType::Int8    => Some("char"),
+      ValueType::Int16   => Some("short"),
+      ValueType::Int32   => Some("int"),
+      ValueType::Int64   => Some("long"),
+      ValueType::Float32 => Some("float"),
+      ValueType::Float64 => Some("double"),
+      ValueType::String  => None,
+    }
+  }
+
+  pub fn is_integer(&self) -> bool {
+    !matches!(self, ValueType::Float32 | ValueType::Float64 | ValueType::String)
+  }
+
+  /// Converts v to the Value variant used for this type, failing if v cannot be represented by it (ie "abc" as an i32, or 300 as a u8).
+  pub fn coerce(&self, v: &Value) -> Result<Value, Box<dyn std::error::Error>> {
+    match self {
+      ValueType::String => Ok(Value::String(v.to_string())),
+      ValueType::Float32 | ValueType::Float64 => Ok(Value::Double(v.to_f64()?)),
+      _ => {
+        let i64_val = match v {
+          Value::Double(d) if d.fract() != 0.0 => {
+            return Err(format!("{} is not a whole number and cannot be stored as {}", d, self.name()).into());
+          }
+          _ => v.to_i64()?,
+        };
+        let (min, max): (i128, i128) = match self {
+          ValueType::Uint8  => (0, u8::MAX as i128),
+          ValueType::Uint16 => (0, u16::MAX as i128),
+          ValueType::Uint32 => (0, u32::MAX as i128),
+          ValueType::Uint64 => (0, i64::MAX as i128), // Value::Integer is an i64
+          ValueType::Int8   => (i8::MIN as i128, i8::MAX as i128),
+          ValueType::Int16  => (i16::MIN as i128, i16::MAX as i128),
+          ValueType::Int32  => (i32::MIN as i128, i32::MAX as i128),
+          _                 => (i64::MIN as i128, i64::MAX as i128),
+        };
+        if (i64_val as i128) < min || (i64_val as i128) > max {
+          return Err(format!("{} is out of range for {}", i64_val, self.name()).into());
+        }
+        Ok(Value::Integer(i64_val))
+      }
+    }
+  }
+}
+
+impl serde::Serialize for ValueType {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: serde::Serializer,
+  {
+    serializer.serialize_str(self.name())
+  }
 }
 
 
@@ -283,7 +1357,7 @@ impl<'de> serde::Deserialize<'de> for ValueType {
             type Value = ValueType;
 
             fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-                write!(f, "A String value of u8, u16, u32, u64, i8, i16, i32, i64, f32, f64 or any of their aliases: uint8, uint16, uint32, uint64, int8, int16, int32, int64, float, float32 or double.")
+                write!(f, "A String value of u8, u16, u32, u64, i8, i16, i32, i64, f32, f64, string or any of their aliases: uint8, uint16, uint32, uint64, int8, int16, int32, int64, float, float32, float64, double, the OpenCL names uchar, ushort, uint, ulong, char, short, int, long, or str.")
             }
 
             fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
@@ -430,6 +1504,367 @@ impl<'de> serde::Deserialize<'de> for Value {
 
 
 
+/// A data constant as written in a simcontrol file; either a single value,
+/// an inclusive numeric range like `{ from = 0.02, to = 0.2, step = 0.02 }`,
+/// a list of values like `[0.05, 0.1, 0.2]`,
+/// a schedule of [step, value] keyframes like `[[0, 0.05], [500, 0.2]]` (see DataConstantSchedule),
+/// or a lookup table like `{ array = [0.1, 0.4, 0.9] }` or `{ file = "drag.csv", columns = ["altitude", "drag"] }` (see ArrayConstant).
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(untagged)]
+pub enum DataConstantDef {
+  Single(Value),
+  Range { from: f64, to: f64, step: f64 },
+  List(Vec<Value>),
+  Schedule(DataConstantSchedule),
+  Array(ArrayConstant),
+}
+
+/// Elements of an ArrayConstant; a list of lists is a 2D table of rows.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum ArrayValues {
+  Flat(Vec<Value>),
+  Rows(Vec<Vec<Value>>),
+}
+
+impl Default for ArrayValues {
+  fn default() -> Self {
+    ArrayValues::Flat(vec![])
+  }
+}
+
+/// A data constant holding a 1D or 2D array, which kernels bind as a read-only buffer (ie `global const float* drag_table`
+/// or `constant float* drag_table`) along with the scalars <name>_len (number of rows) & <name>_cols (elements per row).
+/// src/apollon_lut.cl has interpolation helpers for such tables.
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct ArrayConstant {
+  /// Inline elements; filled from file by utils::load_array_constant_files otherwise
+  pub array: ArrayValues,
+
+  /// OpenCL C element type; kernels must declare the argument with this type. Defaults to the kernel's declaration.
+  #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+  pub cl_type: Option<String>,
+
+  /// A data file (.csv, .json, .toml) whose records become the rows of the array
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file: Option<std::path::PathBuf>,
+
+  /// Columns of file forming each row, in order; may be omitted if file has a single column.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub columns: Vec<String>,
+}
+
+impl ArrayConstant {
+  pub fn rows(&self) -> usize {
+    match &self.array {
+      ArrayValues::Flat(values) => values.len(),
+      ArrayValues::Rows(rows) => rows.len(),
+    }
+  }
+
+  pub fn cols(&self) -> usize {
+    match &self.array {
+      ArrayValues::Flat(_) => 1,
+      ArrayValues::Rows(rows) => rows.first().map(|row| row.len()).unwrap_or(0),
+    }
+  }
+
+  /// Every element in row-major order
+  pub fn flat_values(&self) -> Vec<Value> {
+    match &self.array {
+      ArrayValues::Flat(values) => values.clone(),
+      ArrayValues::Rows(rows) => rows.iter().flat_map(|row| row.iter().cloned()).collect(),
+    }
+  }
+
+  /// Errors unless every row has the same length & every element is a number
+  pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    if let ArrayValues::Rows(rows) = &self.array {
+      if rows.iter().any(|row| row.len() != self.cols()) {
+        return Err("every row of an array must have the same number of elements".into());
+      }
+    }
+    if self.flat_values().iter().any(|v| matches!(v, Value::String(_))) {
+      return Err("array elements must be numbers".into());
+    }
+    Ok(())
+  }
+}
+
+impl DataConstantDef {
+  pub fn as_single(&self) -> Option<&Value> {
+    match self {
+      DataConstantDef::Single(v) => Some(v),
+      _ => None,
+    }
+  }
+
+  /// True for ranges & lists, which main_async expands into one run per value
+  pub fn is_swept(&self) -> bool {
+    matches!(self, DataConstantDef::Range { .. } | DataConstantDef::List(_))
+  }
+
+  /// The value a kernel sees at sim_step_i; None for swept constants, which must be pinned to a single value first.
+  pub fn value_at_step(&self, sim_step_i: u64) -> Option<Value> {
+    match self {
+      DataConstantDef::Single(v) => Some(v.clone()),
+      DataConstantDef::Schedule(schedule) => Some(schedule.value_at_step(sim_step_i)),
+      _ => None,
+    }
+  }
+
+  /// Returns every value this constant takes during a parameter sweep.
+  pub fn expand_values(&self) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+    match self {
+      DataConstantDef::Single(v) => Ok(vec![v.clone()]),
+      DataConstantDef::Schedule(_) => Err("a schedule changes a constant during a run and cannot be swept".into()),
+      DataConstantDef::Array(_) => Err("an array is passed to kernels whole and cannot be swept".into()),
+      DataConstantDef::List(values) => {
+        if values.is_empty() {
+          return Err("a list of values must contain at least one value".into());
+        }
+        Ok(values.clone())
+      }
+      DataConstantDef::Range { from, to, step } => {
+        if *step == 0.0 || step.is_nan() || (to - from).signum() * step.signum() < 0.0 {
+          return Err(format!("step={} never reaches to={} starting from from={}", step, to, from).into());
+        }
+        // The small epsilon keeps floating point error from dropping the final value, ie 0.02 + 9*0.02 == 0.2
+        let num_values = ((to - from) / step + 1.0e-9).floor() as usize + 1;
+        // Values are rounded to the precision from & step are written with, so 0.02 + 5*0.02 is 0.12 rather than 0.12000000000000001
+        let scale = 10f64.powi(std::cmp::max(decimal_places(*from), decimal_places(*step)) as i32);
+        let mut values = Vec::with_capacity(num_values);
+        for i in 0..num_values {
+          values.push(Value::Double(((from + (i as f64 * step)) * scale).round() / scale));
+        }
+        Ok(values)
+      }
+    }
+  }
+}
+
+/// Number of digits after the decimal point in the shortest representation of v, up to 15
+fn decimal_places(v: f64) -> u32 {
+  let v_s = format!("{}", v);
+  let num_places = v_s.split_once('.').map(|(_int, frac)| frac.len()).unwrap_or(0);
+  std::cmp::min(num_places, 15) as u32
+}
+
+impl<'de> serde::Deserialize<'de> for DataConstantDef {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        struct DataConstantDefVisitor;
+        impl<'de> serde::de::Visitor<'de> for DataConstantDefVisitor {
+            type Value = DataConstantDef;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "An Integer, Double, or String value, a list of values, or a range like {{ from = 0.0, to = 1.0, step = 0.1 }}")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            {
+                Ok( DataConstantDef::Single(Value::from_str(v)) )
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+            {
+                Ok( DataConstantDef::Single(Value::Integer(v)) )
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+            {
+                Ok( DataConstantDef::Single(Value::Integer(v as i64)) )
+            }
+
+            fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
+            {
+                Ok( DataConstantDef::Single(Value::Double(v)) )
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+              where
+              A: serde::de::SeqAccess<'de>,
+            {
+              // A list of values is a sweep, a list of [step, value] pairs is a schedule
+              let mut values: Vec<Value> = vec![];
+              let mut keyframes: Vec<(u64, Value)> = vec![];
+              while let Some(elm) = seq.next_element::<DataConstantListElement>()? {
+                match elm {
+                  DataConstantListElement::Value(v) => values.push(v),
+                  DataConstantListElement::Keyframe(step, v) => keyframes.push((step, v)),
+                }
+              }
+              if !values.is_empty() && !keyframes.is_empty() {
+                return Err(serde::de::Error::custom("a list must hold either values or [step, value] keyframes, not both"));
+              }
+              if !keyframes.is_empty() {
+                return DataConstantSchedule::new(keyframes, ScheduleInterpolation::Step).map(DataConstantDef::Schedule).map_err(serde::de::Error::custom);
+              }
+              Ok( DataConstantDef::List(values) )
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+              where
+              A: serde::de::MapAccess<'de>,
+            {
+              let (mut from, mut to, mut step) = (None, None, None);
+              let (mut keyframes, mut interpolation) = (None, None);
+              let mut array: Option<ArrayConstant> = None;
+              while let Some(key) = map.next_key::<String>()? {
+                match key.as_str() {
+                  "from" => { from = Some(map.next_value::<f64>()?); }
+                  "to"   => { to   = Some(map.next_value::<f64>()?); }
+                  "step" => { step = Some(map.next_value::<f64>()?); }
+                  "keyframes"     => { keyframes     = Some(map.next_value::<Vec<(u64, Value)>>()?); }
+                  "interpolation" => { interpolation = Some(map.next_value::<ScheduleInterpolation>()?); }
+                  "array"   => { array.get_or_insert_with(ArrayConstant::default).array   = map.next_value::<ArrayValues>()?; }
+                  "type"    => { array.get_or_insert_with(ArrayConstant::default).cl_type = Some(map.next_value::<String>()?); }
+                  "file"    => { array.get_or_insert_with(ArrayConstant::default).file    = Some(map.next_value::<std::path::PathBuf>()?); }
+                  "columns" => { array.get_or_insert_with(ArrayConstant::default).columns = map.next_value::<Vec<String>>()?; }
+                  unk => {
+                    return Err(serde::de::Error::unknown_field(unk, &["from", "to", "step", "keyframes", "interpolation", "array", "type", "file", "columns"]));
+                  }
+                }
+              }
+              if let Some(keyframes) = keyframes {
+                return DataConstantSchedule::new(keyframes, interpolation.unwrap_or_default()).map(DataConstantDef::Schedule).map_err(serde::de::Error::custom);
+              }
+              if let Some(array) = array {
+                if array.file.is_none() {
+                  array.validate().map_err(serde::de::Error::custom)?;
+                }
+                return Ok( DataConstantDef::Array(array) );
+              }
+              match (from, to, step) {
+                (Some(from), Some(to), Some(step)) => Ok( DataConstantDef::Range { from, to, step } ),
+                (None, _, _) => Err(serde::de::Error::missing_field("from")),
+                (_, None, _) => Err(serde::de::Error::missing_field("to")),
+                (_, _, None) => Err(serde::de::Error::missing_field("step")),
+              }
+            }
+        }
+
+        deserializer.deserialize_any(DataConstantDefVisitor)
+    }
+}
+
+
+
+/// Keyframes of a time-varying data constant; kernels bound to it see value_at_step of the current step.
+/// Declared as `[[step, value], ...]` (piecewise-constant) or `{ keyframes = [[step, value], ...], interpolation = "linear" }`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DataConstantSchedule {
+  /// Sorted by step
+  pub keyframes: Vec<(u64, Value)>,
+  pub interpolation: ScheduleInterpolation,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduleInterpolation {
+  /// Each keyframe's value holds until the next keyframe
+  #[default]
+  Step,
+  /// Values are linearly interpolated between keyframes
+  Linear,
+}
+
+impl DataConstantSchedule {
+  pub fn new(mut keyframes: Vec<(u64, Value)>, interpolation: ScheduleInterpolation) -> Result<DataConstantSchedule, String> {
+    if keyframes.is_empty() {
+      return Err("a schedule must contain at least one [step, value] keyframe".to_string());
+    }
+    if keyframes.iter().any(|(_step, v)| matches!(v, Value::String(_))) {
+      return Err("schedule keyframe values must be numeric".to_string());
+    }
+    keyframes.sort_by_key(|(step, _v)| *step);
+    Ok(DataConstantSchedule {
+      keyframes,
+      interpolation,
+    })
+  }
+
+  /// Before the first keyframe its value is used, and after the last keyframe the last value holds.
+  pub fn value_at_step(&self, sim_step_i: u64) -> Value {
+    let next_i = self.keyframes.partition_point(|(step, _v)| *step <= sim_step_i);
+    if next_i == 0 {
+      return self.keyframes[0].1.clone();
+    }
+    let (prev_step, prev_v) = &self.keyframes[next_i - 1];
+    if next_i >= self.keyframes.len() || self.interpolation == ScheduleInterpolation::Step {
+      return prev_v.clone();
+    }
+    let (next_step, next_v) = &self.keyframes[next_i];
+    match (prev_v.to_f64(), next_v.to_f64()) {
+      (Ok(prev_f), Ok(next_f)) => {
+        let frac = (sim_step_i - prev_step) as f64 / (next_step - prev_step) as f64;
+        Value::Double(prev_f + (next_f - prev_f) * frac)
+      }
+      _ => prev_v.clone(),
+    }
+  }
+}
+
+/// One element of a list in a data constant definition; a bare value or a [step, value] keyframe.
+enum DataConstantListElement {
+  Value(Value),
+  Keyframe(u64, Value),
+}
+
+impl<'de> serde::Deserialize<'de> for DataConstantListElement {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        struct ElementVisitor;
+        impl<'de> serde::de::Visitor<'de> for ElementVisitor {
+            type Value = DataConstantListElement;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "An Integer, Double, or String value, or a [step, value] keyframe")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            {
+                Ok( DataConstantListElement::Value(Value::from_str(v)) )
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+            {
+                Ok( DataConstantListElement::Value(Value::Integer(v)) )
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+            {
+                Ok( DataConstantListElement::Value(Value::Integer(v as i64)) )
+            }
+
+            fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
+            {
+                Ok( DataConstantListElement::Value(Value::Double(v)) )
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+              where
+              A: serde::de::SeqAccess<'de>,
+            {
+              let step = seq.next_element::<u64>()?.ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+              let value = seq.next_element::<Value>()?.ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+              if seq.next_element::<Value>()?.is_some() {
+                return Err(serde::de::Error::invalid_length(3, &self));
+              }
+              Ok( DataConstantListElement::Keyframe(step, value) )
+            }
+        }
+
+        deserializer.deserialize_any(ElementVisitor)
+    }
+}
+
+
+
 #[derive(Debug, Clone, serde::Serialize)]
 #[serde(untagged)]
 pub enum RWColumn {
@@ -485,7 +1920,14 @@ impl<'de> serde::Deserialize<'de> for RWColumn {
 pub struct DataConstantValue {
   pub name: String,
   pub v_type: ValueType,
+  /// For scheduled constants this is the value at step 0
   pub value: Value,
+  /// Set when the third element is a schedule of [step, value] keyframes rather than a single value
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub schedule: Option<DataConstantSchedule>,
+  /// Set when the third element is an array like `{ array = [0.1, 0.4] }`; its type defaults to v_type.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub array: Option<ArrayConstant>,
 }
 
 
@@ -507,11 +1949,24 @@ impl<'de> serde::Deserialize<'de> for DataConstantValue {
               A: serde::de::SeqAccess<'de>,
 
             {
-              if let (Some(name), Some(v_type), Some(value)) = (seq.next_element::<String>()?, seq.next_element::<ValueType>()?, seq.next_element::<Value>()?) {
+              if let (Some(name), Some(v_type), Some(value_def)) = (seq.next_element::<String>()?, seq.next_element::<ValueType>()?, seq.next_element::<DataConstantDef>()?) {
+                let (value, schedule, array) = match value_def {
+                  DataConstantDef::Single(value) => (value, None, None),
+                  DataConstantDef::Schedule(schedule) => (schedule.value_at_step(0), Some(schedule), None),
+                  DataConstantDef::Array(mut array) => {
+                    if array.cl_type.is_none() {
+                      array.cl_type = v_type.cl_type_name().map(str::to_string);
+                    }
+                    (Value::Integer(0), None, Some(array))
+                  }
+                  _ => return Err(serde::de::Error::custom(format!("Kernel data constant {} must be a single value, a schedule or an array; ranges and lists of values may only be swept from the simcontrol file", name))),
+                };
                 Ok(DataConstantValue {
-                  name: name,
-                  v_type: v_type,
-                  value: value,
+                  name,
+                  v_type,
+                  value,
+                  schedule,
+                  array,
                 })
               }
               else {
@@ -530,6 +1985,60 @@ impl<'de> serde::Deserialize<'de> for DataConstantValue {
 #[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
 pub struct CL_Kernels {
   pub kernel: Vec<CL_Kernel>,
+
+  /// Column statistics computed on-device after kernels run; see CL_Reduction
+  #[serde(default = "serde_default_reductions")]
+  pub reduction: Vec<ReductionDef>,
+
+  /// Shared state buffers kernels bind by name
+  #[serde(default = "serde_default_global_buffers")]
+  pub global_buffer: Vec<GlobalBufferDef>,
+}
+
+/// A device buffer of explicit length & type shared by every kernel declaring an argument of its name (or mapping one to it
+/// with colmap), eg `[[global_buffer]] name = "collision_count", type = "uint", len = 1, reset = "every_step"`.
+/// Kernels may update it with OpenCL atomics; its final values are written to SimControl.global_buffers_file_path.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct GlobalBufferDef {
+  pub name: String,
+
+  /// OpenCL C element type, ie "uint" or "float"; kernels must declare the argument with exactly this type.
+  #[serde(rename = "type")]
+  pub cl_type: String,
+
+  #[serde(default = "serde_default_global_buffer_len")]
+  pub len: usize,
+
+  #[serde(default)]
+  pub reset: GlobalBufferReset,
+
+  /// Initial values; a single value fills every element, and missing ones are 0.
+  #[serde(default)]
+  pub init: Vec<Value>,
+}
+
+fn serde_default_global_buffers() -> Vec<GlobalBufferDef> { vec![] }
+fn serde_default_global_buffer_len() -> usize { 1 }
+
+impl GlobalBufferDef {
+  pub fn initial_values(&self) -> Vec<Value> {
+    if self.init.len() == 1 {
+      return vec![self.init[0].clone(); self.len];
+    }
+    let mut values = self.init.clone();
+    values.resize(self.len, Value::Integer(0));
+    values
+  }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GlobalBufferReset {
+  /// Values persist across steps
+  #[default]
+  Never,
+  /// Values return to init before every step's kernels run
+  EveryStep,
 }
 
 #[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
@@ -544,10 +2053,19 @@ pub struct CL_Kernel {
   pub data_columns_processed: Vec<RWColumn>,
   */
 
+  /// Entity table whose entities this kernel runs over & whose columns unqualified arguments bind to;
+  /// if unspecified the primary table (SimControl.table_name) is used.
+  #[serde(default)]
+  pub table: Option<String>,
+
+  /// Argument name -> column name. A value like "sensors.X0" binds a column of another entity table,
+  /// which must be declared `const` as other tables' columns are read-only to this kernel.
+  /// Vector arguments (ie `global float2* pos`) map to a list of one column per element, ie `pos = ["X0", "Y0"]`.
   #[serde(default = "serde_default_colmap")]
-  pub colmap: HashMap<String, String>,
+  pub colmap: HashMap<String, ColumnMapping>,
 
   /// Contains the same keys as colmap; is expected to be constructed at run-time by parsing colmap and the kernel source code.
+  /// Entries given in the kernel file are checked against the argument types of the compiled kernel by load_program.
   #[serde(default = "serde_default_typemap")]
   pub typemap: HashMap<String, ValueType>,
 
@@ -560,6 +2078,46 @@ pub struct CL_Kernel {
   #[serde(default = "serde_empty_string")]
   pub cl_program_compiler_options: String,
 
+  /// In an event-driven simulation the kernel runs for every batch holding events of this type, over only those events;
+  /// see src/apollon_events.cl. Kernels without on_event do not run in an event-driven simulation except with run_once_at_start,
+  /// and kernels with on_event do not run in a time-stepped one.
+  #[serde(default)]
+  pub on_event: Option<String>,
+
+  /// Name of a simcontrol [[field]] whose cells the kernel runs over; unless global_work_size is given the NDRange is
+  /// [<field>_width, <field>_height], so get_global_id(0) & get_global_id(1) are the cell's column & row.
+  #[serde(default)]
+  pub field: Option<String>,
+
+  /// The kernel runs at steps start_step, start_step + every_n_steps, ... up to but excluding end_step.
+  /// 0 is treated as 1, ie every step.
+  #[serde(default = "serde_default_every_n_steps")]
+  pub every_n_steps: u64,
+
+  #[serde(default)]
+  pub start_step: u64,
+
+  /// If unspecified the kernel runs until the end of the simulation
+  #[serde(default)]
+  pub end_step: Option<u64>,
+
+  /// If true the kernel runs exactly once before the first step (ie to initialize columns) instead of on the step schedule above.
+  #[serde(default)]
+  pub run_once_at_start: bool,
+
+  /// Shape of the NDRange the kernel is enqueued over; one size per dimension (up to 3), each an integer or an expression
+  /// like "entities*entities" (see utils::eval_work_size_expr). Defaults to one work item per entity.
+  #[serde(default)]
+  pub global_work_size: Option<WorkSizeDef>,
+
+  /// Work-group shape; must have as many dimensions as global_work_size and evenly divide it.
+  /// If unspecified the OpenCL implementation picks one.
+  #[serde(default)]
+  pub local_work_size: Option<WorkSizeDef>,
+
+  #[serde(default)]
+  pub global_work_offset: Option<WorkSizeDef>,
+
   #[serde(skip_serializing, skip_deserializing)]
   pub cl_device_program: Option<opencl3::program::Program>,
 
@@ -573,28 +2131,183 @@ pub struct CL_Kernel {
 }
 
 
-fn serde_default_colmap() -> HashMap<String, String> { HashMap::<String, String>::new() }
+fn serde_default_colmap() -> HashMap<String, ColumnMapping> { HashMap::<String, ColumnMapping>::new() }
 fn serde_default_typemap() -> HashMap<String, ValueType> { HashMap::<String, ValueType>::new() }
 
 //fn serde_default_data_columns_processed() -> Vec<RWColumn> { vec![] }
 fn serde_default_data_constants() -> Vec<DataConstantValue> { vec![] }
 fn serde_empty_map_str_valtype() -> HashMap<String, ValueType> { HashMap::<String, ValueType>::new() }
+fn serde_default_every_n_steps() -> u64 { 1 }
+
+
+
+/// A colmap value; either a single column or the columns packed into the elements of a vector argument, in element order.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum ColumnMapping {
+  Column(String),
+  Components(Vec<String>),
+}
+
+impl ColumnMapping {
+  /// The mapped column; None for a list of components
+  pub fn column(&self) -> Option<&String> {
+    match self {
+      ColumnMapping::Column(column) => Some(column),
+      ColumnMapping::Components(_) => None,
+    }
+  }
+}
+
+
+/// A work size in a kernel file; either a single dimension (`"entities"`, `1024`) or up to 3 dimensions (`[512, 512]`).
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum WorkSizeDef {
+  Scalar(WorkSizeExpr),
+  Dims(Vec<WorkSizeExpr>),
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum WorkSizeExpr {
+  Number(u64),
+  Expression(String),
+}
+
+impl WorkSizeDef {
+  /// Evaluates every dimension; lookup resolves identifiers used in expressions.
+  pub fn evaluate(&self, lookup: &dyn Fn(&str) -> Option<u64>) -> Result<Vec<usize>, Box<dyn std::error::Error>> {
+    let exprs: Vec<&WorkSizeExpr> = match self {
+      WorkSizeDef::Scalar(e) => vec![e],
+      WorkSizeDef::Dims(v) => v.iter().collect(),
+    };
+    if exprs.is_empty() || exprs.len() > 3 {
+      return Err(format!("a work size must have 1 to 3 dimensions, got {}", exprs.len()).into());
+    }
+    let mut dims = vec![];
+    for e in exprs {
+      dims.push(match e {
+        WorkSizeExpr::Number(n) => *n as usize,
+        WorkSizeExpr::Expression(expr) => utils::eval_work_size_expr(expr, lookup)? as usize,
+      });
+    }
+    Ok(dims)
+  }
+}
 
+/// The evaluated NDRange of one kernel for one run
+#[derive(Debug, Clone, PartialEq)]
+pub struct CL_NDRange {
+  pub global: Vec<usize>,
+  pub local: Option<Vec<usize>>,
+  pub offset: Option<Vec<usize>>,
+}
 
+impl CL_NDRange {
+  pub fn num_work_items(&self) -> usize {
+    self.global.iter().product()
+  }
+}
 
 impl CL_Kernel {
+  /// Name of the entity table this kernel runs over
+  pub fn table_name<'a>(&'a self, simcontrol: &'a SimControl) -> &'a str {
+    self.table.as_deref().unwrap_or(simcontrol.table_name.as_str())
+  }
+
+  /// Evaluates global_work_size, local_work_size & global_work_offset and checks them against the device limits.
+  pub fn ndrange(
+    &self,
+    lookup: &dyn Fn(&str) -> Option<u64>,
+    num_entities: usize,
+    device: &opencl3::device::Device,
+  ) -> Result<CL_NDRange, Box<dyn std::error::Error>> {
+    let global = match (&self.global_work_size, &self.field) {
+      (Some(def), _) => def.evaluate(lookup).map_err(|e| format!("Kernel {} global_work_size: {}", &self.name, e))?,
+      (None, Some(field)) => {
+        let mut global: Vec<usize> = vec![];
+        for dimension in ["width", "height"] {
+          let size = lookup(&format!("{}_{}", field, dimension)).ok_or_else(|| format!("Kernel {} runs over field '{}', which is not declared", &self.name, field))?;
+          global.push(size as usize);
+        }
+        global
+      }
+      (None, None) => vec![num_entities],
+    };
+    let local = match &self.local_work_size {
+      Some(def) => Some(def.evaluate(lookup).map_err(|e| format!("Kernel {} local_work_size: {}", &self.name, e))?),
+      None => None,
+    };
+    let offset = match &self.global_work_offset {
+      Some(def) => Some(def.evaluate(lookup).map_err(|e| format!("Kernel {} global_work_offset: {}", &self.name, e))?),
+      None => None,
+    };
+
+    if let Some(ref offset) = offset {
+      if offset.len() != global.len() {
+        return Err(format!("Kernel {} has a {}-dimensional global_work_offset but a {}-dimensional global_work_size", &self.name, offset.len(), global.len()).into());
+      }
+    }
+    if let Some(ref local) = local {
+      if local.len() != global.len() {
+        return Err(format!("Kernel {} has a {}-dimensional local_work_size but a {}-dimensional global_work_size", &self.name, local.len(), global.len()).into());
+      }
+      let max_work_group_size = device.max_work_group_size()?;
+      let group_size: usize = local.iter().product();
+      if group_size < 1 || group_size > max_work_group_size {
+        return Err(format!("Kernel {} local_work_size {:?} has {} work items per group but the device allows 1 to {}", &self.name, local, group_size, max_work_group_size).into());
+      }
+      let max_work_item_sizes = device.max_work_item_sizes()?;
+      for d in 0..local.len() {
+        if let Some(max_size) = max_work_item_sizes.get(d) {
+          if local[d] > *max_size {
+            return Err(format!("Kernel {} local_work_size[{}]={} exceeds the device maximum of {}", &self.name, d, local[d], max_size).into());
+          }
+        }
+        if global[d] % local[d] != 0 {
+          return Err(format!("Kernel {} global_work_size[{}]={} is not a multiple of local_work_size[{}]={}", &self.name, d, global[d], d, local[d]).into());
+        }
+      }
+    }
+
+    Ok(CL_NDRange {
+      global,
+      local,
+      offset,
+    })
+  }
 
-  pub fn load_program(&mut self, cl_ctx: &opencl3::context::Context) -> Result<(), Box<dyn std::error::Error>>  {
+  /// True if this kernel's schedule includes sim_step_i; always false for run_once_at_start kernels.
+  pub fn runs_at_step(&self, sim_step_i: u64) -> bool {
+    if self.run_once_at_start || sim_step_i < self.start_step {
+      return false;
+    }
+    if let Some(end_step) = self.end_step {
+      if sim_step_i >= end_step {
+        return false;
+      }
+    }
+    (sim_step_i - self.start_step).is_multiple_of(std::cmp::max(1, self.every_n_steps))
+  }
+
+
+  pub fn load_program(&mut self, cl_ctx: &opencl3::context::Context, simcontrol: &SimControl) -> Result<(), Box<dyn std::error::Error>>  {
     let mut cl_compiler_options = self.cl_program_compiler_options.clone();
 
+    // Event type IDs for apollon_events.h
+    for (event_type_id, event_type) in simcontrol.event_types.iter().enumerate() {
+      cl_compiler_options = format!("{} -D APOLLON_EVENT_{}={}", &cl_compiler_options, event_type.to_uppercase(), event_type_id);
+    }
+
     if !cl_compiler_options.contains("-cl-kernel-arg-info") { // required to read arguments out of kernel
       cl_compiler_options = format!("{} -cl-kernel-arg-info", &cl_compiler_options);
     }
 
     self.cl_device_program = Some(
       opencl3::program::Program::create_and_build_from_source(
-        &cl_ctx,
-        &self.source,
+        cl_ctx,
+        &utils::expand_apollon_includes(&self.source),
         &cl_compiler_options
       )?
     );
@@ -604,12 +2317,30 @@ impl CL_Kernel {
       );
     }
 
-    /*
     if let Some(ref cl_device_kernel_ref) = self.cl_device_kernel {
       // Read kernel argument type data & convert to intermediate formats
+      self.cl_arg_types.clear();
+      for arg_i in 0..cl_device_kernel_ref.num_args()? {
+        let type_name = cl_device_kernel_ref.get_arg_type_name(arg_i)?;
+        let type_name = type_name.trim_end_matches('*');
+        if let Some(v_type) = ValueType::maybe_from_str(type_name) {
+          self.cl_arg_types.insert(cl_device_kernel_ref.get_arg_name(arg_i)?, v_type);
+        }
+      }
 
+      // typemap is a declaration of what the source is expected to contain; refuse to run a kernel that disagrees with it.
+      for (arg_name, expected_type) in self.typemap.iter() {
+        match self.cl_arg_types.get(arg_name) {
+          Some(actual_type) if actual_type == expected_type => { }
+          Some(actual_type) => {
+            return Err(format!("Kernel {} declares argument '{}' as {} but typemap expects {}", &self.name, arg_name, actual_type.name(), expected_type.name()).into());
+          }
+          None => {
+            return Err(format!("Kernel {} has a typemap entry for '{}' but no scalar argument named '{}'", &self.name, arg_name, arg_name).into());
+          }
+        }
+      }
     }
-    */
 
     Ok(())
   }
@@ -652,6 +2383,9 @@ pub enum CL_TaggedArgument {
 
   Float  (opencl3::types::cl_float),
   Double (opencl3::types::cl_double),
+
+  // A vector constant (ie float4) named by its CL vector type, holding the bytes the kernel receives; 3-element vectors are padded to 4
+  Vector (&'static str, Vec<u8>),
 }
 
 impl CL_TaggedArgument {
@@ -733,7 +2467,1303 @@ impl CL_TaggedArgument {
     }
   }
 
+  /// Builds a vector constant of the CL vector type named cl_vector_type_name (ie "float4") from one value per element.
+  pub fn from_vector_values(values: &[Value], cl_vector_type_name: &str) -> Result<CL_TaggedArgument, Box<dyn std::error::Error>> {
+    let (vector_type, element_type, width) = cl_vector_type(cl_vector_type_name).ok_or_else(|| format!("{} is not a CL vector type", cl_vector_type_name))?;
+    if values.len() != width {
+      return Err(format!("a {} holds {} elements, but {} values were given", vector_type, width, values.len()).into());
+    }
+    let mut bytes: Vec<u8> = vec![];
+    for v in values.iter() {
+      if let Value::String(_) = v {
+        return Err(format!("a {} cannot hold the string {:?}", vector_type, v).into());
+      }
+      bytes.extend(CL_TaggedArgument::from_value(v, element_type).scalar_ne_bytes());
+    }
+    let element_size = bytes.len() / width;
+    bytes.resize(element_size * cl_vector_stride(width), 0);
+    Ok(CL_TaggedArgument::Vector(vector_type, bytes))
+  }
+
+  /// The in-memory bytes of a scalar constant; empty for buffers & vectors
+  fn scalar_ne_bytes(&self) -> Vec<u8> {
+    match self {
+      CL_TaggedArgument::Uint8(a)  => a.to_ne_bytes().to_vec(),
+      CL_TaggedArgument::Uint16(a) => a.to_ne_bytes().to_vec(),
+      CL_TaggedArgument::Uint32(a) => a.to_ne_bytes().to_vec(),
+      CL_TaggedArgument::Uint64(a) => a.to_ne_bytes().to_vec(),
+      CL_TaggedArgument::Int8(a)   => a.to_ne_bytes().to_vec(),
+      CL_TaggedArgument::Int16(a)  => a.to_ne_bytes().to_vec(),
+      CL_TaggedArgument::Int32(a)  => a.to_ne_bytes().to_vec(),
+      CL_TaggedArgument::Int64(a)  => a.to_ne_bytes().to_vec(),
+      CL_TaggedArgument::Float(a)  => a.to_ne_bytes().to_vec(),
+      CL_TaggedArgument::Double(a) => a.to_ne_bytes().to_vec(),
+      _ => vec![],
+    }
+  }
+
+  /// Returns the OpenCL C scalar type name of a buffer's elements or of a constant, ie "float" for both FloatBuffer and Float;
+  /// vector constants return their vector type, ie "float4".
+  pub fn cl_type_name(&self) -> &'static str {
+    match self {
+      CL_TaggedArgument::Uint8Buffer(_)  | CL_TaggedArgument::Uint8(_)  => "uchar",
+      CL_TaggedArgument::Uint16Buffer(_) | CL_TaggedArgument::Uint16(_) => "ushort",
+      CL_TaggedArgument::Uint32Buffer(_) | CL_TaggedArgument::Uint32(_) => "uint",
+      CL_TaggedArgument::Uint64Buffer(_) | CL_TaggedArgument::Uint64(_) => "ulong",
+      CL_TaggedArgument::Int8Buffer(_)   | CL_TaggedArgument::Int8(_)   => "char",
+      CL_TaggedArgument::Int16Buffer(_)  | CL_TaggedArgument::Int16(_)  => "short",
+      CL_TaggedArgument::Int32Buffer(_)  | CL_TaggedArgument::Int32(_)  => "int",
+      CL_TaggedArgument::Int64Buffer(_)  | CL_TaggedArgument::Int64(_)  => "long",
+      CL_TaggedArgument::FloatBuffer(_)  | CL_TaggedArgument::Float(_)  => "float",
+      CL_TaggedArgument::DoubleBuffer(_) | CL_TaggedArgument::Double(_) => "double",
+      CL_TaggedArgument::Vector(vector_type, _bytes) => vector_type,
+    }
+  }
+
+  pub fn is_buffer(&self) -> bool {
+    matches!(self,
+      CL_TaggedArgument::Uint8Buffer(_)  | CL_TaggedArgument::Uint16Buffer(_) |
+      CL_TaggedArgument::Uint32Buffer(_) | CL_TaggedArgument::Uint64Buffer(_) |
+      CL_TaggedArgument::Int8Buffer(_)   | CL_TaggedArgument::Int16Buffer(_)  |
+      CL_TaggedArgument::Int32Buffer(_)  | CL_TaggedArgument::Int64Buffer(_)  |
+      CL_TaggedArgument::FloatBuffer(_)  | CL_TaggedArgument::DoubleBuffer(_)
+    )
+  }
+
+  /// True for buffers a kernel may write to, ie those allocated for arguments not declared `const`.
+  pub fn is_writable_buffer(&self) -> bool {
+    use opencl3::memory::ClMem;
+    let flags = match self {
+      CL_TaggedArgument::Uint8Buffer(b)  => b.flags(),
+      CL_TaggedArgument::Uint16Buffer(b) => b.flags(),
+      CL_TaggedArgument::Uint32Buffer(b) => b.flags(),
+      CL_TaggedArgument::Uint64Buffer(b) => b.flags(),
+      CL_TaggedArgument::Int8Buffer(b)   => b.flags(),
+      CL_TaggedArgument::Int16Buffer(b)  => b.flags(),
+      CL_TaggedArgument::Int32Buffer(b)  => b.flags(),
+      CL_TaggedArgument::Int64Buffer(b)  => b.flags(),
+      CL_TaggedArgument::FloatBuffer(b)  => b.flags(),
+      CL_TaggedArgument::DoubleBuffer(b) => b.flags(),
+      _ => return false,
+    };
+    match flags {
+      Ok(flags) => (flags & opencl3::memory::CL_MEM_READ_WRITE) != 0,
+      Err(_) => false,
+    }
+  }
+
+  /// Number of elements in a buffer; None for constants.
+  pub fn buffer_len(&self) -> Option<usize> {
+    use opencl3::memory::ClMem;
+    match self {
+      CL_TaggedArgument::Uint8Buffer(b)  => b.size().ok().map(|size| size / std::mem::size_of::<opencl3::types::cl_uchar>()),
+      CL_TaggedArgument::Uint16Buffer(b) => b.size().ok().map(|size| size / std::mem::size_of::<opencl3::types::cl_ushort>()),
+      CL_TaggedArgument::Uint32Buffer(b) => b.size().ok().map(|size| size / std::mem::size_of::<opencl3::types::cl_uint>()),
+      CL_TaggedArgument::Uint64Buffer(b) => b.size().ok().map(|size| size / std::mem::size_of::<opencl3::types::cl_ulong>()),
+      CL_TaggedArgument::Int8Buffer(b)   => b.size().ok().map(|size| size / std::mem::size_of::<opencl3::types::cl_char>()),
+      CL_TaggedArgument::Int16Buffer(b)  => b.size().ok().map(|size| size / std::mem::size_of::<opencl3::types::cl_short>()),
+      CL_TaggedArgument::Int32Buffer(b)  => b.size().ok().map(|size| size / std::mem::size_of::<opencl3::types::cl_int>()),
+      CL_TaggedArgument::Int64Buffer(b)  => b.size().ok().map(|size| size / std::mem::size_of::<opencl3::types::cl_long>()),
+      CL_TaggedArgument::FloatBuffer(b)  => b.size().ok().map(|size| size / std::mem::size_of::<opencl3::types::cl_float>()),
+      CL_TaggedArgument::DoubleBuffer(b) => b.size().ok().map(|size| size / std::mem::size_of::<opencl3::types::cl_double>()),
+      _ => None,
+    }
+  }
+
+  /// Blocking read of an entire buffer into a HostColumn; returns None for constants.
+  pub fn read_to_host(&self, queue: &opencl3::command_queue::CommandQueue, events: &[opencl3::types::cl_event]) -> Result<Option<HostColumn>, Box<dyn std::error::Error>> {
+    Ok(match self {
+      CL_TaggedArgument::Uint8Buffer(b)  => Some(HostColumn::Uint8(utils::read_cl_buffer(queue, b, events)?)),
+      CL_TaggedArgument::Uint16Buffer(b) => Some(HostColumn::Uint16(utils::read_cl_buffer(queue, b, events)?)),
+      CL_TaggedArgument::Uint32Buffer(b) => Some(HostColumn::Uint32(utils::read_cl_buffer(queue, b, events)?)),
+      CL_TaggedArgument::Uint64Buffer(b) => Some(HostColumn::Uint64(utils::read_cl_buffer(queue, b, events)?)),
+      CL_TaggedArgument::Int8Buffer(b)   => Some(HostColumn::Int8(utils::read_cl_buffer(queue, b, events)?)),
+      CL_TaggedArgument::Int16Buffer(b)  => Some(HostColumn::Int16(utils::read_cl_buffer(queue, b, events)?)),
+      CL_TaggedArgument::Int32Buffer(b)  => Some(HostColumn::Int32(utils::read_cl_buffer(queue, b, events)?)),
+      CL_TaggedArgument::Int64Buffer(b)  => Some(HostColumn::Int64(utils::read_cl_buffer(queue, b, events)?)),
+      CL_TaggedArgument::FloatBuffer(b)  => Some(HostColumn::Float(utils::read_cl_buffer(queue, b, events)?)),
+      CL_TaggedArgument::DoubleBuffer(b) => Some(HostColumn::Double(utils::read_cl_buffer(queue, b, events)?)),
+      _ => None,
+    })
+  }
+
+  /// Blocking write of host_column over the start of this buffer; host_column must have the same element type.
+  pub fn write_from_host(&mut self, queue: &opencl3::command_queue::CommandQueue, host_column: &HostColumn) -> Result<(), Box<dyn std::error::Error>> {
+    if host_column.is_empty() {
+      return Ok(());
+    }
+    let unused_write_event = unsafe {
+      match (self, host_column) {
+        (CL_TaggedArgument::Uint8Buffer(b),  HostColumn::Uint8(v))  => queue.enqueue_write_buffer(b, opencl3::types::CL_BLOCKING, 0, v, &[])?,
+        (CL_TaggedArgument::Uint16Buffer(b), HostColumn::Uint16(v)) => queue.enqueue_write_buffer(b, opencl3::types::CL_BLOCKING, 0, v, &[])?,
+        (CL_TaggedArgument::Uint32Buffer(b), HostColumn::Uint32(v)) => queue.enqueue_write_buffer(b, opencl3::types::CL_BLOCKING, 0, v, &[])?,
+        (CL_TaggedArgument::Uint64Buffer(b), HostColumn::Uint64(v)) => queue.enqueue_write_buffer(b, opencl3::types::CL_BLOCKING, 0, v, &[])?,
+        (CL_TaggedArgument::Int8Buffer(b),   HostColumn::Int8(v))   => queue.enqueue_write_buffer(b, opencl3::types::CL_BLOCKING, 0, v, &[])?,
+        (CL_TaggedArgument::Int16Buffer(b),  HostColumn::Int16(v))  => queue.enqueue_write_buffer(b, opencl3::types::CL_BLOCKING, 0, v, &[])?,
+        (CL_TaggedArgument::Int32Buffer(b),  HostColumn::Int32(v))  => queue.enqueue_write_buffer(b, opencl3::types::CL_BLOCKING, 0, v, &[])?,
+        (CL_TaggedArgument::Int64Buffer(b),  HostColumn::Int64(v))  => queue.enqueue_write_buffer(b, opencl3::types::CL_BLOCKING, 0, v, &[])?,
+        (CL_TaggedArgument::FloatBuffer(b),  HostColumn::Float(v))  => queue.enqueue_write_buffer(b, opencl3::types::CL_BLOCKING, 0, v, &[])?,
+        (CL_TaggedArgument::DoubleBuffer(b), HostColumn::Double(v)) => queue.enqueue_write_buffer(b, opencl3::types::CL_BLOCKING, 0, v, &[])?,
+        (tagged_argument, host_column) => return Err(format!("Cannot write a host column of {} values into a {} argument", host_column.cl_type_name(), tagged_argument.cl_type_name()).into()),
+      }
+    };
+    Ok(())
+  }
+
+  /// Sets this value as the next argument of exec_kernel.
+  ///
+  /// # Safety
+  /// The caller must ensure the argument type matches what the kernel declares at this position.
+  pub unsafe fn set_as_arg(&self, exec_kernel: &mut opencl3::kernel::ExecuteKernel) {
+    match self {
+      CL_TaggedArgument::Uint8Buffer(a)  => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Uint16Buffer(a) => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Uint32Buffer(a) => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Uint64Buffer(a) => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Int8Buffer(a)   => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Int16Buffer(a)  => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Int32Buffer(a)  => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Int64Buffer(a)  => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::FloatBuffer(a)  => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::DoubleBuffer(a) => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Uint8(a)        => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Uint16(a)       => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Uint32(a)       => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Uint64(a)       => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Int8(a)         => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Int16(a)        => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Int32(a)        => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Int64(a)        => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Float(a)        => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Double(a)       => {exec_kernel.set_arg(a);},
+      CL_TaggedArgument::Vector(_, bytes) => {
+        // The kernel only sees the argument's size & bytes, so a byte array of the vector's size stands in for it
+        match bytes.len() {
+          2   => set_bytes_as_arg::<2>(exec_kernel, bytes),
+          4   => set_bytes_as_arg::<4>(exec_kernel, bytes),
+          8   => set_bytes_as_arg::<8>(exec_kernel, bytes),
+          16  => set_bytes_as_arg::<16>(exec_kernel, bytes),
+          32  => set_bytes_as_arg::<32>(exec_kernel, bytes),
+          64  => set_bytes_as_arg::<64>(exec_kernel, bytes),
+          128 => set_bytes_as_arg::<128>(exec_kernel, bytes),
+          n => panic!("Logic error! A CL vector cannot be {} bytes", n),
+        }
+      },
+    }
+  }
+
+}
+
+
+/// Safety: as for CL_TaggedArgument::set_as_arg
+unsafe fn set_bytes_as_arg<const N: usize>(exec_kernel: &mut opencl3::kernel::ExecuteKernel, bytes: &[u8]) {
+  let mut arg = [0u8; N];
+  arg.copy_from_slice(bytes);
+  exec_kernel.set_arg(&arg);
+}
+
+
+/// Tracks columns which are bound as more than one CL type (ie `float* X0` in one kernel & `double* X0` in another)
+/// or packed into vector buffers (ie `float2* pos` of X0 & Y0), and which of those buffers hold the latest values.
+/// Indexes refer to the all_kernel_args vector built in main.rs.
+#[derive(Debug, Default)]
+pub struct CL_ColumnVariants {
+  /// Column name -> every all_kernel_args index holding that column; only names with 2+ buffer types are present.
+  pub variants: HashMap<String, Vec<usize>>,
+
+  /// Keyed by (src all_kernel_args idx, dst all_kernel_args idx)
+  pub conversion_kernels: HashMap<(usize, usize), opencl3::kernel::Kernel>,
+
+  /// Keyed by the all_kernel_args idx of every packed vector buffer
+  pub packed: HashMap<usize, CL_PackedColumns>,
+
+  /// fresh[akai] is false when another type of the same column, or a packed buffer holding it, has been written since akai was last synced.
+  pub fresh: Vec<bool>,
+}
+
+/// The kernels moving the elements of a packed vector buffer to & from the canonical buffers of its component columns
+#[derive(Debug)]
+pub struct CL_PackedColumns {
+  /// all_kernel_args index of each component column's canonical buffer, in element order
+  pub component_indicies: Vec<usize>,
+  /// Elements per entity in the packed buffer; see cl_vector_stride
+  pub stride: usize,
+  pub pack_kernel: opencl3::kernel::Kernel,
+  pub unpack_kernel: opencl3::kernel::Kernel,
+}
+
+impl CL_ColumnVariants {
+  /// Finds every column bound as several types & builds the conversion kernels between them.
+  /// Every buffer starts fresh because they were all uploaded from the same t0 data.
+  pub fn new(
+    all_kernel_args: &[CL_NamedTaggedArgument],
+    context: &opencl3::context::Context,
+    generated_programs: &mut utils::CL_ProgramCache,
+  ) -> Result<CL_ColumnVariants, Box<dyn std::error::Error>> {
+    let mut variants: HashMap<String, Vec<usize>> = HashMap::new();
+    for (akai, argument) in all_kernel_args.iter().enumerate() {
+      if argument.tagged_argument.is_buffer() {
+        variants.entry(argument.name.clone()).or_default().push(akai);
+      }
+    }
+    variants.retain(|_name, v| v.len() > 1);
+
+    let mut conversion_kernels = HashMap::new();
+    for (name, column_variants) in variants.iter() {
+      for src_akai in column_variants.iter() {
+        for dst_akai in column_variants.iter() {
+          if src_akai != dst_akai {
+            let (conv_k_name, conv_k_source) = utils::conversion_kernel_source(
+              all_kernel_args[*src_akai].tagged_argument.cl_type_name(), all_kernel_args[*dst_akai].tagged_argument.cl_type_name()
+            );
+            conversion_kernels.insert((*src_akai, *dst_akai), utils::build_generated_kernel(context, generated_programs, &conv_k_name, &conv_k_source)?);
+          }
+        }
+      }
+    }
+
+    let mut column_variants = CL_ColumnVariants {
+      variants,
+      conversion_kernels,
+      packed: HashMap::new(),
+      fresh: vec![true; all_kernel_args.len()],
+    };
+
+    for akai in 0..all_kernel_args.len() {
+      let components = &all_kernel_args[akai].components;
+      if components.is_empty() {
+        continue;
+      }
+      let component_indicies = components.iter().map(|component| column_variants.canonical_idx(all_kernel_args, component))
+        .collect::<Option<Vec<usize>>>()
+        .ok_or_else(|| format!("Logic error! A component of packed buffer {} has no buffer", &all_kernel_args[akai].name))?;
+      let component_cl_types: Vec<&str> = component_indicies.iter().map(|c| all_kernel_args[*c].tagged_argument.cl_type_name()).collect();
+      let stride = cl_vector_stride(components.len());
+      let [(pack_k_name, pack_k_source), (unpack_k_name, unpack_k_source)] = utils::pack_kernel_sources(
+        all_kernel_args[akai].tagged_argument.cl_type_name(), &component_cl_types, stride
+      );
+      column_variants.packed.insert(akai, CL_PackedColumns {
+        component_indicies,
+        stride,
+        pack_kernel: utils::build_generated_kernel(context, generated_programs, &pack_k_name, &pack_k_source)?,
+        unpack_kernel: utils::build_generated_kernel(context, generated_programs, &unpack_k_name, &unpack_k_source)?,
+      });
+    }
+
+    Ok(column_variants)
+  }
+
+  /// The lowest all_kernel_args index holding column as a buffer; used wherever a column must always be read as the same type.
+  pub fn canonical_idx(&self, all_kernel_args: &[CL_NamedTaggedArgument], column: &str) -> Option<usize> {
+    if let Some(v) = self.variants.get(column) {
+      return v.first().copied();
+    }
+    (0..all_kernel_args.len()).find(|akai| all_kernel_args[*akai].name == column && all_kernel_args[*akai].tagged_argument.is_buffer())
+  }
+
+  /// If akai is stale, enqueues a conversion from whichever type of its column was written last; packed buffers are re-packed
+  /// from their components, and columns whose every type is stale are unpacked from the packed buffer written last.
+  /// Every element of the buffer is converted, so columns of any entity table may be synced.
+  pub fn sync(
+    &mut self,
+    akai: usize,
+    all_kernel_args: &[CL_NamedTaggedArgument],
+    queue: &opencl3::command_queue::CommandQueue,
+    sim_events: &mut Vec<opencl3::event::Event>,
+    sim_events_cl: &mut Vec<opencl3::types::cl_event>,
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    if self.fresh[akai] {
+      return Ok(());
+    }
+    let fresh_variant = self.variants.get(&all_kernel_args[akai].name).and_then(|column_variants| column_variants.iter().copied().find(|v| self.fresh[*v]));
+    let src_akai = match fresh_variant {
+      Some(src_akai) => src_akai,
+      None if self.packed.contains_key(&akai) => {
+        let component_indicies = self.packed[&akai].component_indicies.clone();
+        for c in component_indicies.iter() {
+          self.sync(*c, all_kernel_args, queue, sim_events, sim_events_cl)?;
+        }
+        self.enqueue_packing(akai, false, all_kernel_args, queue, sim_events, sim_events_cl)?;
+        self.fresh[akai] = true;
+        return Ok(());
+      }
+      None => {
+        let column = &all_kernel_args[akai].name;
+        let packed_akai = *self.packed.iter()
+          .find(|(p, packed_columns)| self.fresh[**p] && packed_columns.component_indicies.iter().any(|c| &all_kernel_args[*c].name == column))
+          .ok_or("Logic error! No up-to-date buffer exists for a column")?.0;
+        self.enqueue_packing(packed_akai, true, all_kernel_args, queue, sim_events, sim_events_cl)?;
+        for c in self.packed[&packed_akai].component_indicies.clone() {
+          self.fresh[c] = true;
+        }
+        // akai is now fresh if it is its column's canonical buffer, else converted from that
+        return self.sync(akai, all_kernel_args, queue, sim_events, sim_events_cl);
+      }
+    };
+    let work_size = std::cmp::min(
+      all_kernel_args[src_akai].tagged_argument.buffer_len().unwrap_or(0), all_kernel_args[akai].tagged_argument.buffer_len().unwrap_or(0)
+    );
+    let conv_event = utils::enqueue_conversion_kernel(
+      &self.conversion_kernels[&(src_akai, akai)], &all_kernel_args[src_akai].tagged_argument, &all_kernel_args[akai].tagged_argument, work_size, queue
+    )?;
+    // Safety: both vectors increase at same time
+    sim_events_cl.push(conv_event.get());
+    sim_events.push(conv_event);
+    self.fresh[akai] = true;
+    Ok(())
+  }
+
+  /// Records that akai may have been written, making the other types of its column stale.
+  /// Writing a packed buffer makes its components stale, and writing a column makes the packed buffers holding it stale.
+  pub fn mark_written(&mut self, akai: usize, all_kernel_args: &[CL_NamedTaggedArgument]) {
+    if let Some(column_variants) = self.variants.get(&all_kernel_args[akai].name) {
+      for v in column_variants.iter() {
+        self.fresh[*v] = *v == akai;
+      }
+    }
+    if self.packed.contains_key(&akai) {
+      for component in all_kernel_args[akai].components.iter() {
+        for (v, argument) in all_kernel_args.iter().enumerate() {
+          if &argument.name == component && argument.tagged_argument.is_buffer() {
+            self.fresh[v] = false;
+          }
+        }
+        self.mark_packed_stale(component, Some(akai), all_kernel_args);
+      }
+    }
+    else {
+      self.mark_packed_stale(&all_kernel_args[akai].name, None, all_kernel_args);
+    }
+  }
+
+  /// Makes every packed buffer holding column stale, except written_akai
+  pub fn mark_packed_stale(&mut self, column: &str, written_akai: Option<usize>, all_kernel_args: &[CL_NamedTaggedArgument]) {
+    for (p, packed_columns) in self.packed.iter() {
+      if Some(*p) != written_akai && packed_columns.component_indicies.iter().any(|c| all_kernel_args[*c].name == column) {
+        self.fresh[*p] = false;
+      }
+    }
+  }
+
+  /// Enqueues the pack_kernel of packed_akai, or its unpack_kernel if unpack, over every entity of packed_akai
+  fn enqueue_packing(
+    &self,
+    packed_akai: usize,
+    unpack: bool,
+    all_kernel_args: &[CL_NamedTaggedArgument],
+    queue: &opencl3::command_queue::CommandQueue,
+    sim_events: &mut Vec<opencl3::event::Event>,
+    sim_events_cl: &mut Vec<opencl3::types::cl_event>,
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let packed_columns = &self.packed[&packed_akai];
+    let kernel = if unpack { &packed_columns.unpack_kernel } else { &packed_columns.pack_kernel };
+    let components: Vec<&CL_TaggedArgument> = packed_columns.component_indicies.iter().map(|c| all_kernel_args[*c].tagged_argument.as_ref()).collect();
+    let work_size = components.iter()
+      .map(|component| component.buffer_len().unwrap_or(0))
+      .fold(all_kernel_args[packed_akai].tagged_argument.buffer_len().unwrap_or(0) / packed_columns.stride, std::cmp::min);
+    if work_size < 1 {
+      return Ok(());
+    }
+    let packing_event = utils::enqueue_packing_kernel(kernel, &all_kernel_args[packed_akai].tagged_argument, &components, work_size, queue)?;
+    // Safety: both vectors increase at same time
+    sim_events_cl.push(packing_event.get());
+    sim_events.push(packing_event);
+    Ok(())
+  }
+}
+
+
+/// Inputs every run of a sweep or ensemble shares; each run only differs in its SimControl & output paths.
+#[derive(Clone, Copy)]
+pub struct SimInputs<'a> {
+  pub device: &'a opencl3::device::Device,
+  pub context: &'a opencl3::context::Context,
+  pub cl_kernels: &'a [CL_Kernel],
+  pub reductions: &'a [ReductionDef],
+  pub global_buffers: &'a [GlobalBufferDef],
+  pub graph: Option<&'a GraphCSR>,
+  pub tables: &'a utils::TableData,
+}
+
+
+/// Device state of a run which kernels, conversions & compactions all operate on: every kernel argument with the per-kernel
+/// bindings into it, the typed variants of shared columns, and the queue with the events of the commands enqueued on it.
+pub struct CL_DeviceState {
+  pub all_kernel_args: Vec<CL_NamedTaggedArgument>,
+  /// For each kernel of the cl-kernels file, the all_kernel_args index of each of its arguments
+  pub kernel_arg_indicies: Vec<Vec<usize>>,
+  /// Parallel to kernel_arg_indicies; true where the kernel declared a non-const buffer it may write to.
+  pub kernel_arg_writes: Vec<Vec<bool>>,
+  pub column_variants: CL_ColumnVariants,
+  pub queue: opencl3::command_queue::CommandQueue,
+  /// Both vectors must be kept in-sync; we keep sim_events_cl so we can rapidly pass a pointer to always-valid CL event structures
+  pub sim_events: Vec<opencl3::event::Event>,
+  pub sim_events_cl: Vec<opencl3::types::cl_event>,
+}
+
+impl CL_DeviceState {
+  /// Brings the buffer at akai up to date with the latest writes to its column (see CL_ColumnVariants::sync)
+  pub fn sync(&mut self, akai: usize) -> Result<(), Box<dyn std::error::Error>> {
+    self.column_variants.sync(akai, &self.all_kernel_args, &self.queue, &mut self.sim_events, &mut self.sim_events_cl)
+  }
+
+  /// Syncs the buffer at akai & reads it back once every enqueued command has completed; None if akai is not a buffer
+  pub fn read_to_host(&mut self, akai: usize) -> Result<Option<HostColumn>, Box<dyn std::error::Error>> {
+    self.sync(akai)?;
+    self.all_kernel_args[akai].tagged_argument.read_to_host(&self.queue, &self.sim_events_cl)
+  }
+
+  /// Overwrites the buffer at akai with host_column, which is only possible while no kernel_args clone of it is alive
+  pub fn write_from_host(&mut self, akai: usize, host_column: &HostColumn) -> Result<(), Box<dyn std::error::Error>> {
+    let tagged_argument = std::sync::Arc::<CL_TaggedArgument>::get_mut(&mut self.all_kernel_args[akai].tagged_argument).ok_or("Logic error! all_kernel_args tagged_argument is not mutable")?;
+    tagged_argument.write_from_host(&self.queue, host_column)
+  }
+
+  /// Records that the buffer at akai may have been written (see CL_ColumnVariants::mark_written)
+  pub fn mark_written(&mut self, akai: usize) {
+    self.column_variants.mark_written(akai, &self.all_kernel_args);
+  }
+
+  /// The lowest all_kernel_args index holding column as a buffer (see CL_ColumnVariants::canonical_idx)
+  pub fn canonical_idx(&self, column: &str) -> Option<usize> {
+    self.column_variants.canonical_idx(&self.all_kernel_args, column)
+  }
+}
+
+
+/// A StopCondition compiled into a kernel which counts matching entities into matched_count
+pub struct CL_StopCondition {
+  pub condition: StopCondition,
+  pub kernel: opencl3::kernel::Kernel,
+  /// all_kernel_args indexes of the columns & constants referenced by condition.expression, in kernel argument order
+  pub arg_indicies: Vec<usize>,
+  pub matched_count: opencl3::memory::Buffer<opencl3::types::cl_uint>,
+}
+
+impl CL_StopCondition {
+  /// Generates & compiles the kernel for condition; identifiers in the expression naming a kernel-bound column of
+  /// condition.table or scalar become kernel arguments, reading the same buffers the simulation kernels use.
+  pub fn new(
+    simcontrol: &SimControl,
+    condition_i: usize,
+    condition: &StopCondition,
+    dev: &CL_DeviceState,
+    context: &opencl3::context::Context,
+    generated_programs: &mut utils::CL_ProgramCache,
+  ) -> Result<CL_StopCondition, Box<dyn std::error::Error>> {
+    let CL_DeviceState { all_kernel_args, column_variants, .. } = dev;
+    let mut arg_indicies: Vec<usize> = vec![];
+    let mut params: Vec<(String, &'static str, bool)> = vec![];
+    for identifier in utils::cl_identifiers(&condition.expression) {
+      if identifier == "i" || identifier == "n" {
+        continue;
+      }
+      let table = condition.table.as_deref().unwrap_or(simcontrol.table_name.as_str());
+      let akai = column_variants.canonical_idx(all_kernel_args, &simcontrol.qualified_column_name(table, &identifier))
+        .or_else(|| (0..all_kernel_args.len()).find(|akai| all_kernel_args[*akai].name == identifier || all_kernel_args[*akai].name == apollon_buffer_name(&identifier)));
+      if let Some(akai) = akai {
+        let tagged_argument = &all_kernel_args[akai].tagged_argument;
+        params.push((identifier, tagged_argument.cl_type_name(), tagged_argument.is_buffer()));
+        arg_indicies.push(akai);
+      }
+    }
+
+    let (kernel_name, source) = utils::stop_condition_kernel_source(condition_i, &condition.expression, &params);
+    let kernel = utils::build_generated_kernel(context, generated_programs, &kernel_name, &source)
+      .map_err(|e| format!("stop_conditions[{}] \"{}\": {}", condition_i, condition.expression, e))?;
+    let matched_count = unsafe {
+      opencl3::memory::Buffer::<opencl3::types::cl_uint>::create(context, opencl3::memory::CL_MEM_READ_WRITE, 1, std::ptr::null_mut())?
+    };
+
+    Ok(CL_StopCondition {
+      condition: condition.clone(),
+      kernel,
+      arg_indicies,
+      matched_count,
+    })
+  }
+
+  /// Runs the condition kernel over the current device state & blocks until the number of matching entities is known.
+  pub fn evaluate(
+    &mut self,
+    dev: &mut CL_DeviceState,
+    num_entities: usize,
+  ) -> Result<bool, Box<dyn std::error::Error>> {
+    let CL_DeviceState { all_kernel_args, column_variants, queue, sim_events, sim_events_cl, .. } = dev;
+    if num_entities < 1 {
+      return Ok(self.condition.quantifier.is_satisfied(0, 0));
+    }
+    for akai in self.arg_indicies.iter() {
+      column_variants.sync(*akai, all_kernel_args, queue, sim_events, sim_events_cl)?;
+    }
+
+    let zero: [opencl3::types::cl_uint; 1] = [0];
+    let unused_write_event = unsafe { queue.enqueue_write_buffer(&mut self.matched_count, opencl3::types::CL_BLOCKING, 0, &zero, sim_events_cl)? };
+
+    let mut exec_kernel = opencl3::kernel::ExecuteKernel::new(&self.kernel);
+    unsafe {
+      for akai in self.arg_indicies.iter() {
+        all_kernel_args[*akai].tagged_argument.set_as_arg(&mut exec_kernel);
+      }
+      exec_kernel.set_arg(&(num_entities as opencl3::types::cl_ulong));
+      exec_kernel.set_arg(&self.matched_count);
+    }
+    exec_kernel.set_global_work_size(num_entities);
+    let kernel_event = unsafe { exec_kernel.enqueue_nd_range(queue)? };
+    // Safety: both vectors increase at same time
+    sim_events_cl.push(kernel_event.get());
+    sim_events.push(kernel_event);
+
+    let num_matched = utils::read_cl_buffer(queue, &self.matched_count, sim_events_cl)?;
+    Ok(self.condition.quantifier.is_satisfied(num_matched[0] as usize, num_entities))
+  }
+}
+
+
+/// A WorldDef compiled into a kernel run after every step's kernels, which applies the boundary to the entities outside the world.
+pub struct CL_WorldBoundary {
+  pub table: String,
+  pub bounds: [f64; 4],
+  /// all_kernel_args indexes of the x & y columns, the velocity columns if any & the alive column of a "remove" boundary, in kernel argument order
+  pub arg_indicies: Vec<usize>,
+  /// "float", or "double" if any of the bound columns is double
+  pub compute_type: &'static str,
+  pub kernel: opencl3::kernel::Kernel,
+}
+
+impl CL_WorldBoundary {
+  /// Adds the alive column of a "remove" boundary to all_kernel_args if no kernel binds it, so must be called before
+  /// CL_ColumnVariants::new; its T=0 entities of tables are alive. The coordinate & velocity columns must be bound by a kernel.
+  pub fn new(
+    simcontrol: &SimControl,
+    def: &WorldDef,
+    all_kernel_args: &mut Vec<CL_NamedTaggedArgument>,
+    tables: &utils::TableData,
+    context: &opencl3::context::Context,
+    queue: &opencl3::command_queue::CommandQueue,
+    generated_programs: &mut utils::CL_ProgramCache,
+  ) -> Result<CL_WorldBoundary, Box<dyn std::error::Error>> {
+    let bounds = def.world_bounds(simcontrol);
+    if !(bounds[2] > bounds[0] && bounds[3] > bounds[1]) {
+      return Err(format!("world bounds {:?} are empty; they are [x_min, y_min, x_max, y_max]", bounds).into());
+    }
+    let table = def.table.clone().unwrap_or_else(|| simcontrol.table_name.clone());
+    let table_num_entities = tables.get(&table).map(|ld| ld.len()).ok_or_else(|| format!("world bounds table '{}', which is not declared", table))?;
+    let find_buffer = |all_kernel_args: &[CL_NamedTaggedArgument], name: &str| {
+      (0..all_kernel_args.len()).find(|akai| all_kernel_args[*akai].name == name && all_kernel_args[*akai].tagged_argument.is_buffer())
+    };
+
+    let mut columns: Vec<&String> = vec![def.x_column.as_ref().unwrap_or(&simcontrol.gis_x_attr_name), def.y_column.as_ref().unwrap_or(&simcontrol.gis_y_attr_name)];
+    if let Some(ref velocity_columns) = def.velocity_columns {
+      columns.extend(velocity_columns.iter());
+    }
+    let mut arg_indicies: Vec<usize> = vec![];
+    for column in columns {
+      let column = simcontrol.qualified_column_name(&table, column);
+      arg_indicies.push(find_buffer(all_kernel_args, &column).ok_or_else(|| format!("world column {} is not bound by any kernel", column))?);
+    }
+
+    if def.boundary == WorldBoundary::Remove {
+      if !simcontrol.has_dynamic_population(&table) {
+        return Err(format!("world boundary \"remove\" removes entities of table '{}', which requires it to have max_entities", table).into());
+      }
+      let alive_column = simcontrol.qualified_column_name(&table, "alive");
+      let alive_akai = match find_buffer(all_kernel_args, &alive_column) {
+        Some(akai) => akai,
+        None => {
+          let table_capacity = simcontrol.table_capacity(&table, table_num_entities);
+          let alive: Vec<Value> = (0..table_capacity).map(|i| Value::Integer(if i < table_num_entities { 1 } else { 0 })).collect();
+          all_kernel_args.push(CL_NamedTaggedArgument::new(alive_column, utils::values_to_cl_buffer(context, queue, "uchar", &alive, RWColumn::Write(String::new()))?));
+          all_kernel_args.len() - 1
+        }
+      };
+      arg_indicies.push(alive_akai);
+    }
+
+    let arg_types: Vec<&'static str> = arg_indicies.iter().map(|akai| all_kernel_args[*akai].tagged_argument.cl_type_name()).collect();
+    let compute_type = if arg_types.contains(&"double") { "double" } else { "float" };
+    let (kernel_name, source) = utils::world_boundary_kernel_source(def.boundary, &arg_types, compute_type);
+    let kernel = utils::build_generated_kernel(context, generated_programs, &kernel_name, &source)
+      .map_err(|e| format!("world boundary \"{}\": {}", def.boundary.name(), e))?;
+
+    Ok(CL_WorldBoundary {
+      table,
+      bounds,
+      arg_indicies,
+      compute_type,
+      kernel,
+    })
+  }
+
+  /// Enqueues the boundary kernel over the first num_entities entities; nothing is read back to the host.
+  pub fn apply(
+    &self,
+    dev: &mut CL_DeviceState,
+    num_entities: usize,
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let CL_DeviceState { all_kernel_args, column_variants, queue, sim_events, sim_events_cl, .. } = dev;
+    if num_entities < 1 {
+      return Ok(());
+    }
+    for akai in self.arg_indicies.iter() {
+      column_variants.sync(*akai, all_kernel_args, queue, sim_events, sim_events_cl)?;
+    }
+
+    let mut exec_kernel = opencl3::kernel::ExecuteKernel::new(&self.kernel);
+    let bounds_f32: Vec<opencl3::types::cl_float> = self.bounds.iter().map(|b| *b as opencl3::types::cl_float).collect();
+    unsafe {
+      for akai in self.arg_indicies.iter() {
+        all_kernel_args[*akai].tagged_argument.set_as_arg(&mut exec_kernel);
+      }
+      exec_kernel.set_arg(&(num_entities as opencl3::types::cl_ulong));
+      for (bound, bound_f32) in self.bounds.iter().zip(bounds_f32.iter()) {
+        if self.compute_type == "double" {
+          exec_kernel.set_arg(bound);
+        }
+        else {
+          exec_kernel.set_arg(bound_f32);
+        }
+      }
+    }
+    exec_kernel.set_global_work_size(num_entities);
+    let kernel_event = unsafe { exec_kernel.enqueue_nd_range(queue)? };
+    // Safety: both vectors increase at same time
+    sim_events_cl.push(kernel_event.get());
+    sim_events.push(kernel_event);
+
+    for akai in self.arg_indicies.iter() {
+      column_variants.mark_written(*akai, all_kernel_args);
+    }
+    Ok(())
+  }
+}
+
+
+/// A ReductionDef compiled into two kernels: the first reduces the selected entities to one partial sum, min, max & count
+/// per work group in local memory, the second reduces those partials with a single work group.
+pub struct CL_Reduction {
+  pub def: ReductionDef,
+  pub table: String,
+  /// all_kernel_args indexes of the reduced column followed by the columns & constants referenced by def.filter, in kernel argument order
+  pub arg_indicies: Vec<usize>,
+  pub work_group_size: usize,
+  /// Float buffers, or double buffers when the column is double
+  pub partial_sum: CL_TaggedArgument,
+  pub partial_min: CL_TaggedArgument,
+  pub partial_max: CL_TaggedArgument,
+  pub partial_count: opencl3::memory::Buffer<opencl3::types::cl_uint>,
+  pub kernel: opencl3::kernel::Kernel,
+  pub combine_kernel: opencl3::kernel::Kernel,
+}
+
+impl CL_Reduction {
+  /// Generates & compiles the kernels for def; work_group_size is the largest power of two up to 256 the device allows.
+  pub fn new(
+    simcontrol: &SimControl,
+    reduction_i: usize,
+    def: &ReductionDef,
+    dev: &CL_DeviceState,
+    max_work_group_size: usize,
+    context: &opencl3::context::Context,
+    generated_programs: &mut utils::CL_ProgramCache,
+  ) -> Result<CL_Reduction, Box<dyn std::error::Error>> {
+    let CL_DeviceState { all_kernel_args, column_variants, .. } = dev;
+    let table = def.table.clone().unwrap_or_else(|| simcontrol.table_name.clone());
+    if def.ops.is_empty() {
+      return Err(format!("reduction[{}] of column {} has no ops", reduction_i, &def.column).into());
+    }
+    let column_akai = column_variants.canonical_idx(all_kernel_args, &simcontrol.qualified_column_name(&table, &def.column))
+      .ok_or_else(|| format!("reduction[{}] column {} of table {} is not bound by any kernel", reduction_i, &def.column, &table))?;
+    let value_type = all_kernel_args[column_akai].tagged_argument.cl_type_name();
+
+    let mut arg_indicies: Vec<usize> = vec![column_akai];
+    let mut params: Vec<(String, &'static str, bool)> = vec![(def.column.clone(), value_type, true)];
+    let filter = def.filter.clone().unwrap_or_else(|| "1".to_string());
+    for identifier in utils::cl_identifiers(&filter) {
+      if identifier == "i" || identifier == "n" || identifier == def.column {
+        continue;
+      }
+      let akai = column_variants.canonical_idx(all_kernel_args, &simcontrol.qualified_column_name(&table, &identifier))
+        .or_else(|| (0..all_kernel_args.len()).find(|akai| all_kernel_args[*akai].name == identifier || all_kernel_args[*akai].name == apollon_buffer_name(&identifier)));
+      if let Some(akai) = akai {
+        let tagged_argument = &all_kernel_args[akai].tagged_argument;
+        params.push((identifier, tagged_argument.cl_type_name(), tagged_argument.is_buffer()));
+        arg_indicies.push(akai);
+      }
+    }
+
+    let mut work_group_size: usize = 1;
+    while work_group_size * 2 <= std::cmp::min(256, max_work_group_size) {
+      work_group_size *= 2;
+    }
+    let acc_type = if value_type == "double" { "double" } else { "float" };
+    let ((kernel_name, source), (combine_kernel_name, combine_source)) = utils::reduction_kernel_sources(reduction_i, &def.column, &filter, &params, acc_type, work_group_size);
+    let kernel = utils::build_generated_kernel(context, generated_programs, &kernel_name, &source)
+      .map_err(|e| format!("reduction[{}] of column {}: {}", reduction_i, &def.column, e))?;
+    let combine_kernel = utils::build_generated_kernel(context, generated_programs, &combine_kernel_name, &combine_source)
+      .map_err(|e| format!("reduction[{}] of column {}: {}", reduction_i, &def.column, e))?;
+
+    // One partial per work group of the first kernel, of which there are at most work_group_size
+    let create_partials = || -> Result<CL_TaggedArgument, Box<dyn std::error::Error>> {
+      Ok(unsafe {
+        if acc_type == "double" {
+          CL_TaggedArgument::DoubleBuffer(opencl3::memory::Buffer::<opencl3::types::cl_double>::create(context, opencl3::memory::CL_MEM_READ_WRITE, work_group_size, std::ptr::null_mut())?)
+        }
+        else {
+          CL_TaggedArgument::FloatBuffer(opencl3::memory::Buffer::<opencl3::types::cl_float>::create(context, opencl3::memory::CL_MEM_READ_WRITE, work_group_size, std::ptr::null_mut())?)
+        }
+      })
+    };
+
+    Ok(CL_Reduction {
+      def: def.clone(),
+      table,
+      arg_indicies,
+      work_group_size,
+      partial_sum: create_partials()?,
+      partial_min: create_partials()?,
+      partial_max: create_partials()?,
+      partial_count: unsafe {
+        opencl3::memory::Buffer::<opencl3::types::cl_uint>::create(context, opencl3::memory::CL_MEM_READ_WRITE, work_group_size, std::ptr::null_mut())?
+      },
+      kernel,
+      combine_kernel,
+    })
+  }
+
+  /// Runs both kernels over the first num_entities entities & blocks until the results are known.
+  /// Returns (stat name, value) for every op of the reduction, in the order of def.ops.
+  pub fn evaluate(
+    &self,
+    dev: &mut CL_DeviceState,
+    num_entities: usize,
+  ) -> Result<Vec<(String, f64)>, Box<dyn std::error::Error>> {
+    let CL_DeviceState { all_kernel_args, column_variants, queue, sim_events, sim_events_cl, .. } = dev;
+    for akai in self.arg_indicies.iter() {
+      column_variants.sync(*akai, all_kernel_args, queue, sim_events, sim_events_cl)?;
+    }
+
+    // Each work item of the first kernel strides over the entities, so a single group of partials always suffices
+    let num_groups = std::cmp::max(1, std::cmp::min(num_entities.div_ceil(self.work_group_size), self.work_group_size));
+    let num_groups_u32 = num_groups as opencl3::types::cl_uint;
+
+    let mut exec_kernel = opencl3::kernel::ExecuteKernel::new(&self.kernel);
+    unsafe {
+      for akai in self.arg_indicies.iter() {
+        all_kernel_args[*akai].tagged_argument.set_as_arg(&mut exec_kernel);
+      }
+      exec_kernel.set_arg(&(num_entities as opencl3::types::cl_ulong));
+      self.partial_sum.set_as_arg(&mut exec_kernel);
+      self.partial_min.set_as_arg(&mut exec_kernel);
+      self.partial_max.set_as_arg(&mut exec_kernel);
+      exec_kernel.set_arg(&self.partial_count);
+    }
+    exec_kernel.set_global_work_size(num_groups * self.work_group_size);
+    exec_kernel.set_local_work_size(self.work_group_size);
+    let kernel_event = unsafe { exec_kernel.enqueue_nd_range(queue)? };
+    // Safety: both vectors increase at same time
+    sim_events_cl.push(kernel_event.get());
+    sim_events.push(kernel_event);
+
+    let mut exec_kernel = opencl3::kernel::ExecuteKernel::new(&self.combine_kernel);
+    unsafe {
+      self.partial_sum.set_as_arg(&mut exec_kernel);
+      self.partial_min.set_as_arg(&mut exec_kernel);
+      self.partial_max.set_as_arg(&mut exec_kernel);
+      exec_kernel.set_arg(&self.partial_count);
+      exec_kernel.set_arg(&num_groups_u32);
+    }
+    exec_kernel.set_global_work_size(self.work_group_size);
+    exec_kernel.set_local_work_size(self.work_group_size);
+    let kernel_event = unsafe { exec_kernel.enqueue_nd_range(queue)? };
+    sim_events_cl.push(kernel_event.get());
+    sim_events.push(kernel_event);
+
+    let read_first = |partials: &CL_TaggedArgument| -> Result<f64, Box<dyn std::error::Error>> {
+      let host_column = partials.read_to_host(queue, sim_events_cl)?.ok_or("Logic error! Reduction partials are not a buffer")?;
+      host_column.get_value(0).to_f64()
+    };
+    let sum = read_first(&self.partial_sum)?;
+    let min = read_first(&self.partial_min)?;
+    let max = read_first(&self.partial_max)?;
+    let count = utils::read_cl_buffer(queue, &self.partial_count, sim_events_cl)?[0] as u64;
+
+    Ok(self.def.ops.iter().map(|op| (self.def.stat_name(*op), op.apply(sum, min, max, count))).collect())
+  }
+}
+
+
+/// The device-side uniform grid described by a SpatialIndexDef. Every rebuild packs (cell, entity index) of each live entity
+/// into a ulong, bitonic-sorts them & records where each cell's run of entities starts & ends in the sorted order.
+pub struct CL_SpatialIndex {
+  pub table: String,
+  /// all_kernel_args indexes of the coordinate columns & of the cell_start, cell_end & sorted_index buffers
+  pub x_akai: usize,
+  pub y_akai: usize,
+  pub cell_start_akai: usize,
+  pub cell_end_akai: usize,
+  pub sorted_index_akai: usize,
+  pub num_cells: usize,
+  pub grid_width: u32,
+  pub grid_height: u32,
+  pub cell_size: f32,
+  pub origin: [f32; 2],
+  /// Power-of-two length holding at least entity_capacity keys
+  pub sort_keys: opencl3::memory::Buffer<opencl3::types::cl_ulong>,
+  pub sort_keys_len: usize,
+  pub assign_kernel: opencl3::kernel::Kernel,
+  pub bitonic_kernel: opencl3::kernel::Kernel,
+  pub clear_kernel: opencl3::kernel::Kernel,
+  pub bounds_kernel: opencl3::kernel::Kernel,
+}
+
+impl CL_SpatialIndex {
+  /// Adds any of the cell_start, cell_end & sorted_index buffers no kernel binds to all_kernel_args, so must be called before
+  /// CL_ColumnVariants::new. The coordinate columns must be bound by a kernel.
+  pub fn new(
+    simcontrol: &SimControl,
+    def: &SpatialIndexDef,
+    all_kernel_args: &mut Vec<CL_NamedTaggedArgument>,
+    table_capacity: usize,
+    context: &opencl3::context::Context,
+    generated_programs: &mut utils::CL_ProgramCache,
+  ) -> Result<CL_SpatialIndex, Box<dyn std::error::Error>> {
+    if def.cell_size.is_nan() || def.cell_size <= 0.0 {
+      return Err(format!("spatial_index.cell_size must be > 0, not {}", def.cell_size).into());
+    }
+    let table = def.table.clone().unwrap_or_else(|| simcontrol.table_name.clone());
+    let x_column = simcontrol.qualified_column_name(&table, def.x_column.as_ref().unwrap_or(&simcontrol.gis_x_attr_name));
+    let y_column = simcontrol.qualified_column_name(&table, def.y_column.as_ref().unwrap_or(&simcontrol.gis_y_attr_name));
+    let find_buffer = |all_kernel_args: &[CL_NamedTaggedArgument], name: &str| {
+      (0..all_kernel_args.len()).find(|akai| all_kernel_args[*akai].name == name && all_kernel_args[*akai].tagged_argument.is_buffer())
+    };
+    let x_akai = find_buffer(all_kernel_args, &x_column).ok_or_else(|| format!("spatial_index column {} is not bound by any kernel", x_column))?;
+    let y_akai = find_buffer(all_kernel_args, &y_column).ok_or_else(|| format!("spatial_index column {} is not bound by any kernel", y_column))?;
+
+    let num_cells = def.num_cells(simcontrol);
+    let mut index_akais: Vec<usize> = vec![];
+    for (buffer, len) in [("cell_start", num_cells), ("cell_end", num_cells), ("sorted_index", table_capacity)] {
+      let name = apollon_buffer_name(buffer);
+      let akai = match find_buffer(all_kernel_args, &name) {
+        Some(akai) => akai,
+        None => {
+          let cl_buffer = unsafe {
+            opencl3::memory::Buffer::<opencl3::types::cl_uint>::create(context, opencl3::memory::CL_MEM_READ_WRITE, std::cmp::max(len, 1), std::ptr::null_mut())?
+          };
+          all_kernel_args.push(CL_NamedTaggedArgument::new(name, CL_TaggedArgument::Uint32Buffer(cl_buffer)));
+          all_kernel_args.len() - 1
+        }
+      };
+      index_akais.push(akai);
+    }
+
+    let sort_keys_len = std::cmp::max(table_capacity, 2).next_power_of_two();
+    let sort_keys = unsafe {
+      opencl3::memory::Buffer::<opencl3::types::cl_ulong>::create(context, opencl3::memory::CL_MEM_READ_WRITE, sort_keys_len, std::ptr::null_mut())?
+    };
+
+    let mut kernels: Vec<opencl3::kernel::Kernel> = vec![];
+    for (kernel_name, source) in utils::spatial_index_kernel_sources(all_kernel_args[x_akai].tagged_argument.cl_type_name(), all_kernel_args[y_akai].tagged_argument.cl_type_name()) {
+      kernels.push(utils::build_generated_kernel(context, generated_programs, &kernel_name, &source)?);
+    }
+    let bounds_kernel = kernels.pop().ok_or("Logic error! Missing spatial index kernel")?;
+    let clear_kernel = kernels.pop().ok_or("Logic error! Missing spatial index kernel")?;
+    let bitonic_kernel = kernels.pop().ok_or("Logic error! Missing spatial index kernel")?;
+    let assign_kernel = kernels.pop().ok_or("Logic error! Missing spatial index kernel")?;
+
+    let (grid_width, grid_height) = def.grid_dims(simcontrol);
+    Ok(CL_SpatialIndex {
+      table,
+      x_akai,
+      y_akai,
+      cell_start_akai: index_akais[0],
+      cell_end_akai: index_akais[1],
+      sorted_index_akai: index_akais[2],
+      num_cells,
+      grid_width,
+      grid_height,
+      cell_size: def.cell_size as f32,
+      origin: [def.origin[0] as f32, def.origin[1] as f32],
+      sort_keys,
+      sort_keys_len,
+      assign_kernel,
+      bitonic_kernel,
+      clear_kernel,
+      bounds_kernel,
+    })
+  }
+
+  /// Enqueues the kernels which sort the first num_entities entities into cells; nothing is read back to the host.
+  pub fn rebuild(
+    &self,
+    dev: &mut CL_DeviceState,
+    num_entities: usize,
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let CL_DeviceState { all_kernel_args, column_variants, queue, sim_events, sim_events_cl, .. } = dev;
+    column_variants.sync(self.x_akai, all_kernel_args, queue, sim_events, sim_events_cl)?;
+    column_variants.sync(self.y_akai, all_kernel_args, queue, sim_events, sim_events_cl)?;
+
+    let mut events: Vec<opencl3::event::Event> = vec![];
+
+    let mut exec_kernel = opencl3::kernel::ExecuteKernel::new(&self.clear_kernel);
+    unsafe {
+      all_kernel_args[self.cell_start_akai].tagged_argument.set_as_arg(&mut exec_kernel);
+      all_kernel_args[self.cell_end_akai].tagged_argument.set_as_arg(&mut exec_kernel);
+    }
+    exec_kernel.set_global_work_size(self.num_cells);
+    events.push(unsafe { exec_kernel.enqueue_nd_range(queue)? });
+
+    if num_entities > 0 {
+      // Only the smallest power of two covering the live entities is sorted
+      let sort_len = std::cmp::max(num_entities, 2).next_power_of_two();
+      let num_entities_u32 = num_entities as opencl3::types::cl_uint;
+
+      let mut exec_kernel = opencl3::kernel::ExecuteKernel::new(&self.assign_kernel);
+      unsafe {
+        all_kernel_args[self.x_akai].tagged_argument.set_as_arg(&mut exec_kernel);
+        all_kernel_args[self.y_akai].tagged_argument.set_as_arg(&mut exec_kernel);
+        exec_kernel.set_arg(&num_entities_u32);
+        exec_kernel.set_arg(&self.origin[0]);
+        exec_kernel.set_arg(&self.origin[1]);
+        exec_kernel.set_arg(&self.cell_size);
+        exec_kernel.set_arg(&self.grid_width);
+        exec_kernel.set_arg(&self.grid_height);
+        exec_kernel.set_arg(&self.sort_keys);
+      }
+      exec_kernel.set_global_work_size(sort_len);
+      events.push(unsafe { exec_kernel.enqueue_nd_range(queue)? });
+
+      let mut k: usize = 2;
+      while k <= sort_len {
+        let mut j = k / 2;
+        while j > 0 {
+          let (j_u32, k_u32) = (j as opencl3::types::cl_uint, k as opencl3::types::cl_uint);
+          let mut exec_kernel = opencl3::kernel::ExecuteKernel::new(&self.bitonic_kernel);
+          unsafe {
+            exec_kernel.set_arg(&self.sort_keys);
+            exec_kernel.set_arg(&j_u32);
+            exec_kernel.set_arg(&k_u32);
+          }
+          exec_kernel.set_global_work_size(sort_len);
+          events.push(unsafe { exec_kernel.enqueue_nd_range(queue)? });
+          j /= 2;
+        }
+        k *= 2;
+      }
+
+      let mut exec_kernel = opencl3::kernel::ExecuteKernel::new(&self.bounds_kernel);
+      unsafe {
+        exec_kernel.set_arg(&self.sort_keys);
+        exec_kernel.set_arg(&num_entities_u32);
+        all_kernel_args[self.cell_start_akai].tagged_argument.set_as_arg(&mut exec_kernel);
+        all_kernel_args[self.cell_end_akai].tagged_argument.set_as_arg(&mut exec_kernel);
+        all_kernel_args[self.sorted_index_akai].tagged_argument.set_as_arg(&mut exec_kernel);
+      }
+      exec_kernel.set_global_work_size(num_entities);
+      events.push(unsafe { exec_kernel.enqueue_nd_range(queue)? });
+    }
+
+    for event in events {
+      // Safety: both vectors increase at same time
+      sim_events_cl.push(event.get());
+      sim_events.push(event);
+    }
+    Ok(())
+  }
+}
+
+
+/// Host-side copy of one column buffer, holding the same element type as the device buffer it was read from.
+#[derive(Debug, Clone)]
+pub enum HostColumn {
+  Uint8  (Vec<opencl3::types::cl_uchar>),
+  Uint16 (Vec<opencl3::types::cl_ushort>),
+  Uint32 (Vec<opencl3::types::cl_uint>),
+  Uint64 (Vec<opencl3::types::cl_ulong>),
+
+  Int8   (Vec<opencl3::types::cl_char>),
+  Int16  (Vec<opencl3::types::cl_short>),
+  Int32  (Vec<opencl3::types::cl_int>),
+  Int64  (Vec<opencl3::types::cl_long>),
+
+  Float  (Vec<opencl3::types::cl_float>),
+  Double (Vec<opencl3::types::cl_double>),
+}
+
+impl HostColumn {
+  /// Builds a Double column from host data, used for columns no kernel binds.
+  /// Returns None if no record has the column; records with missing or non-numeric values hold NaN.
+  pub fn from_ld_column(ld: &utils::ListedData, column: &str) -> Option<HostColumn> {
+    if !ld.iter().any(|record| record.contains_key(column)) {
+      return None;
+    }
+    Some(HostColumn::Double(
+      ld.iter().map(|record| record.get(column).and_then(|v| v.to_f64().ok()).unwrap_or(f64::NAN)).collect()
+    ))
+  }
+
+  pub fn len(&self) -> usize {
+    match self {
+      HostColumn::Uint8(v)  => v.len(),
+      HostColumn::Uint16(v) => v.len(),
+      HostColumn::Uint32(v) => v.len(),
+      HostColumn::Uint64(v) => v.len(),
+      HostColumn::Int8(v)   => v.len(),
+      HostColumn::Int16(v)  => v.len(),
+      HostColumn::Int32(v)  => v.len(),
+      HostColumn::Int64(v)  => v.len(),
+      HostColumn::Float(v)  => v.len(),
+      HostColumn::Double(v) => v.len(),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn get_f32(&self, i: usize) -> f32 {
+    match self {
+      HostColumn::Uint8(v)  => v[i] as f32,
+      HostColumn::Uint16(v) => v[i] as f32,
+      HostColumn::Uint32(v) => v[i] as f32,
+      HostColumn::Uint64(v) => v[i] as f32,
+      HostColumn::Int8(v)   => v[i] as f32,
+      HostColumn::Int16(v)  => v[i] as f32,
+      HostColumn::Int32(v)  => v[i] as f32,
+      HostColumn::Int64(v)  => v[i] as f32,
+      HostColumn::Float(v)  => v[i],
+      HostColumn::Double(v) => v[i] as f32,
+    }
+  }
+
+  pub fn get_value(&self, i: usize) -> Value {
+    match self {
+      HostColumn::Uint8(v)  => Value::Integer(v[i] as i64),
+      HostColumn::Uint16(v) => Value::Integer(v[i] as i64),
+      HostColumn::Uint32(v) => Value::Integer(v[i] as i64),
+      HostColumn::Uint64(v) => Value::Integer(v[i] as i64),
+      HostColumn::Int8(v)   => Value::Integer(v[i] as i64),
+      HostColumn::Int16(v)  => Value::Integer(v[i] as i64),
+      HostColumn::Int32(v)  => Value::Integer(v[i] as i64),
+      HostColumn::Int64(v)  => Value::Integer(v[i]),
+      HostColumn::Float(v)  => Value::Double(v[i] as f64),
+      HostColumn::Double(v) => Value::Double(v[i]),
+    }
+  }
+
+  /// The OpenCL C type name of this column's elements
+  pub fn cl_type_name(&self) -> &'static str {
+    match self {
+      HostColumn::Uint8(_)  => "uchar",
+      HostColumn::Uint16(_) => "ushort",
+      HostColumn::Uint32(_) => "uint",
+      HostColumn::Uint64(_) => "ulong",
+      HostColumn::Int8(_)   => "char",
+      HostColumn::Int16(_)  => "short",
+      HostColumn::Int32(_)  => "int",
+      HostColumn::Int64(_)  => "long",
+      HostColumn::Float(_)  => "float",
+      HostColumn::Double(_) => "double",
+    }
+  }
+
+  /// Writes elements [0, num) as little-endian bytes
+  pub fn write_le_bytes(&self, num: usize, w: &mut impl std::io::Write) -> std::io::Result<()> {
+    match self {
+      HostColumn::Uint8(v)  => { for x in &v[..num] { w.write_all(&x.to_le_bytes())?; } }
+      HostColumn::Uint16(v) => { for x in &v[..num] { w.write_all(&x.to_le_bytes())?; } }
+      HostColumn::Uint32(v) => { for x in &v[..num] { w.write_all(&x.to_le_bytes())?; } }
+      HostColumn::Uint64(v) => { for x in &v[..num] { w.write_all(&x.to_le_bytes())?; } }
+      HostColumn::Int8(v)   => { for x in &v[..num] { w.write_all(&x.to_le_bytes())?; } }
+      HostColumn::Int16(v)  => { for x in &v[..num] { w.write_all(&x.to_le_bytes())?; } }
+      HostColumn::Int32(v)  => { for x in &v[..num] { w.write_all(&x.to_le_bytes())?; } }
+      HostColumn::Int64(v)  => { for x in &v[..num] { w.write_all(&x.to_le_bytes())?; } }
+      HostColumn::Float(v)  => { for x in &v[..num] { w.write_all(&x.to_le_bytes())?; } }
+      HostColumn::Double(v) => { for x in &v[..num] { w.write_all(&x.to_le_bytes())?; } }
+    }
+    Ok(())
+  }
+
+  /// Reads num little-endian elements of the OpenCL C type cl_type_name, the inverse of write_le_bytes
+  pub fn read_le_bytes(cl_type_name: &str, num: usize, r: &mut impl std::io::Read) -> Result<HostColumn, Box<dyn std::error::Error>> {
+    macro_rules! read_vec {
+      ($t:ty) => {{
+        let mut v: Vec<$t> = Vec::with_capacity(num);
+        let mut elm_bytes = [0u8; std::mem::size_of::<$t>()];
+        for _ in 0..num {
+          r.read_exact(&mut elm_bytes)?;
+          v.push(<$t>::from_le_bytes(elm_bytes));
+        }
+        v
+      }};
+    }
+    Ok(match cl_type_name {
+      "uchar"  => HostColumn::Uint8(read_vec!(opencl3::types::cl_uchar)),
+      "ushort" => HostColumn::Uint16(read_vec!(opencl3::types::cl_ushort)),
+      "uint"   => HostColumn::Uint32(read_vec!(opencl3::types::cl_uint)),
+      "ulong"  => HostColumn::Uint64(read_vec!(opencl3::types::cl_ulong)),
+      "char"   => HostColumn::Int8(read_vec!(opencl3::types::cl_char)),
+      "short"  => HostColumn::Int16(read_vec!(opencl3::types::cl_short)),
+      "int"    => HostColumn::Int32(read_vec!(opencl3::types::cl_int)),
+      "long"   => HostColumn::Int64(read_vec!(opencl3::types::cl_long)),
+      "float"  => HostColumn::Float(read_vec!(opencl3::types::cl_float)),
+      "double" => HostColumn::Double(read_vec!(opencl3::types::cl_double)),
+      unk => return Err(format!("Unknown column type '{}'", unk).into()),
+    })
+  }
+
+  /// A column of len elements where element j is self[indices[j]]; elements without an index (or past indices) are 0.
+  pub fn gather(&self, indices: &[Option<usize>], len: usize) -> HostColumn {
+    macro_rules! gather_vec {
+      ($v:expr) => {
+        (0..len).map(|j| indices.get(j).copied().flatten().map(|i| $v[i]).unwrap_or_default()).collect()
+      };
+    }
+    match self {
+      HostColumn::Uint8(v)  => HostColumn::Uint8(gather_vec!(v)),
+      HostColumn::Uint16(v) => HostColumn::Uint16(gather_vec!(v)),
+      HostColumn::Uint32(v) => HostColumn::Uint32(gather_vec!(v)),
+      HostColumn::Uint64(v) => HostColumn::Uint64(gather_vec!(v)),
+      HostColumn::Int8(v)   => HostColumn::Int8(gather_vec!(v)),
+      HostColumn::Int16(v)  => HostColumn::Int16(gather_vec!(v)),
+      HostColumn::Int32(v)  => HostColumn::Int32(gather_vec!(v)),
+      HostColumn::Int64(v)  => HostColumn::Int64(gather_vec!(v)),
+      HostColumn::Float(v)  => HostColumn::Float(gather_vec!(v)),
+      HostColumn::Double(v) => HostColumn::Double(gather_vec!(v)),
+    }
+  }
+
+  /// A column of the OpenCL C type cl_type_name holding values followed by zeros up to len elements
+  pub fn from_integers(cl_type_name: &str, values: &[u64], len: usize) -> Result<HostColumn, Box<dyn std::error::Error>> {
+    macro_rules! cast_vec {
+      ($t:ty) => {
+        (0..len).map(|i| values.get(i).map(|v| *v as $t).unwrap_or_default()).collect()
+      };
+    }
+    Ok(match cl_type_name {
+      "uchar"  => HostColumn::Uint8(cast_vec!(opencl3::types::cl_uchar)),
+      "ushort" => HostColumn::Uint16(cast_vec!(opencl3::types::cl_ushort)),
+      "uint"   => HostColumn::Uint32(cast_vec!(opencl3::types::cl_uint)),
+      "ulong"  => HostColumn::Uint64(cast_vec!(opencl3::types::cl_ulong)),
+      "char"   => HostColumn::Int8(cast_vec!(opencl3::types::cl_char)),
+      "short"  => HostColumn::Int16(cast_vec!(opencl3::types::cl_short)),
+      "int"    => HostColumn::Int32(cast_vec!(opencl3::types::cl_int)),
+      "long"   => HostColumn::Int64(cast_vec!(opencl3::types::cl_long)),
+      "float"  => HostColumn::Float(cast_vec!(opencl3::types::cl_float)),
+      "double" => HostColumn::Double(cast_vec!(opencl3::types::cl_double)),
+      unk => return Err(format!("Unknown column type '{}'", unk).into()),
+    })
+  }
+
+  /// Writes every value of this column into column of the matching ld records.
+  pub fn update_ld_column(&self, ld: &mut utils::ListedData, column: &str) {
+    for (i, record) in ld.iter_mut().enumerate().take(self.len()) {
+      record.insert(column.to_string(), self.get_value(i));
+    }
+  }
+}
+
 
+/// The JSON header of a checkpoint file; everything needed to continue a simulation except the column & history data which follow it.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CheckpointHeader {
+  /// Always "apollon-checkpoint-1"
+  pub format: String,
+
+  /// The next step to execute, ie the number of steps already completed
+  pub step: u64,
+
+  pub num_entities: usize,
+
+  /// Fully resolved for this run; every data constant is a single value and output paths are already templated
+  pub simcontrol: SimControl,
+
+  /// One entry per device buffer, in the order their data follows the header
+  pub columns: Vec<CheckpointColumn>,
+
+  /// Number of frames already given to the animation encoder, which determines the timestamp of the next frame
+  pub anim_frames_encoded: u64,
+  pub anim_point_history_i: usize,
+  pub anim_point_history_len: usize,
+
+  /// Table name -> population of every table whose population changes during the run
+  #[serde(default)]
+  pub populations: HashMap<String, TablePopulation>,
+
+  /// Queue of an event-driven simulation & the time of the last batch
+  #[serde(default)]
+  pub events: Vec<ScheduledEvent>,
+  #[serde(default)]
+  pub next_event_seq: u64,
+  #[serde(default)]
+  pub sim_time: f64,
+
+  /// Latest value of every [[reduction]] statistic, which kernels may bind as scalar arguments
+  #[serde(default)]
+  pub reduction_values: HashMap<String, f64>,
+
+  /// Length of trajectory_file_path & stats_file_path once every step before this checkpoint was written; None if the file was not written
+  #[serde(default)]
+  pub trajectory_file_offset: Option<u64>,
+  #[serde(default)]
+  pub stats_file_offset: Option<u64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CheckpointColumn {
+  pub name: String,
+  /// OpenCL C type name of the buffer elements, ie "float"
+  pub cl_type: String,
+  pub len: usize,
+}
+
+/// Host-side bookkeeping of a table whose population changes during a run (see SimControl.max_entities).
+/// Live entities always occupy indexes [0, num_entities) of the table's column buffers.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TablePopulation {
+  pub capacity: usize,
+  pub num_entities: usize,
+  /// T=0 record of each live entity, used for host-only attributes such as names & colors; None for spawned entities
+  pub rows: Vec<Option<usize>>,
+  pub entity_ids: Vec<u64>,
+  pub next_entity_id: u64,
+}
+
+impl TablePopulation {
+  /// Every T=0 entity is alive, with its record index as its entity_id
+  pub fn new(num_t0_entities: usize, capacity: usize) -> TablePopulation {
+    TablePopulation {
+      capacity,
+      num_entities: num_t0_entities,
+      rows: (0..num_t0_entities).map(Some).collect(),
+      entity_ids: (0..num_t0_entities as u64).collect(),
+      next_entity_id: num_t0_entities as u64,
+    }
+  }
+
+  /// Admits num_spawned entities after the live ones (as far as capacity allows) & drops every entity for which is_alive is false.
+  /// Returns, for each new index, the index the entity occupied before; survivors keep their relative order.
+  pub fn compact(&mut self, num_spawned: usize, is_alive: impl Fn(usize) -> bool) -> Vec<usize> {
+    let total = std::cmp::min(self.num_entities + num_spawned, self.capacity);
+    for _ in self.num_entities..total {
+      self.rows.push(None);
+      self.entity_ids.push(self.next_entity_id);
+      self.next_entity_id += 1;
+    }
+    let keep: Vec<usize> = (0..total).filter(|i| is_alive(*i)).collect();
+    self.rows = keep.iter().map(|i| self.rows[*i]).collect();
+    self.entity_ids = keep.iter().map(|i| self.entity_ids[*i]).collect();
+    self.num_entities = keep.len();
+    keep
+  }
+}
+
+/// A checkpoint file read back into memory; columns is parallel to header.columns
+#[derive(Debug)]
+pub struct Checkpoint {
+  pub header: CheckpointHeader,
+  pub columns: Vec<HostColumn>,
+  pub anim_point_history: Vec<(f32, f32)>,
 }
 
 
@@ -741,13 +3771,25 @@ impl CL_TaggedArgument {
 pub struct CL_NamedTaggedArgument {
   pub name: String,
   pub tagged_argument: std::sync::Arc<CL_TaggedArgument>,
+  /// Qualified columns packed into the elements of a vector buffer, in element order; empty for everything else.
+  pub components: Vec<String>,
 }
 
 impl CL_NamedTaggedArgument {
   pub fn new(name: String, tagged_argument: CL_TaggedArgument) -> CL_NamedTaggedArgument {
     Self {
       name: name,
-      tagged_argument: std::sync::Arc::new(tagged_argument)
+      tagged_argument: std::sync::Arc::new(tagged_argument),
+      components: vec![],
+    }
+  }
+
+  /// A vector buffer packing components (see CL_ColumnVariants); it is named after them so every kernel packing the same columns shares it.
+  pub fn new_packed(components: Vec<String>, tagged_argument: CL_TaggedArgument) -> CL_NamedTaggedArgument {
+    Self {
+      name: apollon_buffer_name(&components.join(",")),
+      tagged_argument: std::sync::Arc::new(tagged_argument),
+      components,
     }
   }
 }
@@ -797,3 +3839,168 @@ pub(crate) use eloc;
 
 
 
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn value_strings(values: &[Value]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+  }
+
+  #[test]
+  fn expand_values_range_is_rounded_to_input_precision() {
+    let def = DataConstantDef::Range { from: 0.02, to: 0.2, step: 0.02 };
+    let values = def.expand_values().unwrap();
+    assert_eq!(value_strings(&values), vec!["0.02", "0.04", "0.06", "0.08", "0.1", "0.12", "0.14", "0.16", "0.18", "0.2"]);
+  }
+
+  #[test]
+  fn expand_values_descending_range() {
+    let def = DataConstantDef::Range { from: 1.0, to: 0.0, step: -0.25 };
+    assert_eq!(value_strings(&def.expand_values().unwrap()), vec!["1", "0.75", "0.5", "0.25", "0"]);
+  }
+
+  #[test]
+  fn expand_values_rejects_unreachable_range() {
+    assert!(DataConstantDef::Range { from: 0.0, to: 1.0, step: -0.1 }.expand_values().is_err());
+    assert!(DataConstantDef::Range { from: 0.0, to: 1.0, step: 0.0 }.expand_values().is_err());
+  }
+
+  #[test]
+  fn event_queue_pops_earliest_time_in_posting_order() {
+    let mut queue = EventQueue::default();
+    queue.push(2.0, 0, 0, 0.0);
+    queue.push(1.0, 1, 1, 0.0);
+    queue.push(2.0, 2, 0, 0.0);
+    queue.push(1.0, 3, 0, 0.0);
+
+    let (time, batch) = queue.pop_batch(None).unwrap();
+    assert_eq!(time, 1.0);
+    assert_eq!(batch.iter().map(|e| e.entity).collect::<Vec<u32>>(), vec![1, 3]);
+
+    let (time, batch) = queue.pop_batch(None).unwrap();
+    assert_eq!(time, 2.0);
+    assert_eq!(batch.iter().map(|e| e.entity).collect::<Vec<u32>>(), vec![0, 2]);
+
+    assert!(queue.pop_batch(None).is_none());
+  }
+
+  #[test]
+  fn event_queue_stops_after_end_time() {
+    let mut queue = EventQueue::default();
+    queue.push(5.0, 0, 0, 0.0);
+    assert!(queue.pop_batch(Some(4.0)).is_none());
+    assert_eq!(queue.events().len(), 1);
+    assert_eq!(queue.pop_batch(Some(5.0)).map(|(time, batch)| (time, batch.len())), Some((5.0, 1)));
+  }
+
+  #[test]
+  fn event_queue_restored_from_checkpoint_keeps_order() {
+    let mut queue = EventQueue::default();
+    queue.push(3.0, 0, 0, 0.0);
+    queue.push(3.0, 1, 0, 0.0);
+    let mut restored = EventQueue::from_events(&queue.events(), queue.next_seq);
+    restored.push(3.0, 2, 0, 0.0);
+    let (_time, batch) = restored.pop_batch(None).unwrap();
+    assert_eq!(batch.iter().map(|e| e.entity).collect::<Vec<u32>>(), vec![0, 1, 2]);
+  }
+
+  #[test]
+  fn expand_values_list_and_single() {
+    let list = DataConstantDef::List(vec![Value::Double(0.1), Value::Integer(2)]);
+    assert_eq!(value_strings(&list.expand_values().unwrap()), vec!["0.1", "2"]);
+    assert!(DataConstantDef::List(vec![]).expand_values().is_err());
+    assert_eq!(value_strings(&DataConstantDef::Single(Value::Integer(7)).expand_values().unwrap()), vec!["7"]);
+  }
+
+  #[test]
+  fn table_population_compact_admits_spawns_and_drops_dead() {
+    let mut population = TablePopulation::new(3, 5);
+    // Entity 1 dies; 3 entities are spawned but only 2 fit
+    let keep = population.compact(3, |i| i != 1);
+    assert_eq!(keep, vec![0, 2, 3, 4]);
+    assert_eq!(population.num_entities, 4);
+    assert_eq!(population.rows, vec![Some(0), Some(2), None, None]);
+    assert_eq!(population.entity_ids, vec![0, 2, 3, 4]);
+    assert_eq!(population.next_entity_id, 5);
+
+    // Entity ids are never reused
+    let keep = population.compact(1, |i| i != 0);
+    assert_eq!(keep, vec![1, 2, 3, 4]);
+    assert_eq!(population.rows, vec![Some(2), None, None, None]);
+    assert_eq!(population.entity_ids, vec![2, 3, 4, 5]);
+  }
+
+  #[derive(serde::Deserialize)]
+  struct ConstantHolder {
+    c: DataConstantDef,
+  }
+
+  fn parse_constant(toml_s: &str) -> Result<DataConstantDef, toml::de::Error> {
+    toml::from_str::<ConstantHolder>(toml_s).map(|holder| holder.c)
+  }
+
+  #[test]
+  fn data_constant_list_of_pairs_is_a_schedule() {
+    match parse_constant("c = [[0, 0.05], [500, 0.2]]").unwrap() {
+      DataConstantDef::Schedule(schedule) => {
+        assert_eq!(schedule.interpolation, ScheduleInterpolation::Step);
+        assert_eq!(schedule.keyframes.iter().map(|(step, _v)| *step).collect::<Vec<u64>>(), vec![0, 500]);
+      }
+      other => panic!("expected a schedule, got {:?}", other),
+    }
+    assert!(matches!(parse_constant("c = [0.05, 0.2]").unwrap(), DataConstantDef::List(_)));
+    assert!(parse_constant("c = [0.05, [500, 0.2]]").is_err());
+  }
+
+  #[test]
+  fn schedule_step_interpolation_holds_each_keyframe() {
+    let schedule = DataConstantSchedule::new(vec![(10, Value::Double(1.0)), (20, Value::Double(2.0))], ScheduleInterpolation::Step).unwrap();
+    assert_eq!(schedule.value_at_step(0).to_string(), "1");
+    assert_eq!(schedule.value_at_step(10).to_string(), "1");
+    assert_eq!(schedule.value_at_step(19).to_string(), "1");
+    assert_eq!(schedule.value_at_step(20).to_string(), "2");
+    assert_eq!(schedule.value_at_step(1000).to_string(), "2");
+  }
+
+  #[test]
+  fn schedule_linear_interpolation_between_keyframes() {
+    let schedule = DataConstantSchedule::new(vec![(100, Value::Integer(0)), (200, Value::Double(1.0))], ScheduleInterpolation::Linear).unwrap();
+    assert_eq!(schedule.value_at_step(50).to_string(), "0");
+    assert_eq!(schedule.value_at_step(100).to_string(), "0");
+    assert_eq!(schedule.value_at_step(150).to_string(), "0.5");
+    assert_eq!(schedule.value_at_step(175).to_string(), "0.75");
+    assert_eq!(schedule.value_at_step(200).to_string(), "1");
+    assert_eq!(schedule.value_at_step(300).to_string(), "1");
+  }
+
+  #[test]
+  fn schedule_sorts_keyframes() {
+    let schedule = DataConstantSchedule::new(vec![(500, Value::Double(0.2)), (0, Value::Double(0.05)), (250, Value::Double(0.1))], ScheduleInterpolation::Step).unwrap();
+    assert_eq!(schedule.keyframes.iter().map(|(step, _v)| *step).collect::<Vec<u64>>(), vec![0, 250, 500]);
+    assert_eq!(schedule.value_at_step(300).to_string(), "0.1");
+    match parse_constant("c = { keyframes = [[10, 2.0], [0, 0.0]], interpolation = \"linear\" }").unwrap() {
+      DataConstantDef::Schedule(schedule) => assert_eq!(schedule.value_at_step(5).to_string(), "1"),
+      other => panic!("expected a schedule, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn graph_csr_from_unsorted_edges() {
+    // Entity 1 has no edges; edges leaving entity 2 keep their input order
+    let edges = vec![
+      (2, 0, vec![Value::Double(0.5)]),
+      (0, 1, vec![Value::Double(1.5)]),
+      (3, 2, vec![Value::Double(2.5)]),
+      (2, 3, vec![Value::Double(3.5)]),
+      (0, 2, vec![Value::Double(4.5)]),
+    ];
+    let graph = GraphCSR::from_edges("agents", 4, &["weight".to_string()], &edges);
+    assert_eq!(graph.row_offsets, vec![0, 2, 2, 4, 5]);
+    assert_eq!(graph.col_indices, vec![1, 2, 0, 3, 2]);
+    assert_eq!(value_strings(&graph.edge_attributes["weight"]), vec!["1.5", "4.5", "0.5", "3.5", "2.5"]);
+    assert_eq!(graph.edge_sources(), vec![0, 0, 2, 2, 3]);
+  }
+}
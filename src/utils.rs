@@ -7,6 +7,9 @@ use crate::structs;
 /// General-purpose alias used to refer to loosly-typed lists of dictionaries, such as t0 data and intermediate sim steps.
 pub type ListedData = Vec<HashMap<String, structs::Value>>;
 
+/// The T=0 data of every entity table by name; the primary table is stored under SimControl.table_name.
+pub type TableData = HashMap<String, ListedData>;
+
 // ld == "Listed Data", it's shape must be Vec<Map<string, object>>
 pub async fn read_ld_file(path: &std::path::Path) -> ListedData {
   let mut v: Vec<HashMap<String, structs::Value>> = vec![];
@@ -70,9 +73,13 @@ pub async fn read_cl_kernel_file(path: &std::path::Path) -> Result<structs::CL_K
   if let Ok(file_string_content) = tokio::fs::read_to_string(path).await {
     if let Ok(mut file_toml_content) = toml::from_str::<structs::CL_Kernels>(&file_string_content) {
       v.kernel.append(&mut file_toml_content.kernel);
+      v.reduction.append(&mut file_toml_content.reduction);
+      v.global_buffer.append(&mut file_toml_content.global_buffer);
     }
     else if let Ok(mut file_json_content) = serde_jsonrc::from_str::<structs::CL_Kernels>(&file_string_content) {
       v.kernel.append(&mut file_json_content.kernel);
+      v.reduction.append(&mut file_json_content.reduction);
+      v.global_buffer.append(&mut file_json_content.global_buffer);
     }
     else {
       let mut sub_err_strs = String::new();
@@ -103,10 +110,22 @@ pub async fn read_simcontrol_file(path: &std::path::Path) -> Result<structs::Sim
     // First parse the format w/ keys under [simulation]
     if let Ok(mut file_toml_content) = toml::from_str::<structs::SimControl_file>(&file_string_content) {
       file_toml_content.simulation.data_constants.extend(file_toml_content.data_constants);
+      file_toml_content.simulation.schema.extend(file_toml_content.schema);
+      file_toml_content.simulation.tables.extend(file_toml_content.table);
+      file_toml_content.simulation.fields.extend(file_toml_content.field);
+      if file_toml_content.world.is_some() {
+        file_toml_content.simulation.world = file_toml_content.world;
+      }
       return Ok(file_toml_content.simulation);
     }
     else if let Ok(mut file_json_content) = serde_jsonrc::from_str::<structs::SimControl_file>(&file_string_content) {
       file_json_content.simulation.data_constants.extend(file_json_content.data_constants);
+      file_json_content.simulation.schema.extend(file_json_content.schema);
+      file_json_content.simulation.tables.extend(file_json_content.table);
+      file_json_content.simulation.fields.extend(file_json_content.field);
+      if file_json_content.world.is_some() {
+        file_json_content.simulation.world = file_json_content.world;
+      }
       return Ok(file_json_content.simulation);
     }
 
@@ -122,7 +141,243 @@ pub async fn read_simcontrol_file(path: &std::path::Path) -> Result<structs::Sim
   return Err(Box::from( format!("Error, simcontrol file cannot be read b/c it is not TOML or JSON data in the expected format: {}", path.display() ) ));
 }
 
-pub async fn write_ld_file(args: &structs::Args, ld: &ListedData, path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>>  {
+/// Fills every array data constant of sc & cl_kernels which names a file (and holds no elements yet) with that file's records,
+/// one row per record holding the values of the constant's columns.
+pub async fn load_array_constant_files(sc: &mut structs::SimControl, cl_kernels: &mut [structs::CL_Kernel]) -> Result<(), Box<dyn std::error::Error>> {
+  let mut arrays: Vec<(String, &mut structs::ArrayConstant)> = vec![];
+  for (name, def) in sc.data_constants.iter_mut() {
+    if let structs::DataConstantDef::Array(array) = def {
+      arrays.push((name.clone(), array));
+    }
+  }
+  for cl_kernel in cl_kernels.iter_mut() {
+    for constant in cl_kernel.data_constants.iter_mut() {
+      if let Some(array) = constant.array.as_mut() {
+        arrays.push((format!("{}.{}", cl_kernel.name, constant.name), array));
+      }
+    }
+  }
+
+  for (name, array) in arrays {
+    let file = match &array.file {
+      Some(file) if array.rows() < 1 => file.clone(),
+      _ => continue,
+    };
+    let ld_data = read_ld_file(&file).await;
+    if ld_data.is_empty() {
+      return Err(format!("Array data constant {} reads {}, which holds no records", name, file.display()).into());
+    }
+    let columns: Vec<String> = if !array.columns.is_empty() {
+      array.columns.clone()
+    }
+    else if ld_data[0].len() == 1 {
+      ld_data[0].keys().cloned().collect()
+    }
+    else {
+      let mut file_columns: Vec<&String> = ld_data[0].keys().collect();
+      file_columns.sort();
+      return Err(format!("Array data constant {} reads {}, which has the columns {:?}; list those forming each row in `columns`", name, file.display(), file_columns).into());
+    };
+    let mut rows: Vec<Vec<structs::Value>> = vec![];
+    for (record_i, record) in ld_data.iter().enumerate() {
+      let mut row: Vec<structs::Value> = vec![];
+      for column in columns.iter() {
+        row.push(record.get(column).cloned().ok_or_else(|| format!("Array data constant {}: record {} of {} has no column {}", name, record_i, file.display(), column))?);
+      }
+      rows.push(row);
+    }
+    array.array = if columns.len() == 1 {
+      structs::ArrayValues::Flat(rows.into_iter().flatten().collect())
+    }
+    else {
+      structs::ArrayValues::Rows(rows)
+    };
+    array.validate().map_err(|e| format!("Array data constant {} from {}: {}", name, file.display(), e))?;
+  }
+  Ok(())
+}
+
+/// Validates every field of sc & reads the initial cell values of those with an input file, which is either an image
+/// (resized to the field & read as brightness from 0.0 to 1.0) or a .csv file of height rows of width values without a header.
+pub async fn load_field_inputs(sc: &mut structs::SimControl) -> Result<(), Box<dyn std::error::Error>> {
+  for field_i in 0..sc.fields.len() {
+    let field = &sc.fields[field_i];
+    if field.name.contains('.') || sc.fields[..field_i].iter().any(|f| f.name == field.name) {
+      return Err(format!("field {} must have a unique name without '.'", &field.name).into());
+    }
+    if field.width < 1 || field.height < 1 {
+      return Err(format!("field {} must have a width & height of at least 1 cell", &field.name).into());
+    }
+    if !structs::FIELD_TYPES.contains(&field.cl_type.as_str()) {
+      return Err(format!("field {} has type {}; fields hold one of {:?}", &field.name, &field.cl_type, structs::FIELD_TYPES).into());
+    }
+    if structs::SPATIAL_INDEX_BUFFERS.contains(&field.name.as_str()) || structs::EVENT_BUFFERS.iter().any(|(name, _type)| *name == field.name) || structs::GRAPH_BUFFERS.contains(&field.name.as_str()) {
+      return Err(format!("field {} has the name of a buffer managed by apollon", &field.name).into());
+    }
+    let [x_min, y_min, x_max, y_max] = field.world_bounds(sc);
+    if !(x_max > x_min && y_max > y_min) {
+      return Err(format!("field {} has empty bounds [{}, {}, {}, {}]", &field.name, x_min, y_min, x_max, y_max).into());
+    }
+
+    let input = match &field.input {
+      Some(input) => input.clone(),
+      None => continue,
+    };
+    let ext = input.extension().and_then(std::ffi::OsStr::to_str).unwrap_or("").to_lowercase();
+    let mut input_values: Vec<f64> = Vec::with_capacity(field.num_cells());
+    if ext == "csv" {
+      let file_string_content = tokio::fs::read_to_string(&input).await.map_err(structs::eloc!(format!("field {}: {}", &field.name, input.display())))?;
+      let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .from_reader(file_string_content.as_bytes());
+      for (row_i, record) in rdr.records().enumerate() {
+        let record = record.map_err(structs::eloc!(format!("field {}: {}", &field.name, input.display())))?;
+        if record.len() != field.width {
+          return Err(format!("field {}: row {} of {} holds {} values, but the field is {} cells wide", &field.name, row_i, input.display(), record.len(), field.width).into());
+        }
+        for (col_i, cell) in record.iter().enumerate() {
+          input_values.push(cell.parse::<f64>().map_err(|e| format!("field {}: row {} column {} of {}: {}", &field.name, row_i, col_i, input.display(), e))?);
+        }
+      }
+      if input_values.len() != field.num_cells() {
+        return Err(format!("field {}: {} holds {} rows, but the field is {} cells high", &field.name, input.display(), input_values.len() / field.width, field.height).into());
+      }
+    }
+    else {
+      let img = image::ImageReader::open(&input)?.decode().map_err(structs::eloc!(format!("field {}: {}", &field.name, input.display())))?;
+      let img = img.resize_exact(field.width as u32, field.height as u32, image::imageops::FilterType::Triangle).to_luma8();
+      input_values.extend(img.pixels().map(|px| px.0[0] as f64 / 255.0));
+    }
+    sc.fields[field_i].input_values = input_values;
+  }
+  Ok(())
+}
+
+/// Reads input_data_file_path & the input of every [[table]], applying the schema to each.
+pub async fn read_table_data(sc: &structs::SimControl) -> Result<TableData, Box<dyn std::error::Error>> {
+  let mut tables = TableData::new();
+
+  let mut t0_data = read_ld_file(&sc.input_data_file_path).await;
+  apply_schema(&mut t0_data, &sc.schema).map_err(structs::eloc!(format!("{}", sc.input_data_file_path.display())))?;
+  tables.insert(sc.table_name.clone(), t0_data);
+
+  for table in sc.tables.iter() {
+    if table.name.contains('.') {
+      return Err(format!("Table name '{}' may not contain '.', which separates table & column names", table.name).into());
+    }
+    if tables.contains_key(&table.name) {
+      return Err(format!("Table '{}' is declared more than once (the table read from input_data_file_path is named '{}')", table.name, sc.table_name).into());
+    }
+    let mut table_data = read_ld_file(&table.input).await;
+    apply_schema(&mut table_data, &table.schema).map_err(structs::eloc!(format!("table {}: {}", table.name, table.input.display())))?;
+    tables.insert(table.name.clone(), table_data);
+  }
+
+  Ok(tables)
+}
+
+/// Reads sc.graph's edge list & resolves both ends of every edge against the entities of its table.
+pub async fn read_graph(sc: &structs::SimControl, tables: &TableData) -> Result<Option<structs::GraphCSR>, Box<dyn std::error::Error>> {
+  let graph_def = match &sc.graph {
+    Some(graph_def) => graph_def,
+    None => return Ok(None),
+  };
+  let table = graph_def.table.as_deref().unwrap_or(sc.table_name.as_str());
+  let ld_data = tables.get(table).ok_or_else(|| format!("graph connects entities of table '{}', which is not declared", table))?;
+  let edge_data = read_ld_file(&graph_def.input).await;
+
+  // Edges refer to entities by the string form of their ID, so IDs read as integers from one file & strings from another still match
+  let mut entity_indicies: HashMap<String, u32> = HashMap::new();
+  if let Some(ref id_column) = graph_def.id_column {
+    for (row_i, record) in ld_data.iter().enumerate() {
+      let id = record.get(id_column).ok_or_else(|| format!("graph id_column is '{}', but entity {} of table '{}' has no such column", id_column, row_i, table))?;
+      if entity_indicies.insert(id.to_string(), row_i as u32).is_some() {
+        return Err(format!("graph id_column '{}' holds the ID {} more than once in table '{}'", id_column, id.to_string(), table).into());
+      }
+    }
+  }
+  let resolve = |edge_i: usize, record: &HashMap<String, structs::Value>, column: &str| -> Result<u32, Box<dyn std::error::Error>> {
+    let id = record.get(column).ok_or_else(|| format!("edge {} of {} has no column '{}'", edge_i, graph_def.input.display(), column))?;
+    let entity_i = match graph_def.id_column {
+      Some(_) => entity_indicies.get(&id.to_string()).copied(),
+      None => match id {
+        structs::Value::Integer(i) if *i >= 0 && (*i as usize) < ld_data.len() => Some(*i as u32),
+        _ => None,
+      },
+    };
+    entity_i.ok_or_else(|| format!("edge {} of {} refers to entity {}, which is not in table '{}'", edge_i, graph_def.input.display(), id.to_string(), table).into())
+  };
+
+  let mut attribute_names: Vec<String> = edge_data.iter()
+    .flat_map(|record| record.keys())
+    .filter(|column| **column != graph_def.source_column && **column != graph_def.target_column)
+    .cloned()
+    .collect();
+  attribute_names.sort();
+  attribute_names.dedup();
+  if let Some(name) = attribute_names.iter().find(|name| structs::GRAPH_BUFFERS.contains(&format!("edge_{}", name).as_str())) {
+    return Err(format!("{} has an edge attribute named '{}', which would collide with the graph buffer edge_{}", graph_def.input.display(), name, name).into());
+  }
+
+  let mut edges: Vec<(u32, u32, Vec<structs::Value>)> = Vec::with_capacity(edge_data.len());
+  for (edge_i, record) in edge_data.iter().enumerate() {
+    let source = resolve(edge_i, record, &graph_def.source_column)?;
+    let target = resolve(edge_i, record, &graph_def.target_column)?;
+    let attributes: Vec<structs::Value> = attribute_names.iter().map(|name| record.get(name).cloned().unwrap_or(structs::Value::Integer(0))).collect();
+    if !graph_def.directed {
+      edges.push((target, source, attributes.clone()));
+    }
+    edges.push((source, target, attributes));
+  }
+
+  Ok(Some(structs::GraphCSR::from_edges(table, ld_data.len(), &attribute_names, &edges)))
+}
+
+/// Validates ld against schema, converting every present value to its declared type & filling in defaults for missing values.
+/// Errors name the offending record & column; records missing a non-nullable column without a default are errors.
+pub fn apply_schema(ld: &mut ListedData, schema: &HashMap<String, structs::ColumnSchema>) -> Result<(), Box<dyn std::error::Error>> {
+  for (row_i, record) in ld.iter_mut().enumerate() {
+    for (column, column_schema) in schema.iter() {
+      // CSV files produce empty strings for empty cells; for non-string columns these are missing values
+      let is_missing = match record.get(column) {
+        None => true,
+        Some(structs::Value::String(s)) => s.is_empty() && column_schema.v_type != structs::ValueType::String,
+        Some(_) => false,
+      };
+
+      if is_missing {
+        if let Some(default_val) = &column_schema.default {
+          let default_val = column_schema.v_type.coerce(default_val).map_err(|e| format!("schema default for column '{}': {}", column, e))?;
+          record.insert(column.to_string(), default_val);
+        }
+        else if column_schema.nullable {
+          record.remove(column);
+        }
+        else {
+          return Err(format!("Record {} has no value for column '{}', which the schema declares as non-nullable without a default", row_i, column).into());
+        }
+      }
+      else if let Some(val) = record.get_mut(column) {
+        *val = column_schema.v_type.coerce(val).map_err(|e| format!("Record {} column '{}': {}", row_i, column, e))?;
+      }
+    }
+  }
+  Ok(())
+}
+
+pub async fn write_ld_file(args: &structs::Args, ld: &ListedData, schema: &HashMap<String, structs::ColumnSchema>, path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>>  {
+  // Values read back from kernel buffers carry the buffer's type; convert them back to their declared types so outputs can be re-used as inputs.
+  let mut typed_ld: ListedData;
+  let ld = if !schema.is_empty() {
+    typed_ld = ld.clone();
+    apply_schema(&mut typed_ld, schema)?;
+    &typed_ld
+  }
+  else {
+    ld
+  };
+
   // Format is selected based off file path extension
   let ext = path.extension()
     .and_then(std::ffi::OsStr::to_str)
@@ -164,6 +419,7 @@ pub async fn write_ld_file_csv(ld: &ListedData, path: &std::path::Path) -> Resul
             .read(true)
             .write(true)
             .create(true)
+            .truncate(true)
             .open(path)?;
   let mut wtr = csv::Writer::from_writer(fd);
 
@@ -188,7 +444,7 @@ pub async fn write_ld_file_csv(ld: &ListedData, path: &std::path::Path) -> Resul
       if let Some(val) = record.get(key) {
         match val {
           structs::Value::Integer(i) => svalue_row.push(format!("{}", i)),
-          structs::Value::Double(d)  => svalue_row.push(format!("{}", d)),
+          structs::Value::Double(d)  => svalue_row.push(format!("{:?}", d)), // Debug keeps the ".0" on whole numbers so they read back as Doubles
           structs::Value::String(s)  => svalue_row.push(format!("{}", s)),
         }
       }
@@ -336,6 +592,202 @@ pub fn inplace_update_simcontrol_from_args(simcontrol: &mut structs::SimControl,
     simcontrol.gis_color_attr = gis_color_attr.to_string();
   }
 
+  if let Some(trajectory_file_path) = &cli_args.trajectory_file_path {
+    println!("Overriding simcontrol trajectory_file_path={} with cli arg value ={}", simcontrol.trajectory_file_path.display(), trajectory_file_path.display());
+    simcontrol.trajectory_file_path = trajectory_file_path.clone();
+  }
+
+  if let Some(trajectory_step_period) = &cli_args.trajectory_step_period {
+    println!("Overriding simcontrol trajectory_step_period={} with cli arg value ={}", simcontrol.trajectory_step_period, trajectory_step_period);
+    simcontrol.trajectory_step_period = *trajectory_step_period;
+  }
+
+  if let Some(global_buffers_file_path) = &cli_args.global_buffers_file_path {
+    println!("Overriding simcontrol global_buffers_file_path={} with cli arg value ={}", simcontrol.global_buffers_file_path.display(), global_buffers_file_path.display());
+    simcontrol.global_buffers_file_path = global_buffers_file_path.clone();
+  }
+
+  if let Some(stats_file_path) = &cli_args.stats_file_path {
+    println!("Overriding simcontrol stats_file_path={} with cli arg value ={}", simcontrol.stats_file_path.display(), stats_file_path.display());
+    simcontrol.stats_file_path = stats_file_path.clone();
+  }
+
+  if let Some(checkpoint_file_path) = &cli_args.checkpoint_file_path {
+    println!("Overriding simcontrol checkpoint_file_path={} with cli arg value ={}", simcontrol.checkpoint_file_path.display(), checkpoint_file_path.display());
+    simcontrol.checkpoint_file_path = checkpoint_file_path.clone();
+  }
+
+  if let Some(checkpoint_step_period) = &cli_args.checkpoint_step_period {
+    println!("Overriding simcontrol checkpoint_step_period={} with cli arg value ={}", simcontrol.checkpoint_step_period, checkpoint_step_period);
+    simcontrol.checkpoint_step_period = *checkpoint_step_period;
+  }
+
+  if let Some(ensemble) = &cli_args.ensemble {
+    println!("Overriding simcontrol ensemble_size={} with cli arg value ={}", simcontrol.ensemble_size, ensemble);
+    simcontrol.ensemble_size = *ensemble;
+  }
+
+  if let Some(seed) = &cli_args.seed {
+    println!("Overriding simcontrol random_seed={} with cli arg value ={}", simcontrol.random_seed, seed);
+    simcontrol.random_seed = *seed;
+  }
+
+  // --data-constant values pin a constant, which removes it from any parameter sweep or schedule declared in the simcontrol file
+  for dc in cli_args.data_constant.iter() {
+    if let Some(existing_def) = simcontrol.data_constants.get(&dc.name) {
+      if existing_def.as_single().is_none() {
+        println!("Overriding simcontrol data_constants.{}={:?} with cli arg value ={}", dc.name, existing_def, dc.value.to_string());
+        simcontrol.data_constants.insert(dc.name.clone(), structs::DataConstantDef::Single(dc.value.clone()));
+      }
+    }
+  }
+
+}
+
+
+/// Expands `{run}` and `{<data constant name>}` placeholders in an output path for one run of a parameter sweep.
+/// When several runs share a path holding neither `{run}` nor the placeholder of every swept constant, the run number is
+/// appended to the file stem so runs do not overwrite each other.
+pub fn template_run_path(path: &std::path::Path, num_runs: usize, run_i: usize, combination: &[(String, structs::Value)]) -> std::path::PathBuf {
+  let path_s = path.to_string_lossy().to_string();
+  if path_s == "/dev/null" || path_s == "NUL" {
+    return path.to_path_buf();
+  }
+
+  let mut templated_s = path_s.replace("{run}", &format!("{}", run_i));
+  for (name, value) in combination.iter() {
+    templated_s = templated_s.replace(&format!("{{{}}}", name), &value.to_string());
+  }
+
+  let names_every_run = path_s.contains("{run}") || combination.iter().all(|(name, _value)| path_s.contains(&format!("{{{}}}", name)));
+  if !names_every_run && num_runs > 1 {
+    let path = std::path::PathBuf::from(templated_s);
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(String::new);
+    let file_name = match path.extension() {
+      Some(ext) => format!("{}-run{}.{}", stem, run_i, ext.to_string_lossy()),
+      None => format!("{}-run{}", stem, run_i),
+    };
+    return path.with_file_name(file_name);
+  }
+
+  templated_s.into()
+}
+
+
+/// Returns the definition of the data constant a scalar kernel argument named variable_name is bound to, searching
+/// --data-constant args, then simcontrol data_constants (swept constants are pinned to a single value per run by main_async),
+/// then the kernel's own data_constants. Scheduled constants are returned as a DataConstantDef::Schedule, arrays as a DataConstantDef::Array.
+pub fn resolve_data_constant(
+  args: &structs::Args,
+  sc: &structs::SimControl,
+  cl_kernel: &structs::CL_Kernel,
+  variable_name: &str,
+) -> Option<structs::DataConstantDef>
+{
+  for dc in args.data_constant.iter() {
+    if dc.name == variable_name {
+      return Some(structs::DataConstantDef::Single(dc.value.clone()));
+    }
+  }
+
+  if let Some(def) = sc.data_constants.get(variable_name) {
+    if !def.is_swept() {
+      return Some(def.clone());
+    }
+  }
+
+  for constant in cl_kernel.data_constants.iter() {
+    if constant.name == variable_name {
+      return Some(match (&constant.schedule, &constant.array) {
+        (Some(schedule), _) => structs::DataConstantDef::Schedule(schedule.clone()),
+        (None, Some(array)) => structs::DataConstantDef::Array(array.clone()),
+        (None, None) => structs::DataConstantDef::Single(constant.value.clone()),
+      });
+    }
+  }
+
+  None
+}
+
+
+/// Returns the data column bound to a kernel pointer argument.
+/// An entry in cl_kernel.colmap is authoritative and must name a column present in ld_data;
+/// arguments missing from colmap fall back to matching the argument name, then its lowercase and uppercase forms.
+pub fn resolve_kernel_arg_column(
+  cl_kernel: &structs::CL_Kernel,
+  ld_data: &ListedData,
+  variable_name: &str,
+) -> Result<String, Box<dyn std::error::Error>>
+{
+  if let Some(mapped_column) = cl_kernel.colmap.get(variable_name).and_then(|mapping| mapping.column()) {
+    if !ld_data.is_empty() && !ld_data.iter().any(|record| record.contains_key(mapped_column)) {
+      return Err(format!(
+        "Kernel {} maps argument '{}' to column '{}' via colmap, but no record in the input data has a column named '{}'",
+        &cl_kernel.name, variable_name, mapped_column, mapped_column
+      ).into());
+    }
+    return Ok(mapped_column.to_string());
+  }
+
+  for candidate in [variable_name.to_string(), variable_name.to_lowercase(), variable_name.to_uppercase()] {
+    if ld_data.iter().any(|record| record.contains_key(&candidate)) {
+      return Ok(candidate);
+    }
+  }
+
+  Ok(variable_name.to_string())
+}
+
+
+/// Resolves the (table, column) a kernel argument binds to. A colmap value "<table>.<column>" naming a declared
+/// entity table binds that table's column; anything else is resolved by resolve_kernel_arg_column within the kernel's own table.
+pub fn resolve_kernel_arg_table_column(
+  sc: &structs::SimControl,
+  cl_kernel: &structs::CL_Kernel,
+  tables: &TableData,
+  variable_name: &str,
+) -> Result<(String, String), Box<dyn std::error::Error>>
+{
+  if let Some(mapped_column) = cl_kernel.colmap.get(variable_name).and_then(|mapping| mapping.column()) {
+    return resolve_colmap_column(sc, cl_kernel, tables, variable_name, mapped_column);
+  }
+
+  let kernel_table = cl_kernel.table_name(sc);
+  let ld_data = tables.get(kernel_table).ok_or_else(|| format!("Kernel {} runs over table '{}', which is not declared", &cl_kernel.name, kernel_table))?;
+  Ok((kernel_table.to_string(), resolve_kernel_arg_column(cl_kernel, ld_data, variable_name)?))
+}
+
+/// Resolves the (table, column) of a column named in colmap for argument variable_name; "<table>.<column>" naming a declared
+/// entity table refers to that table's column, anything else to a column of the kernel's own table.
+pub fn resolve_colmap_column(
+  sc: &structs::SimControl,
+  cl_kernel: &structs::CL_Kernel,
+  tables: &TableData,
+  variable_name: &str,
+  mapped_column: &str,
+) -> Result<(String, String), Box<dyn std::error::Error>>
+{
+  if let Some((table, column)) = mapped_column.split_once('.') {
+    if let Some(table_ld_data) = tables.get(table) {
+      if !table_ld_data.is_empty() && !table_ld_data.iter().any(|record| record.contains_key(column)) {
+        return Err(format!(
+          "Kernel {} maps argument '{}' to column '{}' of table '{}' via colmap, but no record of that table has a column named '{}'",
+          &cl_kernel.name, variable_name, column, table, column
+        ).into());
+      }
+      return Ok((table.to_string(), column.to_string()));
+    }
+  }
+
+  let kernel_table = cl_kernel.table_name(sc);
+  let ld_data = tables.get(kernel_table).ok_or_else(|| format!("Kernel {} runs over table '{}', which is not declared", &cl_kernel.name, kernel_table))?;
+  if !ld_data.is_empty() && !ld_data.iter().any(|record| record.contains_key(mapped_column)) {
+    return Err(format!(
+      "Kernel {} maps argument '{}' to column '{}' via colmap, but no record in the input data has a column named '{}'",
+      &cl_kernel.name, variable_name, mapped_column, mapped_column
+    ).into());
+  }
+  Ok((kernel_table.to_string(), mapped_column.to_string()))
 }
 
 
@@ -366,30 +818,24 @@ pub fn ld_data_to_kernel_data(
       let type_name = k.get_arg_type_name(arg_i)?;
       let type_name = type_name.trim_end_matches('*'); // Types like 'int*' end with a star, which we do not use b/c we have is_pointer.
       let variable_name = k.get_arg_name(arg_i)?; //.unwrap_or(String::new());
-      let variable_name_lowercase = variable_name.to_lowercase();
-      let variable_name_uppercase = variable_name.to_uppercase();
 
       if is_pointer {
 
-        // Lookup data in ld_data w/ fuzzy string matching from all records;
+        // Lookup data in ld_data from the column given by colmap, or w/ fuzzy string matching from all records;
         // We must allocate a [T] because of the signature required by enqueue_write_buffer.
         // Because our goal is to hold massive quantities of data, we limit the buffer to some moderate stack-sized value and loop over it w/ blocking CL writes.
 
+        let column_name = resolve_kernel_arg_column(cl_kernel, ld_data, &variable_name).map_err(structs::eloc!())?;
+
         let mut ld_values: Vec<structs::Value> = vec![];
 
         for record in ld_data.iter() {
-          if let Some(val) = record.get(&variable_name) {
-            ld_values.push(val.clone());
-          }
-          else if let Some(val) = record.get(&variable_name_lowercase) {
-            ld_values.push(val.clone());
-          }
-          else if let Some(val) = record.get(&variable_name_uppercase) {
+          if let Some(val) = record.get(&column_name) {
             ld_values.push(val.clone());
           }
           else {
             if args.verbose > 1 {
-              println!("[ Warning ] Missing value for simulation data column {}, 0.0 will be used for this record.", variable_name);
+              println!("[ Warning ] Missing value for simulation data column {}, 0.0 will be used for this record.", column_name);
             }
             ld_values.push(structs::Value::Integer(0)); // Default value regardless of type is 0, b/c we allow ld_values to contain different types & unify later
           }
@@ -522,9 +968,9 @@ pub fn ld_data_to_kernel_data(
           }
         }
 
-        // Look through simcontrol toml file
+        // Look through simcontrol toml file; swept constants are pinned to a single value per run by main_async
         if value.is_none() {
-          if let Some(val_ref) = sc.data_constants.get(&variable_name) {
+          if let Some(val_ref) = sc.data_constants.get(&variable_name).and_then(structs::DataConstantDef::as_single) {
             value = Some( structs::CL_TaggedArgument::from_value(val_ref, &type_name) );
           }
         }
@@ -562,16 +1008,18 @@ pub fn ld_data_to_kernel_data(
 pub fn ld_data_to_kernel_data_named(
     args: &structs::Args,
     sc: &structs::SimControl,
-    ld_data: &ListedData,
-    context: &opencl3::context::Context,
+    inputs: &structs::SimInputs,
     cl_kernel: &structs::CL_Kernel,
     k: &opencl3::kernel::Kernel,
     queue: &opencl3::command_queue::CommandQueue,
     events: &Vec<opencl3::types::cl_event>
   ) -> Result<Vec<structs::CL_NamedTaggedArgument>, Box<dyn std::error::Error>>
 {
+  let structs::SimInputs { tables, context, reductions, global_buffers, graph, .. } = *inputs;
   let mut kernel_data = vec![];
 
+  let kernel_table = cl_kernel.table_name(sc);
+  let ld_data = tables.get(kernel_table).ok_or_else(|| format!("Kernel {} runs over table '{}', which is not declared; tables are {:?}", &cl_kernel.name, kernel_table, sc.table_names()))?;
   let work_size = ld_data.len();
   if let Ok(argc) = k.num_args() {
     for arg_i in 0..argc {
@@ -581,192 +1029,229 @@ pub fn ld_data_to_kernel_data_named(
         k.get_arg_type_name(arg_i), k.get_arg_name(arg_i)
       );
       */
-      let is_pointer = k.get_arg_address_qualifier(arg_i)? == 4507;
-      let is_constant = k.get_arg_type_qualifier(arg_i)? == 1;
+      // Pointers into the constant address space (ie `constant float* table`) are read-only buffers as well
+      let address_qualifier = k.get_arg_address_qualifier(arg_i)?;
+      let is_pointer = address_qualifier == 4507 || address_qualifier == 4509;
+      let is_constant = k.get_arg_type_qualifier(arg_i)? == 1 || address_qualifier == 4509;
       let type_name = k.get_arg_type_name(arg_i)?;
       let type_name = type_name.trim_end_matches('*'); // Types like 'int*' end with a star, which we do not use b/c we have is_pointer.
       let variable_name = k.get_arg_name(arg_i)?; //.unwrap_or(String::new());
-      let variable_name_lowercase = variable_name.to_lowercase();
-      let variable_name_uppercase = variable_name.to_uppercase();
 
       if is_pointer {
 
-        // Lookup data in ld_data w/ fuzzy string matching from all records;
+        // Lookup data in ld_data from the column given by colmap, or w/ fuzzy string matching from all records;
         // We must allocate a [T] because of the signature required by enqueue_write_buffer.
         // Because our goal is to hold massive quantities of data, we limit the buffer to some moderate stack-sized value and loop over it w/ blocking CL writes.
 
-        let mut ld_values: Vec<structs::Value> = vec![];
-
-        for record in ld_data.iter() {
-          if let Some(val) = record.get(&variable_name) {
-            ld_values.push(val.clone());
-          }
-          else if let Some(val) = record.get(&variable_name_lowercase) {
-            ld_values.push(val.clone());
-          }
-          else if let Some(val) = record.get(&variable_name_uppercase) {
-            ld_values.push(val.clone());
-          }
-          else {
-            if args.verbose > 1 {
-              println!("[ Warning ] Missing value for simulation data column {}, 0.0 will be used for this record.", variable_name);
+        // The spatial index buffers are filled on-device by structs::CL_SpatialIndex before every step
+        if let Some(spatial_index) = &sc.spatial_index {
+          if structs::SPATIAL_INDEX_BUFFERS.contains(&variable_name.as_str()) {
+            if !is_constant || type_name != "uint" {
+              return Err(format!("Kernel {} must declare {} as `global const uint* {}`", &cl_kernel.name, &variable_name, &variable_name).into());
             }
-            ld_values.push(structs::Value::Integer(0)); // Default value regardless of type is 0, b/c we allow ld_values to contain different types & unify later
+            let len = if variable_name == "sorted_index" {
+              let index_table = spatial_index.table.as_deref().unwrap_or(sc.table_name.as_str());
+              sc.table_capacity(index_table, tables.get(index_table).map(|ld| ld.len()).unwrap_or(0))
+            }
+            else {
+              spatial_index.num_cells(sc)
+            };
+            kernel_data.push(structs::CL_NamedTaggedArgument::new(
+              structs::apollon_buffer_name(&variable_name),
+              zeroed_cl_buffer(context, queue, "uint", len)?
+            ));
+            continue;
           }
         }
 
-        let buffer_rw = if is_constant { structs::RWColumn::Read(String::new()) } else { structs::RWColumn::Write(String::new()) };
-
-        // Now we match on the CL target type & call into the generic write_values_to_cl_buffer helper routine.
-        match type_name {
-          "uchar" => {
+        // The event buffers are written & read by main.rs around every event batch; see src/apollon_events.cl
+        if sc.mode == structs::SimulationMode::Event {
+          if let Some((_name, event_type_name)) = structs::EVENT_BUFFERS.iter().find(|(name, _type)| *name == variable_name) {
+            let is_input = variable_name.starts_with("event_");
+            if type_name != *event_type_name || is_constant != is_input {
+              return Err(format!("Kernel {} must declare {} as `global {}{}* {}`", &cl_kernel.name, &variable_name, if is_input { "const " } else { "" }, event_type_name, &variable_name).into());
+            }
+            let len = if variable_name == "post_event_count" { 1 } else { sc.event_buffer_capacity };
             kernel_data.push(structs::CL_NamedTaggedArgument::new(
-              variable_name.clone(),
-              structs::CL_TaggedArgument::Uint8Buffer(
-                write_values_to_cl_buffer::<opencl3::types::cl_uchar>(
-                context, queue, &ld_values, buffer_rw,
-                |int_val| int_val as opencl3::types::cl_uchar,
-                |double_val| double_val as opencl3::types::cl_uchar,
-              )?)
+              structs::apollon_buffer_name(&variable_name),
+              zeroed_cl_buffer(context, queue, event_type_name, len)?
             ));
+            continue;
           }
-          "ushort" => {
-            kernel_data.push(structs::CL_NamedTaggedArgument::new(
-              variable_name.clone(),
-              structs::CL_TaggedArgument::Uint16Buffer(
-                write_values_to_cl_buffer::<opencl3::types::cl_ushort>(
-                context, queue, &ld_values, buffer_rw,
-                |int_val| int_val as opencl3::types::cl_ushort,
-                |double_val| double_val as opencl3::types::cl_ushort,
-              )?)
-            ));
+        }
+
+        // The graph's CSR buffers & edge attributes are bound by name; see src/apollon_graph.cl
+        if let Some(graph_values) = graph.and_then(|graph| graph.buffer_values(&variable_name)) {
+          if structs::GRAPH_BUFFERS.contains(&variable_name.as_str()) && (!is_constant || type_name != "uint") {
+            return Err(format!("Kernel {} must declare {} as `global const uint* {}`", &cl_kernel.name, &variable_name, &variable_name).into());
           }
-          "uint" => {
-            kernel_data.push(
-              structs::CL_NamedTaggedArgument::new(
-              variable_name.clone(),
-              structs::CL_TaggedArgument::Uint32Buffer(
-                write_values_to_cl_buffer::<opencl3::types::cl_uint>(
-                context, queue, &ld_values, buffer_rw,
-                |int_val| int_val as opencl3::types::cl_uint,
-                |double_val| double_val as opencl3::types::cl_uint,
-              )?)
-            ));
+          let buffer_rw = if is_constant { structs::RWColumn::Read(String::new()) } else { structs::RWColumn::ReadWrite(String::new()) };
+          kernel_data.push(structs::CL_NamedTaggedArgument::new(
+            structs::apollon_buffer_name(&variable_name),
+            values_to_cl_buffer(context, queue, type_name, &graph_values, buffer_rw)?
+          ));
+          continue;
+        }
+
+        // Global buffers are bound by name (or colmap) instead of a column; every kernel binding one shares the same buffer
+        let global_buffer_name = cl_kernel.colmap.get(&variable_name).and_then(|mapping| mapping.column()).unwrap_or(&variable_name);
+        if let Some(global_buffer) = global_buffers.iter().find(|b| &b.name == global_buffer_name) {
+          if type_name != global_buffer.cl_type {
+            return Err(format!("Kernel {} declares global buffer {} as {} but it holds {}", &cl_kernel.name, &global_buffer.name, type_name, &global_buffer.cl_type).into());
           }
-          "ulong" => {
-            kernel_data.push(structs::CL_NamedTaggedArgument::new(
-              variable_name.clone(),
-              structs::CL_TaggedArgument::Uint64Buffer(
-                write_values_to_cl_buffer::<opencl3::types::cl_ulong>(
-                context, queue, &ld_values, buffer_rw,
-                |int_val| int_val as opencl3::types::cl_ulong,
-                |double_val| double_val as opencl3::types::cl_ulong,
-              )?)
-            ));
+          kernel_data.push(structs::CL_NamedTaggedArgument::new(
+            structs::apollon_buffer_name(&global_buffer.name),
+            values_to_cl_buffer(context, queue, type_name, &global_buffer.initial_values(), structs::RWColumn::ReadWrite(String::new()))?
+          ));
+          continue;
+        }
+
+        // Fields are bound like global buffers, by name (or colmap); see src/apollon_field.cl
+        if let Some(field) = sc.fields.iter().find(|f| &f.name == global_buffer_name) {
+          if type_name != field.cl_type {
+            return Err(format!("Kernel {} declares field {} as {} but it holds {}", &cl_kernel.name, &field.name, type_name, &field.cl_type).into());
           }
+          // Every kernel binding the field shares its buffer, so it is writable even for kernels declaring it const
+          kernel_data.push(structs::CL_NamedTaggedArgument::new(
+            structs::apollon_buffer_name(&field.name),
+            values_to_cl_buffer(context, queue, type_name, &field.initial_values(), structs::RWColumn::ReadWrite(String::new()))?
+          ));
+          continue;
+        }
 
-          "char" => {
-            kernel_data.push(structs::CL_NamedTaggedArgument::new(
-              variable_name.clone(),
-              structs::CL_TaggedArgument::Int8Buffer(
-                write_values_to_cl_buffer::<opencl3::types::cl_char>(
-                context, queue, &ld_values, buffer_rw,
-                |int_val| int_val as opencl3::types::cl_char,
-                |double_val| double_val as opencl3::types::cl_char,
-              )?)
-            ));
+        // Array data constants are uploaded once as read-only lookup tables; see src/apollon_lut.cl
+        if let Some(structs::DataConstantDef::Array(array)) = resolve_data_constant(args, sc, cl_kernel, &variable_name) {
+          if !is_constant {
+            return Err(format!("Kernel {} must declare array data constant {} as `global const {}*` or `constant {}*`", &cl_kernel.name, &variable_name, type_name, type_name).into());
           }
-          "short" => {
-            kernel_data.push(structs::CL_NamedTaggedArgument::new(
-              variable_name.clone(),
-              structs::CL_TaggedArgument::Int16Buffer(
-                write_values_to_cl_buffer::<opencl3::types::cl_short>(
-                context, queue, &ld_values, buffer_rw,
-                |int_val| int_val as opencl3::types::cl_short,
-                |double_val| double_val as opencl3::types::cl_short,
-              )?)
-            ));
+          if let Some(ref array_type) = array.cl_type {
+            if array_type != type_name {
+              return Err(format!("Kernel {} declares array data constant {} as {} but it holds {}", &cl_kernel.name, &variable_name, type_name, array_type).into());
+            }
           }
-          "int" => {
-            kernel_data.push(structs::CL_NamedTaggedArgument::new(
-              variable_name.clone(),
-              structs::CL_TaggedArgument::Int32Buffer(
-                write_values_to_cl_buffer::<opencl3::types::cl_int>(
-                context, queue, &ld_values, buffer_rw,
-                |int_val| int_val as opencl3::types::cl_int,
-                |double_val| double_val as opencl3::types::cl_int,
-              )?)
-            ));
+          kernel_data.push(structs::CL_NamedTaggedArgument::new(
+            structs::apollon_buffer_name(&variable_name),
+            values_to_cl_buffer(context, queue, type_name, &array.flat_values(), structs::RWColumn::Read(String::new()))?
+          ));
+          continue;
+        }
+
+        // Vector arguments pack one column per element into a buffer kept in sync with the columns by structs::CL_ColumnVariants
+        if let Some((vector_type, element_type, width)) = structs::cl_vector_type(type_name) {
+          let components = match cl_kernel.colmap.get(&variable_name) {
+            Some(structs::ColumnMapping::Components(components)) if components.len() == width => components,
+            _ => {
+              return Err(format!(
+                "Kernel {} declares {} as {}*; map it to the {} columns packed into its elements in colmap, ie {} = [\"X0\", \"Y0\", ...]",
+                &cl_kernel.name, &variable_name, vector_type, width, &variable_name
+              ).into());
+            }
+          };
+          let mut component_columns: Vec<String> = vec![];
+          let mut component_values: Vec<Vec<structs::Value>> = vec![];
+          for component in components.iter() {
+            let (column_table, column_name) = resolve_colmap_column(sc, cl_kernel, tables, &variable_name, component).map_err(structs::eloc!())?;
+            if column_table != kernel_table && !is_constant {
+              return Err(format!(
+                "Kernel {} packs column '{}' of table '{}' into argument '{}'; columns of other tables are read-only, so declare the argument const",
+                &cl_kernel.name, &column_name, &column_table, &variable_name
+              ).into());
+            }
+            if !component_columns.is_empty() && sc.split_qualified_column_name(&component_columns[0]).0 != column_table {
+              return Err(format!("Kernel {} packs columns of several tables into argument '{}'; every element must come from the same table", &cl_kernel.name, &variable_name).into());
+            }
+            component_values.push(kernel_column_values(args, sc, tables, cl_kernel, (&column_table, &column_name), element_type, is_constant)?);
+            component_columns.push(sc.qualified_column_name(&column_table, &column_name));
           }
-          "long" => {
-            kernel_data.push(structs::CL_NamedTaggedArgument::new(
-              variable_name.clone(),
-              structs::CL_TaggedArgument::Int64Buffer(
-                write_values_to_cl_buffer::<opencl3::types::cl_long>(
-                context, queue, &ld_values, buffer_rw,
-                |int_val| int_val as opencl3::types::cl_long,
-                |double_val| double_val as opencl3::types::cl_long,
-              )?)
-            ));
+          let stride = structs::cl_vector_stride(width);
+          let mut packed_values: Vec<structs::Value> = Vec::with_capacity(component_values[0].len() * stride);
+          for i in 0..component_values[0].len() {
+            for element_i in 0..stride {
+              packed_values.push(component_values.get(element_i).map(|values| values[i].clone()).unwrap_or(structs::Value::Integer(0)));
+            }
           }
+          // The pack kernel writes this buffer whenever its columns change, so it is writable even for const arguments
+          kernel_data.push(structs::CL_NamedTaggedArgument::new_packed(
+            component_columns,
+            values_to_cl_buffer(context, queue, element_type, &packed_values, structs::RWColumn::ReadWrite(String::new()))?
+          ));
+          continue;
+        }
 
-          "float" => {
-            kernel_data.push(structs::CL_NamedTaggedArgument::new(
-              variable_name.clone(),
-              structs::CL_TaggedArgument::FloatBuffer(
-                write_values_to_cl_buffer::<opencl3::types::cl_float>(
-                context, queue, &ld_values, buffer_rw,
-                |int_val| int_val as opencl3::types::cl_float,
-                |double_val| double_val as opencl3::types::cl_float,
-              )?)
-            ));
-          }
-          "double" => {
-            kernel_data.push(structs::CL_NamedTaggedArgument::new(
-              variable_name.clone(),
-              structs::CL_TaggedArgument::DoubleBuffer(
-                write_values_to_cl_buffer::<opencl3::types::cl_double>(
-                context, queue, &ld_values, buffer_rw,
-                |int_val| int_val as opencl3::types::cl_double,
-                |double_val| double_val as opencl3::types::cl_double,
-              )?)
-            ));
-          }
-
-          unk => {
-            println!("Unknown CL Buffer type: {}", unk);
-            panic!("Unknown CL Buffer type!");
-          }
+        let (column_table, column_name) = resolve_kernel_arg_table_column(sc, cl_kernel, tables, &variable_name).map_err(structs::eloc!())?;
+        if column_table != kernel_table && !is_constant {
+          return Err(format!(
+            "Kernel {} binds argument '{}' to column '{}' of table '{}'; columns of other tables are read-only, so declare the argument const",
+            &cl_kernel.name, &variable_name, &column_name, &column_table
+          ).into());
         }
+        let buffer_name = sc.qualified_column_name(&column_table, &column_name);
+        let ld_values = kernel_column_values(args, sc, tables, cl_kernel, (&column_table, &column_name), type_name, is_constant)?;
+
+        let buffer_rw = if is_constant { structs::RWColumn::Read(String::new()) } else { structs::RWColumn::Write(String::new()) };
+
+        // Now we match on the CL target type & call into the generic write_values_to_cl_buffer helper routine.
+        kernel_data.push(structs::CL_NamedTaggedArgument::new(
+          buffer_name.clone(),
+          values_to_cl_buffer(context, queue, type_name, &ld_values, buffer_rw)?
+        ));
 
       }
       else {
         // This is a constant, look up in cl_kernel.data_constants and if not exists lookup in sc
         let mut value: Option<structs::CL_TaggedArgument> = None;
 
-        // Look through args.data_constant
-        if value.is_none() {
-          for dc in args.data_constant.iter() {
-            if dc.name == variable_name {
-              value = Some( structs::CL_TaggedArgument::from_value(&dc.value, &type_name) );
+        // Reserved arguments (sim_step, random_seed, ...) take priority; main.rs updates them before every step
+        if let Some(reserved_val) = sc.reserved_arg_value(&variable_name, 0, 0.0, work_size, sc.table_capacity(kernel_table, work_size)) {
+          value = Some( structs::CL_TaggedArgument::from_value(&reserved_val, type_name) );
+        }
+
+        // Vector constants (ie `float4 gravity`) are array data constants of exactly as many elements, ie gravity = { array = [0.0, -9.8, 0.0, 0.0] }
+        if let Some((vector_type, element_type, width)) = structs::cl_vector_type(type_name) {
+          match resolve_data_constant(args, sc, cl_kernel, &variable_name) {
+            Some(structs::DataConstantDef::Array(array)) if array.flat_values().len() == width && array.cl_type.as_deref().unwrap_or(element_type) == element_type => {
+              value = Some( structs::CL_TaggedArgument::from_vector_values(&array.flat_values(), vector_type).map_err(structs::eloc!(variable_name.clone()))? );
+            }
+            _ => {
+              return Err(format!(
+                "Kernel {} declares {} as {}; define it as an array data constant of {} {} values, ie {} = {{ array = [...] }}",
+                &cl_kernel.name, &variable_name, vector_type, width, element_type, &variable_name
+              ).into());
             }
           }
         }
 
-        // Look through simcontrol toml file
+        // Reduction statistics are rewritten by main.rs whenever their reduction runs; 0 until then
+        if value.is_none() && reductions.iter().any(|r| r.ops.iter().any(|op| r.stat_name(*op) == variable_name)) {
+          value = Some( structs::CL_TaggedArgument::from_value(&structs::Value::Double(0.0), type_name) );
+        }
+
+        // Look through args.data_constant, then the simcontrol file, then kernel constants
         if value.is_none() {
-          if let Some(val_ref) = sc.data_constants.get(&variable_name) {
-            value = Some( structs::CL_TaggedArgument::from_value(val_ref, &type_name) );
+          if let Some(val) = resolve_data_constant(args, sc, cl_kernel, &variable_name).and_then(|def| def.value_at_step(0)) {
+            value = Some( structs::CL_TaggedArgument::from_value(&val, type_name) );
           }
         }
 
-        // Look through kernel constants
+        // <field>_width, <field>_height, <field>_origin_x, ... describe the shape of each field
         if value.is_none() {
-          for constant in cl_kernel.data_constants.iter() {
-            if constant.name == variable_name {
-              // Found it!
-              value = Some( structs::CL_TaggedArgument::from_value(&constant.value, &type_name) );
-              break;
+          if let Some(val) = sc.fields.iter().find_map(|field| field.scalar_value(sc, &variable_name)) {
+            value = Some( structs::CL_TaggedArgument::from_value(&val, type_name) );
+          }
+        }
+
+        if value.is_none() && variable_name == "edge_count" {
+          if let Some(graph) = graph {
+            value = Some( structs::CL_TaggedArgument::from_value(&structs::Value::Integer(graph.num_edges() as i64), type_name) );
+          }
+        }
+
+        // <name>_len & <name>_cols give the number of rows & elements per row of array data constant <name>
+        if value.is_none() {
+          for (suffix, is_len) in [("_len", true), ("_cols", false)] {
+            if let Some(structs::DataConstantDef::Array(array)) = variable_name.strip_suffix(suffix).and_then(|array_name| resolve_data_constant(args, sc, cl_kernel, array_name)) {
+              let dimension = if is_len { array.rows() } else { array.cols() };
+              value = Some( structs::CL_TaggedArgument::from_value(&structs::Value::Integer(dimension as i64), type_name) );
             }
           }
         }
@@ -777,9 +1262,11 @@ pub fn ld_data_to_kernel_data_named(
             panic!("Required constant not found in kernels.toml data_constants, or simcontrol.toml data_constants, or passed as --data-constant VAR=val");
           }
           Some(cl_tagged_value) => {
+            // entity_count & entity_capacity differ between tables, so like a column they are qualified by the kernel's table
+            let arg_name = if variable_name == "entity_count" || variable_name == "entity_capacity" { sc.qualified_column_name(kernel_table, &variable_name) } else { variable_name.clone() };
             kernel_data.push(
               structs::CL_NamedTaggedArgument::new(
-                variable_name.clone(), cl_tagged_value
+                arg_name, cl_tagged_value
               )
             );
           }
@@ -794,6 +1281,155 @@ pub fn ld_data_to_kernel_data_named(
 }
 
 
+/// Returns writable buffers holding each component column of packed, a vector buffer kernel_args of cl_kernel returned, as its element type.
+/// Packed buffers are unpacked into these for output, rendering & compaction, so main.rs adds those no kernel binds as scalars.
+pub fn packed_component_buffers(
+    args: &structs::Args,
+    sc: &structs::SimControl,
+    tables: &TableData,
+    context: &opencl3::context::Context,
+    queue: &opencl3::command_queue::CommandQueue,
+    cl_kernel: &structs::CL_Kernel,
+    packed: &structs::CL_NamedTaggedArgument,
+  ) -> Result<Vec<structs::CL_NamedTaggedArgument>, Box<dyn std::error::Error>>
+{
+  let element_type = packed.tagged_argument.cl_type_name();
+  let mut component_buffers = vec![];
+  for component in packed.components.iter() {
+    let (column_table, column_name) = sc.split_qualified_column_name(component);
+    let ld_values = kernel_column_values(args, sc, tables, cl_kernel, (column_table, column_name), element_type, false)?;
+    component_buffers.push(structs::CL_NamedTaggedArgument::new(
+      component.clone(),
+      values_to_cl_buffer(context, queue, element_type, &ld_values, structs::RWColumn::Write(String::new()))?
+    ));
+  }
+  Ok(component_buffers)
+}
+
+/// Returns the values of column, a (table, column name) pair, to upload for an argument of cl_kernel of type type_name;
+/// one per entity slot of the table, with slots past the T=0 entities set to 0.
+fn kernel_column_values(
+  args: &structs::Args,
+  sc: &structs::SimControl,
+  tables: &TableData,
+  cl_kernel: &structs::CL_Kernel,
+  column: (&str, &str),
+  type_name: &str,
+  is_constant: bool,
+) -> Result<Vec<structs::Value>, Box<dyn std::error::Error>>
+{
+  let (column_table, column_name) = column;
+  let column_ld_data = &tables[column_table];
+  let column_schema = sc.table_schema(column_table).get(column_name);
+  if let Some(column_schema) = column_schema {
+    match column_schema.v_type.cl_type_name() {
+      None => {
+        return Err(format!("Kernel {} binds column '{}' of table '{}', which the schema declares as a string", &cl_kernel.name, column_name, column_table).into());
+      }
+      Some(schema_cl_type) if schema_cl_type != type_name && args.verbose > 0 => {
+        println!("[ Warning ] Kernel {} reads column '{}' as {} but the schema declares {}; values will be converted", &cl_kernel.name, column_name, type_name, column_schema.v_type.name());
+      }
+      _ => { }
+    }
+  }
+
+  let mut ld_values: Vec<structs::Value> = vec![];
+  let capacity = sc.table_capacity(column_table, column_ld_data.len());
+
+  if sc.has_dynamic_population(column_table) && (column_name == "alive" || column_name == "entity_id" || column_name == "spawn_count") {
+    // Population columns are managed by apollon; see SimControl.max_entities
+    match column_name {
+      "alive" => {
+        ld_values = (0..capacity).map(|i| structs::Value::Integer(if i < column_ld_data.len() { 1 } else { 0 })).collect();
+      }
+      "entity_id" => {
+        if !is_constant {
+          return Err(format!("Kernel {} must declare entity_id const; entity IDs are assigned by apollon", &cl_kernel.name).into());
+        }
+        ld_values = (0..capacity).map(|i| structs::Value::Integer(i as i64)).collect();
+      }
+      _ => {
+        if is_constant || type_name != "uint" {
+          return Err(format!("Kernel {} must declare spawn_count as `global uint* spawn_count` so it may atomic_inc it", &cl_kernel.name).into());
+        }
+        ld_values = vec![structs::Value::Integer(0)];
+      }
+    }
+  }
+  else {
+    for record in column_ld_data.iter() {
+      if let Some(val) = record.get(column_name) {
+        ld_values.push(val.clone());
+      }
+      else if let Some(default_val) = column_schema.and_then(|cs| cs.default.as_ref()) {
+        ld_values.push(default_val.clone());
+      }
+      else {
+        if args.verbose > 1 {
+          println!("[ Warning ] Missing value for simulation data column {}, 0.0 will be used for this record.", column_name);
+        }
+        ld_values.push(structs::Value::Integer(0)); // Default value regardless of type is 0, b/c we allow ld_values to contain different types & unify later
+      }
+    }
+    // Slots past the T=0 entities are free for spawned entities
+    ld_values.resize(capacity, structs::Value::Integer(0));
+  }
+  Ok(ld_values)
+}
+
+/// A ReadWrite buffer of len zeros of the given CL type, for buffers managed by apollon
+fn zeroed_cl_buffer(
+  context: &opencl3::context::Context,
+  queue: &opencl3::command_queue::CommandQueue,
+  cl_type_name: &str,
+  len: usize,
+) -> Result<structs::CL_TaggedArgument, Box<dyn std::error::Error>> {
+  values_to_cl_buffer(context, queue, cl_type_name, &vec![structs::Value::Integer(0); len], structs::RWColumn::ReadWrite(String::new()))
+}
+
+/// Allocates a buffer of the CL type named cl_type_name (ie "float") holding values, converted to that type.
+pub fn values_to_cl_buffer(
+  context: &opencl3::context::Context,
+  queue: &opencl3::command_queue::CommandQueue,
+  cl_type_name: &str,
+  values: &Vec<structs::Value>,
+  buffer_rw: structs::RWColumn,
+) -> Result<structs::CL_TaggedArgument, Box<dyn std::error::Error>> {
+  match cl_type_name {
+    "uchar" => Ok(structs::CL_TaggedArgument::Uint8Buffer(write_values_to_cl_buffer::<opencl3::types::cl_uchar>(
+      context, queue, values, buffer_rw, |int_val| int_val as opencl3::types::cl_uchar, |double_val| double_val as opencl3::types::cl_uchar,
+    )?)),
+    "ushort" => Ok(structs::CL_TaggedArgument::Uint16Buffer(write_values_to_cl_buffer::<opencl3::types::cl_ushort>(
+      context, queue, values, buffer_rw, |int_val| int_val as opencl3::types::cl_ushort, |double_val| double_val as opencl3::types::cl_ushort,
+    )?)),
+    "uint" => Ok(structs::CL_TaggedArgument::Uint32Buffer(write_values_to_cl_buffer::<opencl3::types::cl_uint>(
+      context, queue, values, buffer_rw, |int_val| int_val as opencl3::types::cl_uint, |double_val| double_val as opencl3::types::cl_uint,
+    )?)),
+    "ulong" => Ok(structs::CL_TaggedArgument::Uint64Buffer(write_values_to_cl_buffer::<opencl3::types::cl_ulong>(
+      context, queue, values, buffer_rw, |int_val| int_val as opencl3::types::cl_ulong, |double_val| double_val as opencl3::types::cl_ulong,
+    )?)),
+    "char" => Ok(structs::CL_TaggedArgument::Int8Buffer(write_values_to_cl_buffer::<opencl3::types::cl_char>(
+      context, queue, values, buffer_rw, |int_val| int_val as opencl3::types::cl_char, |double_val| double_val as opencl3::types::cl_char,
+    )?)),
+    "short" => Ok(structs::CL_TaggedArgument::Int16Buffer(write_values_to_cl_buffer::<opencl3::types::cl_short>(
+      context, queue, values, buffer_rw, |int_val| int_val as opencl3::types::cl_short, |double_val| double_val as opencl3::types::cl_short,
+    )?)),
+    "int" => Ok(structs::CL_TaggedArgument::Int32Buffer(write_values_to_cl_buffer::<opencl3::types::cl_int>(
+      context, queue, values, buffer_rw, |int_val| int_val as opencl3::types::cl_int, |double_val| double_val as opencl3::types::cl_int,
+    )?)),
+    "long" => Ok(structs::CL_TaggedArgument::Int64Buffer(write_values_to_cl_buffer::<opencl3::types::cl_long>(
+      context, queue, values, buffer_rw, |int_val| int_val as opencl3::types::cl_long, |double_val| double_val as opencl3::types::cl_long,
+    )?)),
+    "float" => Ok(structs::CL_TaggedArgument::FloatBuffer(write_values_to_cl_buffer::<opencl3::types::cl_float>(
+      context, queue, values, buffer_rw, |int_val| int_val as opencl3::types::cl_float, |double_val| double_val as opencl3::types::cl_float,
+    )?)),
+    "double" => Ok(structs::CL_TaggedArgument::DoubleBuffer(write_values_to_cl_buffer::<opencl3::types::cl_double>(
+      context, queue, values, buffer_rw, |int_val| int_val as opencl3::types::cl_double, |double_val| double_val as opencl3::types::cl_double,
+    )?)),
+    unk => Err(format!("Unknown CL Buffer type: {}", unk).into()),
+  }
+}
+
 fn write_values_to_cl_buffer<T>(
   context: &opencl3::context::Context,
   queue: &opencl3::command_queue::CommandQueue,
@@ -805,8 +1441,6 @@ fn write_values_to_cl_buffer<T>(
   -> Result<opencl3::memory::Buffer::<T>, Box<dyn std::error::Error>>
   where T: Copy
 {
-  const STACK_BUFF_SIZE: usize = 8 * 1024;
-
   // Allocate buffer of size
   let array_len = values.len();
   let cl_memory_flags = match buffer_rw {
@@ -815,41 +1449,49 @@ fn write_values_to_cl_buffer<T>(
     structs::RWColumn::ReadWrite(_) => opencl3::memory::CL_MEM_READ_WRITE
   };
 
+  // CL does not allow zero-sized buffers, so an empty column still allocates 1 element
   let mut cl_buff = unsafe {
-      opencl3::memory::Buffer::<T>::create(&context, cl_memory_flags, array_len, std::ptr::null_mut())?
+      opencl3::memory::Buffer::<T>::create(context, cl_memory_flags, std::cmp::max(1, array_len), std::ptr::null_mut())?
   };
-  let mut cl_buff_write_offset = 0;
-
-  // We write into this over and over again, keeping track of use and making blocking calls to write into cl_buff
-  let mut stack_arr: [T; STACK_BUFF_SIZE] = [i64_to_t(0); STACK_BUFF_SIZE];
-  let mut stack_arr_write_offset = 0;
 
+  // Build a typed host mirror of the column & upload it with a single blocking write
+  let mut host_values: Vec<T> = Vec::with_capacity(array_len);
   for i in 0..values.len() {
-
     match values[i] {
         structs::Value::Integer(i) => {
-          stack_arr[stack_arr_write_offset] = i64_to_t(i);
+          host_values.push(i64_to_t(i));
         }
         structs::Value::Double(d) => {
-          stack_arr[stack_arr_write_offset] = f64_to_t(d);
+          host_values.push(f64_to_t(d));
         }
         structs::Value::String(_) => panic!("Cannot place string value into a CL kernel argument buffer!"),
     }
+  }
 
-    stack_arr_write_offset += 1;
-
-    // If we are at the end of the buffer OR at the last value, make blocking write into cl_buff
-    if stack_arr_write_offset >= STACK_BUFF_SIZE-1 || i == values.len()-1 {
-      let num_items_to_write = if cl_buff_write_offset+STACK_BUFF_SIZE > array_len { array_len - cl_buff_write_offset } else { STACK_BUFF_SIZE };
-      let write_event = unsafe { queue.enqueue_write_buffer(&mut cl_buff, opencl3::types::CL_BLOCKING, cl_buff_write_offset, &stack_arr[0..num_items_to_write], &[])? };
-      cl_buff_write_offset += STACK_BUFF_SIZE;
-      stack_arr_write_offset = 0;
-    }
+  if !host_values.is_empty() {
+    let write_event = unsafe { queue.enqueue_write_buffer(&mut cl_buff, opencl3::types::CL_BLOCKING, 0, &host_values, &[])? };
   }
 
   Ok(cl_buff)
 }
 
+/// Blocking read of every element of buffer into a new Vec, after events complete.
+pub fn read_cl_buffer<T>(
+  queue: &opencl3::command_queue::CommandQueue,
+  buffer: &opencl3::memory::Buffer<T>,
+  events: &[opencl3::types::cl_event],
+)
+  -> Result<Vec<T>, Box<dyn std::error::Error>>
+  where T: Copy + Default
+{
+  use opencl3::memory::ClMem;
+
+  let array_len = buffer.size().map_err(structs::eloc!())? / std::mem::size_of::<T>();
+  let mut host_values: Vec<T> = vec![T::default(); array_len];
+  let unused_read_event = unsafe { queue.enqueue_read_buffer(buffer, opencl3::types::CL_BLOCKING, 0, &mut host_values, events).map_err(structs::eloc!())? };
+  Ok(host_values)
+}
+
 
 
 pub fn kernel_data_update_ld_data(
@@ -992,6 +1634,7 @@ pub fn kernel_data_update_ld_data(
       structs::CL_TaggedArgument::Int64(unused) => { /* NOP */ }
       structs::CL_TaggedArgument::Float(unused) => { /* NOP */ }
       structs::CL_TaggedArgument::Double(unused) => { /* NOP */ }
+      structs::CL_TaggedArgument::Vector(_, unused) => { /* NOP */ }
 
       /*unhandled => {
         println!("Unhandled variant of structs::CL_TaggedArgument: {:?}", unhandled);
@@ -1143,6 +1786,7 @@ pub fn kernel_data_update_ld_data_named(cli_args: &structs::Args,
       structs::CL_TaggedArgument::Int64(unused) => { /* NOP */ }
       structs::CL_TaggedArgument::Float(unused) => { /* NOP */ }
       structs::CL_TaggedArgument::Double(unused) => { /* NOP */ }
+      structs::CL_TaggedArgument::Vector(_, unused) => { /* NOP */ }
 
       /*unhandled => {
         println!("Unhandled variant of structs::CL_TaggedArgument: {:?}", unhandled);
@@ -1212,6 +1856,1039 @@ fn read_values_from_cl_buffer<T>(
 
 
 
+/// Streams per-step entity states to simcontrol.trajectory_file_path.
+///
+/// CSV files get a "step,entity,<columns...>" header and one row per entity per recorded step.
+/// Every other extension gets a compact little-endian binary columnar format:
+///   header:  b"APTRAJ01", u32 num_columns, then per column: u16 name length, name (utf-8), u8 type length, OpenCL type name (ie "float")
+///   records: u64 step, u64 num_entities, then per column in header order: num_entities contiguous values of that column's type
+pub struct TrajectoryWriter {
+  columns: Vec<String>,
+  is_csv: bool,
+  wrote_header: bool,
+  out: std::io::BufWriter<std::fs::File>,
+}
+
+impl TrajectoryWriter {
+  /// Returns None when path is /dev/null (or NUL), ie trajectory recording is disabled.
+  /// With resume_offset the existing file is cut back to that length & appended to instead of being replaced.
+  pub fn new(path: &std::path::Path, columns: Vec<String>, resume_offset: Option<u64>) -> Result<Option<TrajectoryWriter>, Box<dyn std::error::Error>> {
+    let path_s = path.to_string_lossy();
+    if path_s == "/dev/null" || path_s == "NUL" || path_s.is_empty() {
+      return Ok(None);
+    }
+    let ext = path.extension()
+      .and_then(std::ffi::OsStr::to_str)
+      .unwrap_or("")
+      .to_lowercase();
+    Ok(Some(TrajectoryWriter {
+      columns,
+      is_csv: ext == "csv",
+      wrote_header: resume_offset.unwrap_or(0) > 0,
+      out: std::io::BufWriter::new(open_resumable_output(path, resume_offset)?),
+    }))
+  }
+
+  /// Flushes buffered records & returns the length of the file, which a checkpoint stores as its resume_offset
+  pub fn flushed_len(&mut self) -> Result<u64, Box<dyn std::error::Error>> {
+    use std::io::Seek;
+    Ok(self.out.stream_position()?)
+  }
+
+  pub fn columns(&self) -> &[String] {
+    &self.columns
+  }
+
+  /// Appends one record; host_columns must hold one HostColumn per entry in self.columns(), in the same order.
+  pub fn write_step(&mut self, step: u64, num_entities: usize, host_columns: &[&structs::HostColumn]) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+
+    if host_columns.len() != self.columns.len() {
+      return Err(format!("Logic error! {} trajectory columns given but {} expected", host_columns.len(), self.columns.len()).into());
+    }
+    let num_entities = host_columns.iter().fold(num_entities, |n, c| std::cmp::min(n, c.len()));
+
+    if self.is_csv {
+      if !self.wrote_header {
+        write!(self.out, "step,entity")?;
+        for column in self.columns.iter() {
+          write!(self.out, ",{}", column)?;
+        }
+        writeln!(self.out)?;
+        self.wrote_header = true;
+      }
+      for entity_i in 0..num_entities {
+        write!(self.out, "{},{}", step, entity_i)?;
+        for host_column in host_columns.iter() {
+          match host_column.get_value(entity_i) {
+            structs::Value::Double(d) => write!(self.out, ",{:?}", d)?,
+            other => write!(self.out, ",{}", other.to_string())?,
+          }
+        }
+        writeln!(self.out)?;
+      }
+    }
+    else {
+      if !self.wrote_header {
+        self.out.write_all(b"APTRAJ01")?;
+        self.out.write_all(&(self.columns.len() as u32).to_le_bytes())?;
+        for (column, host_column) in self.columns.iter().zip(host_columns.iter()) {
+          self.out.write_all(&(column.len() as u16).to_le_bytes())?;
+          self.out.write_all(column.as_bytes())?;
+          let type_name = host_column.cl_type_name();
+          self.out.write_all(&(type_name.len() as u8).to_le_bytes())?;
+          self.out.write_all(type_name.as_bytes())?;
+        }
+        self.wrote_header = true;
+      }
+      self.out.write_all(&step.to_le_bytes())?;
+      self.out.write_all(&(num_entities as u64).to_le_bytes())?;
+      for host_column in host_columns.iter() {
+        host_column.write_le_bytes(num_entities, &mut self.out)?;
+      }
+    }
+    Ok(())
+  }
+
+  pub fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+    self.out.flush()?;
+    Ok(())
+  }
+}
+
+
+/// Writes [[reduction]] results to simcontrol.stats_file_path as CSV: a "step,sim_time,<statistics...>" header and one row
+/// per step on which any reduction ran, leaving the statistics of the other reductions empty.
+pub struct StatsWriter {
+  stat_names: Vec<String>,
+  out: std::io::BufWriter<std::fs::File>,
+}
+
+impl StatsWriter {
+  /// Returns None when path is /dev/null (or NUL) or there are no statistics to record.
+  /// With resume_offset the existing file is cut back to that length & appended to instead of being replaced.
+  pub fn new(path: &std::path::Path, stat_names: Vec<String>, resume_offset: Option<u64>) -> Result<Option<StatsWriter>, Box<dyn std::error::Error>> {
+    use std::io::Write;
+    let path_s = path.to_string_lossy();
+    if path_s == "/dev/null" || path_s == "NUL" || path_s.is_empty() || stat_names.is_empty() {
+      return Ok(None);
+    }
+    let mut out = std::io::BufWriter::new(open_resumable_output(path, resume_offset)?);
+    if resume_offset.unwrap_or(0) < 1 {
+      writeln!(out, "step,sim_time,{}", stat_names.join(","))?;
+    }
+    Ok(Some(StatsWriter {
+      stat_names,
+      out,
+    }))
+  }
+
+  /// Appends one row holding every statistic in values
+  pub fn write_step(&mut self, step: u64, sim_time: f64, values: &[(String, f64)]) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+    write!(self.out, "{},{:?}", step, sim_time)?;
+    for stat_name in self.stat_names.iter() {
+      match values.iter().find(|(name, _value)| name == stat_name) {
+        Some((_name, value)) => write!(self.out, ",{:?}", value)?,
+        None => write!(self.out, ",")?,
+      }
+    }
+    writeln!(self.out)?;
+    Ok(())
+  }
+
+  /// Flushes buffered rows & returns the length of the file, which a checkpoint stores as its resume_offset
+  pub fn flushed_len(&mut self) -> Result<u64, Box<dyn std::error::Error>> {
+    use std::io::Seek;
+    Ok(self.out.stream_position()?)
+  }
+
+  pub fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+    self.out.flush()?;
+    Ok(())
+  }
+}
+
+/// Collects the final state (and optionally the trajectory) of every replica of an ensemble & reduces them to
+/// per-entity statistics: <column>_mean, _stddev (sample), _min, _max and _q<percent> for every quantile.
+pub struct EnsembleAccumulator {
+  quantiles: Vec<f64>,
+  pub trajectory_stats: bool,
+  num_replicas: usize,
+  /// column -> replica -> entity
+  final_values: HashMap<String, Vec<Vec<f64>>>,
+  final_columns: Vec<String>,
+  /// step -> column -> replica -> entity
+  trajectory_values: std::collections::BTreeMap<u64, HashMap<String, Vec<Vec<f64>>>>,
+  trajectory_columns: Vec<String>,
+}
+
+impl EnsembleAccumulator {
+  pub fn new(quantiles: &[f64], trajectory_stats: bool) -> Result<EnsembleAccumulator, Box<dyn std::error::Error>> {
+    if let Some(q) = quantiles.iter().find(|q| !(0.0..=1.0).contains(*q)) {
+      return Err(format!("ensemble_quantiles must be within [0, 1], got {}", q).into());
+    }
+    Ok(EnsembleAccumulator {
+      quantiles: quantiles.to_vec(),
+      trajectory_stats,
+      num_replicas: 0,
+      final_values: HashMap::new(),
+      final_columns: vec![],
+      trajectory_values: std::collections::BTreeMap::new(),
+      trajectory_columns: vec![],
+    })
+  }
+
+  /// Adds one replica's final data; columns are the numeric columns to reduce, normally those written by kernels.
+  pub fn add_final(&mut self, ld: &ListedData, columns: &[String]) {
+    self.num_replicas += 1;
+    for column in columns.iter() {
+      if !self.final_columns.contains(column) {
+        self.final_columns.push(column.clone());
+      }
+      let values: Vec<f64> = ld.iter().map(|record| record.get(column).and_then(|v| v.to_f64().ok()).unwrap_or(f64::NAN)).collect();
+      self.final_values.entry(column.clone()).or_default().push(values);
+    }
+  }
+
+  /// Adds one recorded trajectory step of the current replica; host_columns is parallel to columns.
+  pub fn add_trajectory_step(&mut self, step: u64, num_entities: usize, columns: &[String], host_columns: &[&structs::HostColumn]) {
+    if self.trajectory_columns.is_empty() {
+      self.trajectory_columns = columns.to_vec();
+    }
+    let step_values = self.trajectory_values.entry(step).or_default();
+    for (column, host_column) in columns.iter().zip(host_columns.iter()) {
+      let n = std::cmp::min(num_entities, host_column.len());
+      let values: Vec<f64> = (0..n).map(|i| host_column.get_value(i).to_f64().unwrap_or(f64::NAN)).collect();
+      step_values.entry(column.clone()).or_default().push(values);
+    }
+  }
+
+  fn stat_names(&self, column: &str) -> Vec<String> {
+    let mut names = vec![
+      format!("{}_mean", column), format!("{}_stddev", column), format!("{}_min", column), format!("{}_max", column),
+    ];
+    for q in self.quantiles.iter() {
+      // Rounded so quantiles like 0.07 are named X0_q7 rather than X0_q7.000000000000001
+      let percent = (q * 100.0 * 1.0e6).round() / 1.0e6;
+      names.push(format!("{}_q{}", column, percent));
+    }
+    names
+  }
+
+  /// Statistics of one entity across replicas, in the order of stat_names; NaN values (ie missing data) are ignored.
+  fn entity_stats(&self, per_replica: &[Vec<f64>], entity_i: usize) -> Vec<f64> {
+    let mut samples: Vec<f64> = per_replica.iter().filter_map(|values| values.get(entity_i).copied()).filter(|v| !v.is_nan()).collect();
+    let num_stats = 4 + self.quantiles.len();
+    if samples.is_empty() {
+      return vec![f64::NAN; num_stats];
+    }
+    samples.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let stddev = if samples.len() > 1 {
+      (samples.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / (n - 1.0)).sqrt()
+    }
+    else {
+      0.0
+    };
+    let mut stats = vec![mean, stddev, samples[0], samples[samples.len() - 1]];
+    for q in self.quantiles.iter() {
+      // Linear interpolation between the closest ranks
+      let rank = q * (n - 1.0);
+      let lo = rank.floor() as usize;
+      let hi = std::cmp::min(lo + 1, samples.len() - 1);
+      stats.push(samples[lo] + (samples[hi] - samples[lo]) * (rank - lo as f64));
+    }
+    stats
+  }
+
+  /// Writes one record per entity holding t0_data's columns which were not reduced (ie names) plus the statistics of every reduced column.
+  pub async fn write_final(&self, args: &structs::Args, t0_data: &ListedData, path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stats_ld: ListedData = Vec::with_capacity(t0_data.len());
+    for (entity_i, t0_record) in t0_data.iter().enumerate() {
+      let mut record: HashMap<String, structs::Value> = t0_record.iter()
+        .filter(|(column, _v)| !self.final_columns.contains(column))
+        .map(|(column, v)| (column.clone(), v.clone()))
+        .collect();
+      for column in self.final_columns.iter() {
+        for (stat_name, stat) in self.stat_names(column).into_iter().zip(self.entity_stats(&self.final_values[column], entity_i)) {
+          record.insert(stat_name, structs::Value::Double(stat));
+        }
+      }
+      stats_ld.push(record);
+    }
+    if args.verbose > 0 {
+      eprintln!("Writing statistics of {} columns across {} replicas to {}", self.final_columns.len(), self.num_replicas, path.display());
+    }
+    write_ld_file(args, &stats_ld, &HashMap::new(), path).await
+  }
+
+  /// Writes "step,entity,<statistics...>" CSV rows for every recorded step & entity
+  pub fn write_trajectory(&self, path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+
+    let path_s = path.to_string_lossy();
+    if path_s == "/dev/null" || path_s == "NUL" || path_s.is_empty() {
+      return Ok(());
+    }
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    write!(out, "step,entity")?;
+    for column in self.trajectory_columns.iter() {
+      for stat_name in self.stat_names(column) {
+        write!(out, ",{}", stat_name)?;
+      }
+    }
+    writeln!(out)?;
+    for (step, step_values) in self.trajectory_values.iter() {
+      let num_entities = step_values.values().flat_map(|per_replica| per_replica.iter().map(|v| v.len())).max().unwrap_or(0);
+      for entity_i in 0..num_entities {
+        write!(out, "{},{}", step, entity_i)?;
+        for column in self.trajectory_columns.iter() {
+          let stats = match step_values.get(column) {
+            Some(per_replica) => self.entity_stats(per_replica, entity_i),
+            None => vec![f64::NAN; 4 + self.quantiles.len()],
+          };
+          for stat in stats {
+            write!(out, ",{:?}", stat)?;
+          }
+        }
+        writeln!(out)?;
+      }
+    }
+    out.flush()?;
+    Ok(())
+  }
+}
+
+/// Names the file of one replica of an ensemble by replacing `{replica}` in path, or appending -replica<N> to the file stem.
+pub fn template_replica_path(path: &std::path::Path, replica_i: u64) -> std::path::PathBuf {
+  let path_s = path.to_string_lossy().to_string();
+  if path_s == "/dev/null" || path_s == "NUL" {
+    return path.to_path_buf();
+  }
+  if path_s.contains("{replica}") {
+    return path_s.replace("{replica}", &format!("{}", replica_i)).into();
+  }
+  let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+  let file_name = match path.extension() {
+    Some(ext) => format!("{}-replica{}.{}", stem, replica_i, ext.to_string_lossy()),
+    None => format!("{}-replica{}", stem, replica_i),
+  };
+  path.with_file_name(file_name)
+}
+
+
+/// Names the animation segment a run resumed at step writes, ie /tmp/anim.mp4 -> /tmp/anim-from-step5000.mp4.
+/// An encoded video cannot be appended to, so the resumed run continues the animation's frame timestamps in this file instead;
+/// the first CheckpointHeader.anim_frames_encoded frames of the original followed by the segment (ie joined with ffmpeg's concat demuxer)
+/// give the animation of an uninterrupted run.
+pub fn template_resume_segment_path(path: &std::path::Path, step: u64) -> std::path::PathBuf {
+  let path_s = path.to_string_lossy().to_string();
+  if path_s == "/dev/null" || path_s == "NUL" {
+    return path.to_path_buf();
+  }
+  let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+  let file_name = match path.extension() {
+    Some(ext) => format!("{}-from-step{}.{}", stem, step, ext.to_string_lossy()),
+    None => format!("{}-from-step{}", stem, step),
+  };
+  path.with_file_name(file_name)
+}
+
+
+/// Writes a checkpoint file, first to "<path>.tmp" then renamed over path so a crash mid-write never destroys the previous checkpoint.
+///
+/// Layout: b"APCKPT01", u64 header length, the structs::CheckpointHeader as JSON, then for every header column
+/// len little-endian values of its type, then anim_point_history as (f32 x, f32 y) little-endian pairs.
+/// columns must be parallel to header.columns.
+pub fn write_checkpoint(
+  path: &std::path::Path,
+  header: &structs::CheckpointHeader,
+  columns: &[structs::HostColumn],
+  anim_point_history: &[(f32, f32)],
+) -> Result<(), Box<dyn std::error::Error>>
+{
+  use std::io::Write;
+
+  let mut tmp_path = path.as_os_str().to_owned();
+  tmp_path.push(".tmp");
+  let tmp_path = std::path::PathBuf::from(tmp_path);
+
+  let header_json = serde_jsonrc::to_string(header)?;
+  let mut out = std::io::BufWriter::new(std::fs::File::create(&tmp_path).map_err(structs::eloc!(format!("{}", tmp_path.display())))?);
+  out.write_all(b"APCKPT01")?;
+  out.write_all(&(header_json.len() as u64).to_le_bytes())?;
+  out.write_all(header_json.as_bytes())?;
+  for (column, host_column) in header.columns.iter().zip(columns.iter()) {
+    host_column.write_le_bytes(column.len, &mut out)?;
+  }
+  for (x, y) in anim_point_history.iter() {
+    out.write_all(&x.to_le_bytes())?;
+    out.write_all(&y.to_le_bytes())?;
+  }
+  out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+  std::fs::rename(&tmp_path, path).map_err(structs::eloc!(format!("{}", path.display())))?;
+  Ok(())
+}
+
+/// Reads a file written by write_checkpoint
+pub fn read_checkpoint(path: &std::path::Path) -> Result<structs::Checkpoint, Box<dyn std::error::Error>> {
+  use std::io::Read;
+
+  let mut r = std::io::BufReader::new(std::fs::File::open(path).map_err(structs::eloc!(format!("{}", path.display())))?);
+  let mut magic = [0u8; 8];
+  r.read_exact(&mut magic)?;
+  if &magic != b"APCKPT01" {
+    return Err(format!("{} is not an apollon checkpoint file", path.display()).into());
+  }
+  let mut header_len_bytes = [0u8; 8];
+  r.read_exact(&mut header_len_bytes)?;
+  let mut header_json = vec![0u8; u64::from_le_bytes(header_len_bytes) as usize];
+  r.read_exact(&mut header_json)?;
+  let header: structs::CheckpointHeader = serde_jsonrc::from_slice(&header_json)?;
+  if header.format != "apollon-checkpoint-1" {
+    return Err(format!("{} has unsupported checkpoint format {}", path.display(), header.format).into());
+  }
+
+  let mut columns: Vec<structs::HostColumn> = vec![];
+  for column in header.columns.iter() {
+    columns.push(structs::HostColumn::read_le_bytes(&column.cl_type, column.len, &mut r).map_err(structs::eloc!(format!("column {}", column.name)))?);
+  }
+  let mut anim_point_history: Vec<(f32, f32)> = Vec::with_capacity(header.anim_point_history_len);
+  let mut xy_bytes = [0u8; 4];
+  for _ in 0..header.anim_point_history_len {
+    r.read_exact(&mut xy_bytes)?;
+    let x = f32::from_le_bytes(xy_bytes);
+    r.read_exact(&mut xy_bytes)?;
+    let y = f32::from_le_bytes(xy_bytes);
+    anim_point_history.push((x, y));
+  }
+
+  Ok(structs::Checkpoint {
+    header,
+    columns,
+    anim_point_history,
+  })
+}
+
+/// Rejects resuming from checkpoint when the trajectory or stats file could not be continued where the checkpointed run left it,
+/// which would leave it silently missing the steps before the checkpoint. Both must be the checkpointed files, whose lengths
+/// the checkpoint records. (The animation is continued in a segment file instead, see template_resume_segment_path.)
+pub fn check_resumable_outputs(simcontrol: &structs::SimControl, checkpoint: &structs::CheckpointHeader) -> Result<(), Box<dyn std::error::Error>> {
+  let is_disabled = |path: &std::path::Path| { let path_s = path.to_string_lossy(); path_s == "/dev/null" || path_s == "NUL" || path_s.is_empty() };
+  let devnull = structs::serde_default_pathbuf_devnull();
+  let outputs = [
+    ("trajectory_file_path", if simcontrol.trajectory_step_period > 0 { &simcontrol.trajectory_file_path } else { &devnull }, &checkpoint.simcontrol.trajectory_file_path, checkpoint.trajectory_file_offset),
+    ("stats_file_path", &simcontrol.stats_file_path, &checkpoint.simcontrol.stats_file_path, checkpoint.stats_file_offset),
+  ];
+  for (attr_name, path, checkpointed_path, offset) in outputs {
+    if is_disabled(path) {
+      continue;
+    }
+    if path != checkpointed_path || offset.is_none() {
+      return Err(format!("The checkpoint at step {} does not record how much of {} was written, so resuming would leave it without the earlier steps; resume with the checkpointed {} ({}) or /dev/null",
+        checkpoint.step, path.display(), attr_name, checkpointed_path.display()).into());
+    }
+  }
+  Ok(())
+}
+
+/// Creates path, or with resume_offset reopens it & discards everything written after the checkpoint, ie steps a resumed run writes again
+fn open_resumable_output(path: &std::path::Path, resume_offset: Option<u64>) -> Result<std::fs::File, Box<dyn std::error::Error>> {
+  use std::io::Seek;
+  let offset = match resume_offset {
+    Some(offset) => offset,
+    None => return Ok(std::fs::File::create(path).map_err(structs::eloc!(format!("{}", path.display())))?),
+  };
+  let mut file = std::fs::OpenOptions::new().write(true).create(true).truncate(false).open(path).map_err(structs::eloc!(format!("{}", path.display())))?;
+  let len = file.metadata()?.len();
+  if len < offset {
+    return Err(format!("{} holds {} bytes but the checkpoint expects at least {}; it was modified since the checkpoint was written", path.display(), len, offset).into());
+  }
+  file.set_len(offset)?;
+  file.seek(std::io::SeekFrom::End(0))?;
+  Ok(file)
+}
+
+
+/// Evaluates an integer work size expression such as "entities*entities" or "(entity_count + 63) / 64 * 64".
+/// Supports non-negative integer literals, identifiers resolved through lookup, + - * / %, and parentheses.
+pub fn eval_work_size_expr(expr: &str, lookup: &dyn Fn(&str) -> Option<u64>) -> Result<u64, Box<dyn std::error::Error>> {
+  struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    lookup: &'a dyn Fn(&str) -> Option<u64>,
+  }
+
+  impl<'a> Parser<'a> {
+    fn skip_ws(&mut self) {
+      while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+        self.pos += 1;
+      }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+      self.skip_ws();
+      self.chars.get(self.pos).copied()
+    }
+
+    // sum := product (('+' | '-') product)*
+    fn sum(&mut self) -> Result<u64, String> {
+      let mut val = self.product()?;
+      while let Some(op) = self.peek() {
+        if op != '+' && op != '-' {
+          break;
+        }
+        self.pos += 1;
+        let rhs = self.product()?;
+        val = if op == '+' { val.checked_add(rhs) } else { val.checked_sub(rhs) }.ok_or("overflow or negative result")?;
+      }
+      Ok(val)
+    }
+
+    // product := atom (('*' | '/' | '%') atom)*
+    fn product(&mut self) -> Result<u64, String> {
+      let mut val = self.atom()?;
+      while let Some(op) = self.peek() {
+        if op != '*' && op != '/' && op != '%' {
+          break;
+        }
+        self.pos += 1;
+        let rhs = self.atom()?;
+        val = match op {
+          '*' => val.checked_mul(rhs).ok_or("overflow")?,
+          '/' => val.checked_div(rhs).ok_or("division by zero")?,
+          _   => val.checked_rem(rhs).ok_or("division by zero")?,
+        };
+      }
+      Ok(val)
+    }
+
+    // atom := number | identifier | '(' sum ')'
+    fn atom(&mut self) -> Result<u64, String> {
+      match self.peek() {
+        Some('(') => {
+          self.pos += 1;
+          let val = self.sum()?;
+          if self.peek() != Some(')') {
+            return Err("expected ')'".to_string());
+          }
+          self.pos += 1;
+          Ok(val)
+        }
+        Some(c) if c.is_ascii_digit() => {
+          let start = self.pos;
+          while self.pos < self.chars.len() && self.chars[self.pos].is_ascii_digit() {
+            self.pos += 1;
+          }
+          let digits: String = self.chars[start..self.pos].iter().collect();
+          digits.parse::<u64>().map_err(|e| e.to_string())
+        }
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+          let start = self.pos;
+          while self.pos < self.chars.len() && (self.chars[self.pos].is_ascii_alphanumeric() || self.chars[self.pos] == '_') {
+            self.pos += 1;
+          }
+          let identifier: String = self.chars[start..self.pos].iter().collect();
+          (self.lookup)(&identifier).ok_or_else(|| format!("unknown identifier '{}'", identifier))
+        }
+        Some(c) => Err(format!("unexpected '{}'", c)),
+        None => Err("unexpected end of expression".to_string()),
+      }
+    }
+  }
+
+  let mut parser = Parser { chars: expr.chars().collect(), pos: 0, lookup };
+  let val = parser.sum().map_err(|e| format!("\"{}\": {}", expr, e))?;
+  if parser.peek().is_some() {
+    return Err(format!("\"{}\": unexpected '{}'", expr, parser.chars[parser.pos]).into());
+  }
+  Ok(val)
+}
+
+
+/// OpenCL C headers shipped with apollon which kernel sources may `#include "<name>"`
+pub const APOLLON_CL_HEADERS: &[(&str, &str)] = &[
+  ("apollon_rng.h", include_str!("apollon_rng.cl")),
+  ("apollon_grid.h", include_str!("apollon_grid.cl")),
+  ("apollon_events.h", include_str!("apollon_events.cl")),
+  ("apollon_lut.h", include_str!("apollon_lut.cl")),
+  ("apollon_graph.h", include_str!("apollon_graph.cl")),
+  ("apollon_field.h", include_str!("apollon_field.cl")),
+];
+
+/// Replaces every `#include "<header>"` (or `<header>`) line naming one of APOLLON_CL_HEADERS with the header source,
+/// as OpenCL compilers have no include path pointing into apollon. Other lines are left untouched.
+pub fn expand_apollon_includes(source: &str) -> String {
+  let mut expanded = String::with_capacity(source.len());
+  for line in source.lines() {
+    let trimmed = line.trim();
+    let included = trimmed.strip_prefix("#include")
+      .map(|rest| rest.trim().trim_matches(|c| c == '"' || c == '<' || c == '>'))
+      .and_then(|header_name| APOLLON_CL_HEADERS.iter().find(|(name, _src)| *name == header_name));
+    match included {
+      Some((_name, header_src)) => expanded.push_str(header_src),
+      None => expanded.push_str(line),
+    }
+    expanded.push('\n');
+  }
+  expanded
+}
+
+
+/// Compiled programs for kernels generated by apollon itself (conversion kernels and similar), keyed by kernel name.
+/// These are shared between all runs of a parameter sweep so each program is only compiled once.
+pub type CL_ProgramCache = HashMap<String, opencl3::program::Program>;
+
+/// Compiles source into cache under kernel_name if it is not already present, then creates a kernel object from the cached program.
+pub fn build_generated_kernel(
+  context: &opencl3::context::Context,
+  cache: &mut CL_ProgramCache,
+  kernel_name: &str,
+  source: &str,
+) -> Result<opencl3::kernel::Kernel, Box<dyn std::error::Error>>
+{
+  if !cache.contains_key(kernel_name) {
+    let program = opencl3::program::Program::create_and_build_from_source(context, source, "")
+      .map_err(|e| format!("Failed to compile generated kernel {}: {}", kernel_name, e))?;
+    cache.insert(kernel_name.to_string(), program);
+  }
+  let program = cache.get(kernel_name).ok_or("Logic error! Generated program missing from cache")?;
+  Ok(opencl3::kernel::Kernel::create(program, kernel_name)?)
+}
+
+fn cl_source_fp64_pragma(cl_type_names: &[&str]) -> &'static str {
+  if cl_type_names.contains(&"double") {
+    "#pragma OPENCL EXTENSION cl_khr_fp64 : enable\n"
+  }
+  else {
+    ""
+  }
+}
+
+/// Returns (kernel name, kernel source) for a kernel copying every element of a src_cl_type buffer into a dst_cl_type buffer.
+pub fn conversion_kernel_source(src_cl_type: &str, dst_cl_type: &str) -> (String, String) {
+  let kernel_name = format!("apollon_convert_{}_to_{}", src_cl_type, dst_cl_type);
+  let source = format!(r#"{pragma}
+kernel void {kernel_name} (
+    global const {src_t}* src,
+    global {dst_t}* dst
+)
+{{
+    const size_t i = get_global_id(0);
+    dst[i] = convert_{dst_t}(src[i]);
+}}
+"#, pragma=cl_source_fp64_pragma(&[src_cl_type, dst_cl_type]), kernel_name=kernel_name, src_t=src_cl_type, dst_t=dst_cl_type);
+  (kernel_name, source)
+}
+
+/// Returns (kernel name, source) of the pack & unpack kernels of structs::CL_PackedColumns, in that order. Both take the packed buffer
+/// of packed_cl_type elements, stride per entity, followed by one component column of each of component_cl_types.
+pub fn pack_kernel_sources(packed_cl_type: &str, component_cl_types: &[&str], stride: usize) -> [(String, String); 2] {
+  let mut cl_types: Vec<&str> = component_cl_types.to_vec();
+  cl_types.push(packed_cl_type);
+  let pragma = cl_source_fp64_pragma(&cl_types);
+  let name_suffix = format!("{}x{}_{}", packed_cl_type, stride, component_cl_types.join("_"));
+
+  let mut pack_params = String::new();
+  let mut pack_body = String::new();
+  let mut unpack_params = String::new();
+  let mut unpack_body = String::new();
+  for (c, c_t) in component_cl_types.iter().enumerate() {
+    pack_params.push_str(&format!(",\n    global const {c_t}* c{c}", c_t=c_t, c=c));
+    pack_body.push_str(&format!("    packed[i * {stride} + {c}] = convert_{p_t}(c{c}[i]);\n", stride=stride, c=c, p_t=packed_cl_type));
+    unpack_params.push_str(&format!(",\n    global {c_t}* c{c}", c_t=c_t, c=c));
+    unpack_body.push_str(&format!("    c{c}[i] = convert_{c_t}(packed[i * {stride} + {c}]);\n", stride=stride, c=c, c_t=c_t));
+  }
+
+  let pack_name = format!("apollon_pack_{}", name_suffix);
+  let pack_source = format!(r#"{pragma}
+kernel void {kernel_name} (
+    global {p_t}* packed{params}
+)
+{{
+    const size_t i = get_global_id(0);
+{body}}}
+"#, pragma=pragma, kernel_name=pack_name, p_t=packed_cl_type, params=pack_params, body=pack_body);
+
+  let unpack_name = format!("apollon_unpack_{}", name_suffix);
+  let unpack_source = format!(r#"{pragma}
+kernel void {kernel_name} (
+    global const {p_t}* packed{params}
+)
+{{
+    const size_t i = get_global_id(0);
+{body}}}
+"#, pragma=pragma, kernel_name=unpack_name, p_t=packed_cl_type, params=unpack_params, body=unpack_body);
+
+  [(pack_name, pack_source), (unpack_name, unpack_source)]
+}
+
+/// Returns (kernel name, source) of the assign, bitonic sort step, clear & bounds kernels of structs::CL_SpatialIndex, in that order.
+/// The assign kernel reads coordinate columns of x_cl_type & y_cl_type.
+pub fn spatial_index_kernel_sources(x_cl_type: &str, y_cl_type: &str) -> Vec<(String, String)> {
+  let assign_name = format!("apollon_grid_assign_{}_{}", x_cl_type, y_cl_type);
+  let assign_source = expand_apollon_includes(&format!(r#"{pragma}
+#include "apollon_grid.h"
+kernel void {kernel_name} (
+    global const {x_t}* x,
+    global const {y_t}* y,
+    const uint n,
+    const float origin_x,
+    const float origin_y,
+    const float cell_size,
+    const uint grid_width,
+    const uint grid_height,
+    global ulong* sort_keys
+)
+{{
+    const uint i = get_global_id(0);
+    if (i < n) {{
+        const uint cell = apollon_grid_cell_index(apollon_grid_cell((float)x[i], (float)y[i], origin_x, origin_y, cell_size, grid_width, grid_height), grid_width);
+        sort_keys[i] = ((ulong)cell << 32) | i;
+    }}
+    else {{
+        sort_keys[i] = 0xFFFFFFFFFFFFFFFFUL;
+    }}
+}}
+"#, pragma=cl_source_fp64_pragma(&[x_cl_type, y_cl_type]), kernel_name=assign_name, x_t=x_cl_type, y_t=y_cl_type));
+
+  // Keys are unique (they hold the entity index), so the sorted order & with it the in-cell order are deterministic
+  let bitonic_source = r#"
+kernel void apollon_grid_bitonic_step (
+    global ulong* sort_keys,
+    const uint j,
+    const uint k
+)
+{
+    const uint i = get_global_id(0);
+    const uint l = i ^ j;
+    if (l > i) {
+        const ulong a = sort_keys[i];
+        const ulong b = sort_keys[l];
+        const bool ascending = (i & k) == 0;
+        if ((a > b) == ascending) {
+            sort_keys[i] = b;
+            sort_keys[l] = a;
+        }
+    }
+}
+"#.to_string();
+
+  let clear_source = r#"
+kernel void apollon_grid_clear (
+    global uint* cell_start,
+    global uint* cell_end
+)
+{
+    const size_t c = get_global_id(0);
+    cell_start[c] = 0;
+    cell_end[c] = 0;
+}
+"#.to_string();
+
+  let bounds_source = r#"
+kernel void apollon_grid_bounds (
+    global const ulong* sort_keys,
+    const uint n,
+    global uint* cell_start,
+    global uint* cell_end,
+    global uint* sorted_index
+)
+{
+    const uint i = get_global_id(0);
+    const uint cell = (uint)(sort_keys[i] >> 32);
+    sorted_index[i] = (uint)(sort_keys[i] & 0xFFFFFFFFUL);
+    if (i == 0 || (uint)(sort_keys[i - 1] >> 32) != cell) {
+        cell_start[cell] = i;
+    }
+    if (i == n - 1 || (uint)(sort_keys[i + 1] >> 32) != cell) {
+        cell_end[cell] = i + 1;
+    }
+}
+"#.to_string();
+
+  vec![
+    (assign_name, assign_source),
+    ("apollon_grid_bitonic_step".to_string(), bitonic_source),
+    ("apollon_grid_clear".to_string(), clear_source),
+    ("apollon_grid_bounds".to_string(), bounds_source),
+  ]
+}
+
+/// Returns every distinct C identifier in expression in order of first appearance, skipping those directly
+/// following a '.' (ie vector components such as `.x`) and numeric literal suffixes.
+pub fn cl_identifiers(expression: &str) -> Vec<String> {
+  let mut identifiers: Vec<String> = vec![];
+  let chars: Vec<char> = expression.chars().collect();
+  let mut i = 0;
+  while i < chars.len() {
+    if chars[i].is_ascii_alphabetic() || chars[i] == '_' {
+      let start = i;
+      while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+        i += 1;
+      }
+      let identifier: String = chars[start..i].iter().collect();
+      let follows_dot = start > 0 && chars[start - 1] == '.';
+      if !follows_dot && !identifiers.contains(&identifier) {
+        identifiers.push(identifier);
+      }
+    }
+    else if chars[i].is_ascii_digit() {
+      // Skip numeric literals entirely, including suffixes & exponents like 2.5e3f
+      while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.' || chars[i] == '_') {
+        i += 1;
+      }
+    }
+    else {
+      i += 1;
+    }
+  }
+  identifiers
+}
+
+/// Returns (kernel name, kernel source) for a kernel which atomically counts the entities for which expression is true.
+/// params holds (name, OpenCL C type, is_buffer) for every column or constant the expression references.
+pub fn stop_condition_kernel_source(condition_i: usize, expression: &str, params: &[(String, &str, bool)]) -> (String, String) {
+  let kernel_name = format!("apollon_stop_condition_{}", condition_i);
+  let mut param_decls = String::new();
+  for (name, cl_type, is_buffer) in params.iter() {
+    if *is_buffer {
+      param_decls.push_str(&format!("    global const {}* {},\n", cl_type, name));
+    }
+    else {
+      param_decls.push_str(&format!("    const {} {},\n", cl_type, name));
+    }
+  }
+  let param_types: Vec<&str> = params.iter().map(|(_name, cl_type, _is_buffer)| *cl_type).collect();
+  let source = format!(r#"{pragma}
+kernel void {kernel_name} (
+{param_decls}    const ulong n,
+    global uint* matched_count
+)
+{{
+    const size_t i = get_global_id(0);
+    if (i < n && ({expression})) {{
+        atomic_inc(matched_count);
+    }}
+}}
+"#, pragma=cl_source_fp64_pragma(&param_types), kernel_name=kernel_name, param_decls=param_decls, expression=expression);
+  (kernel_name, source)
+}
+
+/// Returns (kernel name, kernel source) of the kernel of a structs::CL_WorldBoundary. arg_types holds the OpenCL C types of the
+/// x & y columns, followed by those of the velocity columns if any & of the alive column of a "remove" boundary;
+/// coordinates are computed in compute_type, which is also the type of the x_min, y_min, x_max & y_max arguments.
+pub fn world_boundary_kernel_source(boundary: structs::WorldBoundary, arg_types: &[&str], compute_type: &str) -> (String, String) {
+  let kernel_name = format!("apollon_world_{}_{}", boundary.name(), arg_types.join("_"));
+  let has_velocity = arg_types.len() - if boundary == structs::WorldBoundary::Remove { 1 } else { 0 } == 4;
+  let mut param_decls = format!("    global {}* x,\n    global {}* y,\n", arg_types[0], arg_types[1]);
+  if has_velocity {
+    param_decls.push_str(&format!("    global {}* vx,\n    global {}* vy,\n", arg_types[2], arg_types[3]));
+  }
+  if boundary == structs::WorldBoundary::Remove {
+    param_decls.push_str(&format!("    global {}* alive,\n", arg_types[arg_types.len() - 1]));
+  }
+
+  let axis_body = |p: &str, v: &str, v_t: &str, min: &str, max: &str| -> String {
+    match boundary {
+      structs::WorldBoundary::Clamp => format!("    {p} = clamp({p}, {min}, {max});\n", p=p, min=min, max=max),
+      structs::WorldBoundary::Wrap => format!("    {p} = {p} - ({max} - {min}) * floor(({p} - {min}) / ({max} - {min}));\n", p=p, min=min, max=max),
+      structs::WorldBoundary::Reflect => {
+        let (into_min, into_max) = if has_velocity {
+          (format!(" {v}[i] = ({v_t})fabs(({c_t}){v}[i]);", v=v, v_t=v_t, c_t=compute_type), format!(" {v}[i] = ({v_t})-fabs(({c_t}){v}[i]);", v=v, v_t=v_t, c_t=compute_type))
+        }
+        else {
+          (String::new(), String::new())
+        };
+        format!(
+          "    if ({p} < {min}) {{ {p} = 2 * {min} - {p};{into_min} }}\n    else if ({p} > {max}) {{ {p} = 2 * {max} - {p};{into_max} }}\n    {p} = clamp({p}, {min}, {max});\n",
+          p=p, min=min, max=max, into_min=into_min, into_max=into_max
+        )
+      }
+      structs::WorldBoundary::Remove => String::new(),
+    }
+  };
+  let body = if boundary == structs::WorldBoundary::Remove {
+    // Written so entities with NaN coordinates are removed as well
+    "    if (!(px >= x_min && px <= x_max && py >= y_min && py <= y_max)) {\n        alive[i] = 0;\n    }\n".to_string()
+  }
+  else {
+    let vx_t = if has_velocity { arg_types[2] } else { "" };
+    let vy_t = if has_velocity { arg_types[3] } else { "" };
+    format!("{}{}    x[i] = ({})px;\n    y[i] = ({})py;\n",
+      axis_body("px", "vx", vx_t, "x_min", "x_max"), axis_body("py", "vy", vy_t, "y_min", "y_max"), arg_types[0], arg_types[1])
+  };
+
+  let mut pragma_types: Vec<&str> = arg_types.to_vec();
+  pragma_types.push(compute_type);
+  let source = format!(r#"{pragma}
+kernel void {kernel_name} (
+{param_decls}    const ulong n,
+    const {c_t} x_min,
+    const {c_t} y_min,
+    const {c_t} x_max,
+    const {c_t} y_max
+)
+{{
+    const size_t i = get_global_id(0);
+    if (i >= n) {{
+        return;
+    }}
+    {c_t} px = ({c_t})x[i];
+    {c_t} py = ({c_t})y[i];
+{body}}}
+"#, pragma=cl_source_fp64_pragma(&pragma_types), c_t=compute_type, kernel_name=kernel_name, param_decls=param_decls, body=body);
+  (kernel_name, source)
+}
+
+/// Returns (kernel name, kernel source) of the two kernels of a reduction over the column named value; see structs::CL_Reduction.
+/// The first reduces the entities for which filter is true to per-work-group partials, the second combines up to work_group_size
+/// partials into element 0 of each partials buffer. params holds (name, OpenCL C type, is_buffer) for the column & filter references.
+pub fn reduction_kernel_sources(reduction_i: usize, value: &str, filter: &str, params: &[(String, &str, bool)], acc_type: &str, work_group_size: usize) -> ((String, String), (String, String)) {
+  let kernel_name = format!("apollon_reduction_{}", reduction_i);
+  let combine_kernel_name = format!("apollon_reduction_{}_combine", reduction_i);
+  let mut param_decls = String::new();
+  for (name, cl_type, is_buffer) in params.iter() {
+    if *is_buffer {
+      param_decls.push_str(&format!("    global const {}* {},\n", cl_type, name));
+    }
+    else {
+      param_decls.push_str(&format!("    const {} {},\n", cl_type, name));
+    }
+  }
+  let mut param_types: Vec<&str> = params.iter().map(|(_name, cl_type, _is_buffer)| *cl_type).collect();
+  param_types.push(acc_type);
+
+  // Both kernels finish with the same tree reduction over local memory
+  let local_reduce = format!(r#"    local {acc_t} local_sum[{wg}];
+    local {acc_t} local_min[{wg}];
+    local {acc_t} local_max[{wg}];
+    local uint local_count[{wg}];
+    const size_t lid = get_local_id(0);
+    local_sum[lid] = sum;
+    local_min[lid] = lo;
+    local_max[lid] = hi;
+    local_count[lid] = count;
+    barrier(CLK_LOCAL_MEM_FENCE);
+    for (size_t s = {wg} / 2; s > 0; s /= 2) {{
+        if (lid < s) {{
+            local_sum[lid] += local_sum[lid + s];
+            local_min[lid] = fmin(local_min[lid], local_min[lid + s]);
+            local_max[lid] = fmax(local_max[lid], local_max[lid + s]);
+            local_count[lid] += local_count[lid + s];
+        }}
+        barrier(CLK_LOCAL_MEM_FENCE);
+    }}"#, acc_t=acc_type, wg=work_group_size);
+
+  let source = format!(r#"{pragma}
+kernel void {kernel_name} (
+{param_decls}    const ulong n,
+    global {acc_t}* partial_sum,
+    global {acc_t}* partial_min,
+    global {acc_t}* partial_max,
+    global uint* partial_count
+)
+{{
+    {acc_t} sum = 0;
+    {acc_t} lo = INFINITY;
+    {acc_t} hi = -INFINITY;
+    uint count = 0;
+    for (size_t i = get_global_id(0); i < n; i += get_global_size(0)) {{
+        if ({filter}) {{
+            const {acc_t} v = ({acc_t}){value}[i];
+            sum += v;
+            lo = fmin(lo, v);
+            hi = fmax(hi, v);
+            count += 1;
+        }}
+    }}
+{local_reduce}
+    if (lid == 0) {{
+        const size_t group = get_group_id(0);
+        partial_sum[group] = local_sum[0];
+        partial_min[group] = local_min[0];
+        partial_max[group] = local_max[0];
+        partial_count[group] = local_count[0];
+    }}
+}}
+"#, pragma=cl_source_fp64_pragma(&param_types), kernel_name=kernel_name, param_decls=param_decls, acc_t=acc_type, filter=filter, value=value, local_reduce=local_reduce);
+
+  let combine_source = format!(r#"{pragma}
+kernel void {kernel_name} (
+    global {acc_t}* partial_sum,
+    global {acc_t}* partial_min,
+    global {acc_t}* partial_max,
+    global uint* partial_count,
+    const uint num_groups
+)
+{{
+    {acc_t} sum = 0;
+    {acc_t} lo = INFINITY;
+    {acc_t} hi = -INFINITY;
+    uint count = 0;
+    for (uint g = get_local_id(0); g < num_groups; g += {wg}) {{
+        sum += partial_sum[g];
+        lo = fmin(lo, partial_min[g]);
+        hi = fmax(hi, partial_max[g]);
+        count += partial_count[g];
+    }}
+{local_reduce}
+    if (lid == 0) {{
+        partial_sum[0] = local_sum[0];
+        partial_min[0] = local_min[0];
+        partial_max[0] = local_max[0];
+        partial_count[0] = local_count[0];
+    }}
+}}
+"#, pragma=cl_source_fp64_pragma(&[acc_type]), kernel_name=combine_kernel_name, acc_t=acc_type, wg=work_group_size, local_reduce=local_reduce);
+
+  ((kernel_name, source), (combine_kernel_name, combine_source))
+}
+
+/// Enqueues a conversion kernel built from conversion_kernel_source over the first work_size elements of src and dst.
+pub fn enqueue_conversion_kernel(
+  conversion_kernel: &opencl3::kernel::Kernel,
+  src: &structs::CL_TaggedArgument,
+  dst: &structs::CL_TaggedArgument,
+  work_size: usize,
+  queue: &opencl3::command_queue::CommandQueue,
+) -> Result<opencl3::event::Event, Box<dyn std::error::Error>>
+{
+  let mut exec_kernel = opencl3::kernel::ExecuteKernel::new(conversion_kernel);
+  unsafe {
+    src.set_as_arg(&mut exec_kernel);
+    dst.set_as_arg(&mut exec_kernel);
+  }
+  exec_kernel.set_global_work_size(work_size);
+  Ok(unsafe { exec_kernel.enqueue_nd_range(queue).map_err(structs::eloc!())? })
+}
+
+/// Enqueues a pack or unpack kernel of pack_kernel_sources over work_size entities
+pub fn enqueue_packing_kernel(
+  packing_kernel: &opencl3::kernel::Kernel,
+  packed: &structs::CL_TaggedArgument,
+  components: &[&structs::CL_TaggedArgument],
+  work_size: usize,
+  queue: &opencl3::command_queue::CommandQueue,
+) -> Result<opencl3::event::Event, Box<dyn std::error::Error>>
+{
+  let mut exec_kernel = opencl3::kernel::ExecuteKernel::new(packing_kernel);
+  unsafe {
+    packed.set_as_arg(&mut exec_kernel);
+    for component in components.iter() {
+      component.set_as_arg(&mut exec_kernel);
+    }
+  }
+  exec_kernel.set_global_work_size(work_size);
+  Ok(unsafe { exec_kernel.enqueue_nd_range(queue).map_err(structs::eloc!())? })
+}
+
+
 pub fn duration_to_display_str(d: &std::time::Duration) -> String {
   let total_millis = d.as_millis();
   let ms = total_millis % 1000;
@@ -1293,3 +2970,173 @@ pub fn trim_completed_events(
 }
 
 
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn work_size_lookup(name: &str) -> Option<u64> {
+    match name {
+      "entities" => Some(100),
+      "edges" => Some(7),
+      _ => None,
+    }
+  }
+
+  #[test]
+  fn eval_work_size_expr_precedence_and_parentheses() {
+    assert_eq!(eval_work_size_expr("entities*entities", &work_size_lookup).unwrap(), 10000);
+    assert_eq!(eval_work_size_expr("(entities + 63) / 64 * 64", &work_size_lookup).unwrap(), 128);
+    assert_eq!(eval_work_size_expr("2 + 3 * 4", &work_size_lookup).unwrap(), 14);
+    assert_eq!(eval_work_size_expr("(2 + 3) * 4", &work_size_lookup).unwrap(), 20);
+    assert_eq!(eval_work_size_expr("entities % edges", &work_size_lookup).unwrap(), 2);
+    assert_eq!(eval_work_size_expr(" 512 ", &work_size_lookup).unwrap(), 512);
+  }
+
+  #[test]
+  fn eval_work_size_expr_rejects_invalid_expressions() {
+    assert!(eval_work_size_expr("unknown * 2", &work_size_lookup).is_err());
+    assert!(eval_work_size_expr("entities / 0", &work_size_lookup).is_err());
+    assert!(eval_work_size_expr("(entities", &work_size_lookup).is_err());
+    assert!(eval_work_size_expr("entities entities", &work_size_lookup).is_err());
+    assert!(eval_work_size_expr("", &work_size_lookup).is_err());
+  }
+
+  #[test]
+  fn template_run_path_substitutes_placeholders() {
+    let combination = vec![("red".to_string(), structs::Value::Double(0.12))];
+    let path = template_run_path(std::path::Path::new("/tmp/out-red{red}.csv"), 10, 5, &combination);
+    assert_eq!(path, std::path::PathBuf::from("/tmp/out-red0.12.csv"));
+    let path = template_run_path(std::path::Path::new("/tmp/out-{run}.csv"), 10, 5, &combination);
+    assert_eq!(path, std::path::PathBuf::from("/tmp/out-5.csv"));
+  }
+
+  #[test]
+  fn template_run_path_appends_run_unless_every_swept_constant_is_named() {
+    let combination = vec![("blue".to_string(), structs::Value::Double(0.1)), ("red".to_string(), structs::Value::Double(0.2))];
+    let path = template_run_path(std::path::Path::new("/tmp/out.csv"), 4, 3, &combination);
+    assert_eq!(path, std::path::PathBuf::from("/tmp/out-run3.csv"));
+    let path = template_run_path(std::path::Path::new("/tmp/out-red{red}.csv"), 4, 3, &combination);
+    assert_eq!(path, std::path::PathBuf::from("/tmp/out-red0.2-run3.csv"));
+    let path = template_run_path(std::path::Path::new("/tmp/out-{blue}-{red}.csv"), 4, 3, &combination);
+    assert_eq!(path, std::path::PathBuf::from("/tmp/out-0.1-0.2.csv"));
+  }
+
+  #[test]
+  fn template_run_path_single_run_and_devnull_are_unchanged() {
+    let path = template_run_path(std::path::Path::new("/tmp/out.csv"), 1, 0, &[]);
+    assert_eq!(path, std::path::PathBuf::from("/tmp/out.csv"));
+    let combination = vec![("red".to_string(), structs::Value::Double(0.2))];
+    let path = template_run_path(std::path::Path::new("/dev/null"), 4, 3, &combination);
+    assert_eq!(path, std::path::PathBuf::from("/dev/null"));
+  }
+
+  #[test]
+  fn stats_writer_resumes_at_checkpointed_length() {
+    let path = std::env::temp_dir().join(format!("apollon-stats-resume-{}.csv", std::process::id()));
+    let stat_names = vec!["mean_x".to_string()];
+    let mut writer = StatsWriter::new(&path, stat_names.clone(), None).unwrap().unwrap();
+    writer.write_step(0, 0.0, &[("mean_x".to_string(), 1.0)]).unwrap();
+    let offset = writer.flushed_len().unwrap();
+    writer.write_step(1, 1.0, &[("mean_x".to_string(), 2.0)]).unwrap();
+    writer.finish().unwrap();
+    drop(writer);
+
+    // The step after the checkpoint is discarded & written again without repeating the header
+    let mut writer = StatsWriter::new(&path, stat_names, Some(offset)).unwrap().unwrap();
+    writer.write_step(1, 1.0, &[("mean_x".to_string(), 3.0)]).unwrap();
+    writer.finish().unwrap();
+    drop(writer);
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(contents, "step,sim_time,mean_x\n0,0.0,1.0\n1,1.0,3.0\n");
+  }
+
+  #[test]
+  fn template_resume_segment_path_names_the_resumed_step() {
+    let path = template_resume_segment_path(std::path::Path::new("/tmp/anim.mp4"), 5000);
+    assert_eq!(path, std::path::PathBuf::from("/tmp/anim-from-step5000.mp4"));
+    let path = template_resume_segment_path(std::path::Path::new("/dev/null"), 5000);
+    assert_eq!(path, std::path::PathBuf::from("/dev/null"));
+  }
+
+  fn column_schema(v_type: structs::ValueType, default: Option<structs::Value>, nullable: bool) -> structs::ColumnSchema {
+    structs::ColumnSchema { v_type, default, nullable }
+  }
+
+  fn ld_record(values: &[(&str, structs::Value)]) -> HashMap<String, structs::Value> {
+    values.iter().map(|(k, v)| (k.to_string(), v.clone())).collect()
+  }
+
+  #[test]
+  fn apply_schema_coerces_mixed_column_to_one_type() {
+    let mut ld: ListedData = vec![
+      ld_record(&[("x", structs::Value::Integer(1)), ("n", structs::Value::Double(3.0))]),
+      ld_record(&[("x", structs::Value::Double(2.5)), ("n", structs::Value::Integer(4))]),
+    ];
+    let schema = HashMap::from([
+      ("x".to_string(), column_schema(structs::ValueType::Float64, None, false)),
+      ("n".to_string(), column_schema(structs::ValueType::Int32, None, false)),
+    ]);
+    apply_schema(&mut ld, &schema).unwrap();
+    assert!(ld.iter().all(|r| matches!(r["x"], structs::Value::Double(_))));
+    assert!(ld.iter().all(|r| matches!(r["n"], structs::Value::Integer(_))));
+    assert_eq!(ld[0]["x"].to_string(), "1");
+    assert_eq!(ld[0]["n"].to_string(), "3");
+
+    let mut fractional: ListedData = vec![ld_record(&[("n", structs::Value::Double(2.5))])];
+    assert!(apply_schema(&mut fractional, &schema).is_err());
+  }
+
+  #[test]
+  fn apply_schema_fills_missing_cells_from_default() {
+    let mut ld: ListedData = vec![
+      ld_record(&[("x", structs::Value::Double(1.5))]),
+      ld_record(&[("x", structs::Value::String("".to_string()))]), // An empty CSV cell
+    ];
+    let schema = HashMap::from([
+      ("x".to_string(), column_schema(structs::ValueType::Float32, Some(structs::Value::Integer(7)), false)),
+      ("color".to_string(), column_schema(structs::ValueType::String, Some(structs::Value::String("black".to_string())), false)),
+    ]);
+    apply_schema(&mut ld, &schema).unwrap();
+    assert_eq!(ld[0]["x"].to_string(), "1.5");
+    assert!(matches!(ld[1]["x"], structs::Value::Double(d) if d == 7.0));
+    assert!(ld.iter().all(|r| r["color"].to_string() == "black"));
+  }
+
+  #[test]
+  fn apply_schema_rejects_missing_non_nullable_cell() {
+    let mut ld: ListedData = vec![ld_record(&[("x", structs::Value::Double(1.0))]), ld_record(&[])];
+    let schema = HashMap::from([("x".to_string(), column_schema(structs::ValueType::Float64, None, false))]);
+    assert!(apply_schema(&mut ld, &schema).is_err());
+
+    let nullable_schema = HashMap::from([("x".to_string(), column_schema(structs::ValueType::Float64, None, true))]);
+    apply_schema(&mut ld, &nullable_schema).unwrap();
+    assert!(!ld[1].contains_key("x"));
+  }
+
+  #[tokio::test]
+  async fn write_ld_file_round_trip_keeps_double_and_integer_types() {
+    let args = <structs::Args as clap::Parser>::parse_from(["apollon", "simcontrol.toml"]);
+    let path = std::env::temp_dir().join(format!("apollon-schema-round-trip-{}.csv", std::process::id()));
+    // Values as read back from kernel buffers: a whole float and an integer stored in a float buffer
+    let ld: ListedData = vec![
+      ld_record(&[("x", structs::Value::Double(2.0)), ("n", structs::Value::Double(3.0))]),
+      ld_record(&[("x", structs::Value::Integer(4)), ("n", structs::Value::Integer(5))]),
+    ];
+    let schema = HashMap::from([
+      ("x".to_string(), column_schema(structs::ValueType::Float64, None, false)),
+      ("n".to_string(), column_schema(structs::ValueType::Int64, None, false)),
+    ]);
+    write_ld_file(&args, &ld, &schema, &path).await.unwrap();
+    let read_back = read_ld_file(&path).await;
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(read_back.len(), 2);
+    assert!(read_back.iter().all(|r| matches!(r["x"], structs::Value::Double(_))));
+    assert!(read_back.iter().all(|r| matches!(r["n"], structs::Value::Integer(_))));
+    assert_eq!(read_back[1]["x"].to_string(), "4");
+    assert_eq!(read_back[0]["n"].to_string(), "3");
+  }
+}